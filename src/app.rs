@@ -1,36 +1,211 @@
 use crate::audio::AudioManager;
-use crate::playlist::PlaylistManager;
+#[cfg(all(feature = "mpris", target_os = "linux"))]
+use crate::mpris::{MprisServer, MprisState};
+use crate::player::Player;
+use crate::remote::{RemoteCommand, RemoteRequest, RemoteServer};
+use crate::session::{Session, WindowGeometry};
+use crate::settings::Settings;
 use crate::ui::MusicPlayerUI;
 use egui::Context;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How often the session is saved while the app runs, so a crash loses little.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct MusicPlayerApp {
     ui: MusicPlayerUI,
-    audio_manager: Arc<Mutex<AudioManager>>,
-    playlist_manager: Arc<Mutex<PlaylistManager>>,
+    player: Player,
+    remote: Option<RemoteServer>,
+    #[cfg(all(feature = "mpris", target_os = "linux"))]
+    mpris: Option<MprisServer>,
+    /// Latest window geometry, saved on exit.
+    window: Option<WindowGeometry>,
+    /// Whether the restored geometry has been checked against the monitor yet.
+    window_checked: bool,
+    /// Files and folders from the command line, opened on the first frame.
+    open_on_launch: Vec<PathBuf>,
+    last_autosave: Instant,
+}
+
+impl Default for MusicPlayerApp {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MusicPlayerApp {
     pub fn new() -> Self {
-        let audio_manager = Arc::new(Mutex::new(AudioManager::new()));
-        let playlist_manager = Arc::new(Mutex::new(PlaylistManager::new()));
-        
+        let settings = Settings::load();
+        let mut player = Player::new(AudioManager::new());
+        player.set_volume(settings.default_volume);
+        player.set_auto_advance_delay(Duration::from_secs_f32(settings.auto_advance_delay_secs));
+        player.set_delay_between_albums_only(settings.delay_between_albums_only);
+        player.set_crossfade(Duration::from_secs_f32(settings.crossfade_secs));
+        player.set_fade(Duration::from_secs_f32(settings.fade_secs));
+        player.set_normalize(settings.normalize_volume);
+        player.set_album_gain(settings.album_gain);
+        player.set_resume_after(settings.resume_after());
+        player.set_library_root(settings.library_root());
+        player.set_balance(settings.balance);
+        player.set_mono(settings.mono);
+        player.set_speed(settings.playback_speed);
+        player.set_bass(settings.bass_db);
+        player.set_treble(settings.treble_db);
+        player.set_eq_gains(&settings.eq_gains);
+        player.set_eq_enabled(settings.eq_enabled);
+        player.set_spectrum_options(settings.spectrum_bars, settings.spectrum_smoothing);
+        player.set_spectrum_enabled(settings.spectrum_enabled);
+        player.set_meter_enabled(settings.meter_enabled);
+        if let Some(device) = &settings.output_device {
+            // A device that isn't plugged in right now just leaves the default in use
+            if let Err(e) = player.set_output_device(Some(device)) {
+                warn!("Could not use output device {}: {}", device, e);
+            }
+        }
+        if let Some(session) = Session::load() {
+            player.restore_session(session, settings.resume_playing_on_launch);
+        }
+
         Self {
-            ui: MusicPlayerUI::new(),
-            audio_manager,
-            playlist_manager,
+            ui: MusicPlayerUI::new(settings),
+            player,
+            remote: None,
+            #[cfg(all(feature = "mpris", target_os = "linux"))]
+            mpris: None,
+            window: None,
+            window_checked: true,
+            open_on_launch: Vec::new(),
+            last_autosave: Instant::now(),
+        }
+    }
+
+    /// Tells the app which geometry the window was opened with, so it can be moved back
+    /// on-screen if the monitor it was saved on is gone.
+    pub fn with_restored_window(mut self, geometry: Option<WindowGeometry>) -> Self {
+        self.window_checked = geometry.is_none();
+        self.window = geometry;
+        self
+    }
+
+    fn track_window_geometry(&mut self, ctx: &Context) {
+        if !self.window_checked {
+            let Some(monitor) = ctx.input(|i| i.viewport().monitor_size) else { return };
+            self.window_checked = true;
+            if let Some(saved) = self.window.filter(|w| !w.maximized) {
+                let (position, size) = saved.fit_to_monitor([monitor.x, monitor.y]);
+                if (position, size) != (saved.position.unwrap_or_default(), saved.size) {
+                    info!("Moving the window onto the current monitor");
+                    ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(position.into()));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size.into()));
+                }
+            }
+            return;
+        }
+        self.window = WindowGeometry::capture(ctx, self.window);
+    }
+
+    /// Opens `paths` once the window is up and starts playing the first song among them.
+    pub fn with_open_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.open_on_launch = paths;
+        self
+    }
+
+    /// Takes commands from `remote` (sent by `--play`, `--next`, ...) on every frame.
+    pub fn with_remote(mut self, remote: RemoteServer) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Takes commands from the desktop's media keys and controls, and keeps them up to
+    /// date with what's playing.
+    #[cfg(all(feature = "mpris", target_os = "linux"))]
+    pub fn with_mpris(mut self, mpris: MprisServer) -> Self {
+        self.mpris = Some(mpris);
+        self
+    }
+
+    #[cfg(all(feature = "mpris", target_os = "linux"))]
+    fn publish_mpris_state(&self) {
+        let Some(mpris) = &self.mpris else { return };
+        let song = self.player.current_song();
+        mpris.update(MprisState {
+            playing: self.player.is_playing(),
+            paused: self.player.is_paused(),
+            track: self.player.current_index().filter(|_| song.is_some()),
+            title: song.map(|s| s.title.clone()).unwrap_or_default(),
+            artist: song.map(|s| s.artist.clone()).unwrap_or_default(),
+            album: song.and_then(|s| s.album.clone()),
+            length: self.player.total_duration(),
+            position: self.player.position(),
+            volume: self.player.volume(),
+        });
+    }
+
+    fn handle_remote_commands(&mut self, ctx: &Context) {
+        let requests = self.remote.as_ref().map(RemoteServer::poll).unwrap_or_default();
+        #[cfg(all(feature = "mpris", target_os = "linux"))]
+        let requests: Vec<RemoteRequest> =
+            requests.into_iter().chain(self.mpris.iter().flat_map(MprisServer::poll).map(RemoteRequest::Command)).collect();
+        for request in requests {
+            let command = match request {
+                RemoteRequest::Command(command) => command,
+                // "Open with" from a file manager lands here when the player is running
+                RemoteRequest::Open(paths) => {
+                    self.ui.open_paths(paths, true, ctx, &mut self.player);
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                    continue;
+                }
+            };
+            match command {
+                RemoteCommand::Play if self.player.is_paused() => self.player.resume(),
+                RemoteCommand::Play if !self.player.is_playing() => self.player.play(),
+                RemoteCommand::Play | RemoteCommand::Ping => {}
+                RemoteCommand::Pause => self.player.pause(),
+                RemoteCommand::TogglePause => self.player.toggle_play_pause(),
+                RemoteCommand::Stop => self.player.stop(),
+                RemoteCommand::Next => self.player.next(),
+                RemoteCommand::Previous => self.player.previous(),
+                RemoteCommand::CycleRepeat => self.player.set_repeat_mode(self.player.repeat_mode().cycle()),
+                RemoteCommand::ToggleShuffle => self.player.toggle_shuffle(),
+                RemoteCommand::ToggleMute => self.player.toggle_mute(),
+            }
         }
     }
 }
 
 impl eframe::App for MusicPlayerApp {
-    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &Context, frame: &mut eframe::Frame) {
+        self.track_window_geometry(ctx);
+        self.handle_remote_commands(ctx);
+        self.ui.set_system_dark_mode(frame.info().system_theme.map(|theme| theme == eframe::Theme::Dark));
+        if !self.open_on_launch.is_empty() {
+            self.ui.open_paths(std::mem::take(&mut self.open_on_launch), true, ctx, &mut self.player);
+        }
         // Update the UI
-        self.ui.update(
-            ctx,
-            self.audio_manager.clone(),
-            self.playlist_manager.clone(),
-        );
+        self.ui.update(ctx, &mut self.player);
+        #[cfg(all(feature = "mpris", target_os = "linux"))]
+        self.publish_mpris_state();
+        if self.last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+            self.last_autosave = Instant::now();
+            if let Err(e) = self.player.session_snapshot().save() {
+                warn!("Failed to autosave session: {}", e);
+            }
+        }
+    }
+
+    // `save` is only called with eframe's "persistence" feature, so the session is
+    // written from `on_exit` instead.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.ui.on_exit();
+        if let Err(e) = self.player.session_snapshot().save() {
+            tracing::warn!("Failed to save session: {}", e);
+        }
+        if let Some(window) = &self.window {
+            if let Err(e) = window.save() {
+                tracing::warn!("Failed to save window geometry: {}", e);
+            }
+        }
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file
@@ -0,0 +1,112 @@
+use crossbeam_channel::{unbounded, Receiver};
+use egui::{ColorImage, Context, TextureHandle, TextureOptions};
+use std::fs::File;
+use std::path::Path;
+use std::thread;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardVisualKey, Visual};
+use symphonia::core::probe::Hint;
+use symphonia::default::get_probe;
+use tracing::warn;
+
+use crate::utils::has_extension;
+
+/// File stems taken as album art when they sit in the same folder as the song.
+const FOLDER_ART_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+
+/// Returns the encoded bytes of the picture embedded in the file, preferring the front
+/// cover when there are several. `None` if there is no embedded art.
+pub fn read_cover_art(file_path: &str) -> Option<Vec<u8>> {
+    let file = File::open(file_path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = Path::new(file_path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut probed = get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    // As with tags, pictures can sit in a block read while probing or in the container
+    let mut visuals: Vec<Visual> = Vec::new();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        visuals.extend(revision.visuals().iter().cloned());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        visuals.extend(revision.visuals().iter().cloned());
+    }
+    let cover = visuals
+        .iter()
+        .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+        .or_else(|| visuals.first())?;
+    Some(cover.data.to_vec())
+}
+
+/// Returns the bytes of a `cover.png` (or `folder.png`, `front.png`, `album.png`) in the
+/// same folder as `file_path`, for albums whose files have no art of their own. Only PNG
+/// is picked up, since that's the one format the art is decoded from.
+pub fn read_folder_art(file_path: &str) -> Option<Vec<u8>> {
+    let folder = Path::new(file_path).parent()?;
+    let art_path = std::fs::read_dir(folder).ok()?.filter_map(|e| e.ok()).map(|e| e.path()).find(|path| {
+        has_extension(path, &["png"])
+            && path.file_stem().is_some_and(|stem| FOLDER_ART_NAMES.iter().any(|name| stem.eq_ignore_ascii_case(name)))
+    })?;
+    std::fs::read(art_path).ok()
+}
+
+/// The song's embedded art, or failing that the art in its folder.
+pub fn find_cover_art(file_path: &str) -> Option<Vec<u8>> {
+    read_cover_art(file_path).or_else(|| read_folder_art(file_path))
+}
+
+/// Reads and decodes the art for `file_path` into an image egui can upload.
+fn load_cover_image(file_path: &str) -> Option<ColorImage> {
+    let bytes = find_cover_art(file_path)?;
+    match image::load_from_memory(&bytes) {
+        Ok(image) => {
+            let rgba = image.thumbnail(256, 256).to_rgba8();
+            let size = [rgba.width() as usize, rgba.height() as usize];
+            Some(ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()))
+        }
+        Err(e) => {
+            warn!("Could not decode album art in {}: {}", file_path, e);
+            None
+        }
+    }
+}
+
+/// Album art for the song currently shown. Art is decoded on a background thread and
+/// the texture is released as soon as a different song is requested.
+#[derive(Default)]
+pub struct AlbumArt {
+    file_path: Option<String>,
+    texture: Option<TextureHandle>,
+    receiver: Option<Receiver<Option<ColorImage>>>,
+}
+
+impl AlbumArt {
+    /// Returns the texture for `file_path` once it has loaded, or `None` while loading
+    /// and when the file has no (readable) art.
+    pub fn get(&mut self, ctx: &Context, file_path: &str) -> Option<&TextureHandle> {
+        if self.file_path.as_deref() != Some(file_path) {
+            // Dropping the handle frees the previous song's texture
+            self.texture = None;
+            self.file_path = Some(file_path.to_string());
+            let (sender, receiver) = unbounded();
+            self.receiver = Some(receiver);
+            let path = file_path.to_string();
+            let repaint_ctx = ctx.clone();
+            thread::spawn(move || {
+                let _ = sender.send(load_cover_image(&path));
+                repaint_ctx.request_repaint();
+            });
+        }
+
+        if let Some(image) = self.receiver.as_ref().and_then(|r| r.try_recv().ok()) {
+            self.receiver = None;
+            self.texture = image.map(|image| ctx.load_texture(format!("album_art:{}", file_path), image, TextureOptions::LINEAR));
+        }
+        self.texture.as_ref()
+    }
+}
\ No newline at end of file
@@ -1,70 +1,753 @@
-use anyhow::Result;
-use rodio::{Decoder, OutputStream, Sink};
+use crate::effects::{ChannelControl, ChannelMixed};
+use crate::equalizer::{EqControl, Equalized};
+use crate::meter::{LevelMeter, Metered};
+use crate::render::{render_wav, RenderEffects, RenderTrack};
+use crate::spectrum::{SpectrumTap, Tapped};
+use crate::stream::{HttpStream, StreamStatus};
+use crossbeam_channel::{bounded, Receiver};
+use rodio::cpal::traits::HostTrait;
+use rodio::{Decoder, DeviceTrait, OutputStream, Sink, Source};
 use std::fs::File;
 use std::io::BufReader;
-use std::time::Duration;
-use tracing::info;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
 use symphonia::core::codecs::CODEC_TYPE_NULL;
 use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
-use symphonia::default::get_probe;
+use symphonia::default::{get_codecs, get_probe};
+use thiserror::Error;
+
+/// Shortest time a track must have been loaded before it can count as finished.
+const MIN_PLAY_TIME: Duration = Duration::from_millis(500);
+
+/// How long an empty sink whose source never reported running out is waited on.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often `check_output_device` looks for the chosen device. Enumerating devices
+/// can take a while on some backends, so this isn't done every frame.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Playback speeds offered in the UI. 1.0 is normal speed.
+pub const PLAYBACK_SPEEDS: [f32; 7] = [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0];
+
+#[derive(Debug, Error)]
+pub enum PlaybackError {
+    #[error("file not found: {0}")]
+    FileNotFound(String),
+    #[error("unsupported or corrupt audio: {0}")]
+    UnsupportedFormat(String),
+    #[error("audio device error: {0}")]
+    DeviceError(String),
+    #[error("network error: {0}")]
+    Network(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Technical details of an audio file, read from its container headers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackInfo {
+    /// Short codec name, e.g. `mp3` or `flac`.
+    pub codec: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    /// Bit depth, for formats that have one (lossless and PCM).
+    pub bits_per_sample: Option<u32>,
+    /// Average bitrate over the whole file, in kbit/s.
+    pub bitrate_kbps: Option<u32>,
+    pub duration: Option<Duration>,
+}
+
+impl TrackInfo {
+    /// One line such as `FLAC · 44.1 kHz · 16-bit · Stereo · 912 kbps`, leaving out
+    /// whatever isn't known.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(codec) = &self.codec {
+            // The PCM variants (pcm_s16le and so on) repeat what the bit depth says
+            parts.push(if codec.starts_with("pcm") { "PCM".to_string() } else { codec.to_uppercase() });
+        }
+        if let Some(rate) = self.sample_rate {
+            parts.push(format!("{} kHz", rate as f32 / 1000.0));
+        }
+        if let Some(bits) = self.bits_per_sample {
+            parts.push(format!("{}-bit", bits));
+        }
+        match self.channels {
+            Some(1) => parts.push("Mono".to_string()),
+            Some(2) => parts.push("Stereo".to_string()),
+            Some(n) => parts.push(format!("{} channels", n)),
+            None => {}
+        }
+        if let Some(kbps) = self.bitrate_kbps {
+            parts.push(format!("{} kbps", kbps));
+        }
+        parts.join(" · ")
+    }
+}
+
+/// How far the output has got through one track, shared with its `PositionTracked`.
+#[derive(Default)]
+struct Progress {
+    /// Samples pulled from the source so far.
+    samples: AtomicU64,
+    /// Set once the source has run out of samples.
+    exhausted: AtomicBool,
+}
+
+impl Progress {
+    /// Whether a track that was loaded `elapsed` ago has played to the end, given that
+    /// its sink has drained. The source must have run out and the track been loaded for
+    /// at least `MIN_PLAY_TIME`; one that never runs out is given up on after
+    /// `STALL_TIMEOUT`.
+    fn finished(&self, elapsed: Duration) -> bool {
+        (self.exhausted.load(Ordering::Relaxed) && elapsed >= MIN_PLAY_TIME) || elapsed >= STALL_TIMEOUT
+    }
+}
+
+/// Counts the samples pulled from the wrapped source. The output only pulls while the
+/// sink is playing, so the count gives the real playback position and freezes on pause.
+struct PositionTracked<S> {
+    inner: S,
+    progress: Arc<Progress>,
+}
+
+impl<S: Source<Item = i16>> Iterator for PositionTracked<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        match self.inner.next() {
+            Some(sample) => {
+                self.progress.samples.fetch_add(1, Ordering::Relaxed);
+                Some(sample)
+            }
+            None => {
+                self.progress.exhausted.store(true, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+impl<S: Source<Item = i16>> Source for PositionTracked<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
 
 pub struct AudioManager {
-    _stream: OutputStream,
-    _stream_handle: rodio::OutputStreamHandle,
+    /// `None` when no output device could be opened, e.g. on a machine without sound
+    /// hardware. Everything but playback still works, and `check_output_device` keeps
+    /// trying to open one.
+    output: Option<(OutputStream, rodio::OutputStreamHandle)>,
     sink: Option<Sink>,
     current_file: Option<String>,
     is_playing: bool,
     is_paused: bool,
     current_duration: Option<Duration>,
+    progress: Arc<Progress>,
+    /// Samples per second across all channels of the current source.
+    sample_rate: u64,
+    /// Track position the sample counter started from (non-zero after a seek).
+    position_offset: Duration,
+    /// When the current source was handed to the sink.
+    started_at: Instant,
+    volume: f32,
+    /// Applied to every new sink. The sample counter works in source samples, so the
+    /// reported position stays in track time at any speed.
+    speed: f32,
+    crossfade: Duration,
+    /// Length of the volume ramps on play, pause, resume and stop.
+    fade: Duration,
+    /// Volume ramp on the current sink for a pause or resume in progress.
+    ramp: Option<Ramp>,
+    /// The previous track while it fades out under the current one.
+    fading_out: Option<FadeOut>,
+    /// The next track, already appended to the sink behind the current one.
+    queued: Option<QueuedTrack>,
+    /// Shared with every source handed to a sink so EQ changes apply immediately.
+    eq: Arc<EqControl>,
+    /// Copies of the samples being played, for the spectrum analyzer.
+    spectrum: Arc<SpectrumTap>,
+    /// Output levels of what is being played, for the level meter.
+    meter: Arc<LevelMeter>,
+    /// Stereo balance and mono downmix, shared with every playing source like `eq`.
+    channels: Arc<ChannelControl>,
+    /// Name of the chosen output device; `None` follows the system default.
+    output_device: Option<String>,
+    last_device_check: Instant,
+    /// Download state when the current track is an internet stream.
+    stream: Option<StreamStatus>,
+    /// The one track being opened ahead of time by `prefetch`.
+    prefetched: Option<Prefetch>,
+}
+
+/// A file opened on a worker thread before it's needed.
+struct Prefetch {
+    file_path: String,
+    receiver: Receiver<Result<Decoder<BufReader<File>>, PlaybackError>>,
+}
+
+/// A source after the effects chain applied by `AudioManager::process`.
+type Processed<S> = Metered<Tapped<ChannelMixed<Equalized<S>>>>;
+
+/// A track queued for gapless playback, with its own progress that takes over once it
+/// starts playing.
+struct QueuedTrack {
+    file_path: String,
+    duration: Option<Duration>,
+    progress: Arc<Progress>,
+    sample_rate: u64,
+}
+
+/// A pause or resume that `update_fades` is ramping the volume for.
+enum Ramp {
+    /// Fading down; the sink is paused once silent.
+    Pausing(Instant),
+    /// Fading back up after a resume.
+    Resuming(Instant),
+}
+
+/// A sink being faded out by `update_fades`.
+struct FadeOut {
+    sink: Sink,
+    started: Instant,
+    length: Duration,
+}
+
+impl Default for AudioManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AudioManager {
     pub fn new() -> Self {
-        let (_stream, stream_handle) = OutputStream::try_default().expect("Failed to create audio stream");
-        
+        let output = match Self::open_output(None) {
+            Ok(output) => Some(output),
+            Err(e) => {
+                warn!("No audio output available, playback is disabled: {}", e);
+                None
+            }
+        };
+
         Self {
-            _stream,
-            _stream_handle: stream_handle,
+            output,
             sink: None,
             current_file: None,
             is_playing: false,
             is_paused: false,
             current_duration: None,
+            progress: Arc::default(),
+            sample_rate: 0,
+            position_offset: Duration::ZERO,
+            started_at: Instant::now(),
+            volume: 1.0,
+            speed: 1.0,
+            crossfade: Duration::ZERO,
+            fade: Duration::ZERO,
+            ramp: None,
+            fading_out: None,
+            queued: None,
+            eq: Arc::new(EqControl::default()),
+            spectrum: Arc::new(SpectrumTap::default()),
+            meter: Arc::new(LevelMeter::default()),
+            channels: Arc::new(ChannelControl::default()),
+            output_device: None,
+            last_device_check: Instant::now(),
+            stream: None,
+            prefetched: None,
         }
     }
 
-    pub fn play_file(&mut self, file_path: &str) -> Result<()> {
+    /// Names of the output devices currently available.
+    pub fn list_devices() -> Vec<String> {
+        match rodio::cpal::default_host().output_devices() {
+            Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+            Err(e) => {
+                warn!("Could not list output devices: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// False while no output device could be opened, so nothing can be played.
+    pub fn has_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn new_sink(&self) -> Result<Sink, PlaybackError> {
+        let (_, handle) = self.output.as_ref().ok_or_else(|| PlaybackError::DeviceError("no audio output device is available".to_string()))?;
+        let sink = Sink::try_new(handle).map_err(|e| PlaybackError::DeviceError(e.to_string()))?;
+        sink.set_speed(self.speed);
+        Ok(sink)
+    }
+
+    pub fn output_device(&self) -> Option<&str> {
+        self.output_device.as_deref()
+    }
+
+    /// Sends output to the device called `name`, or to the system default for `None`.
+    /// The output stream is rebuilt and the current track carries on from where it was.
+    pub fn set_output_device(&mut self, name: Option<&str>) -> Result<(), PlaybackError> {
+        let output = Self::open_output(name)?;
+        info!("Switched output to {}", name.unwrap_or("the default device"));
+        self.output_device = name.map(str::to_string);
+        self.replace_stream(output)
+    }
+
+    /// Re-opens the output stream on the current device, e.g. after it reported an
+    /// error, and moves the current track onto it. Nothing needs to be playing.
+    pub fn rebuild_stream(&mut self) -> Result<(), PlaybackError> {
+        let output = Self::open_output(self.output_device.as_deref())?;
+        info!("Rebuilt the output stream");
+        self.replace_stream(output)
+    }
+
+    fn open_output(name: Option<&str>) -> Result<(OutputStream, rodio::OutputStreamHandle), PlaybackError> {
+        match name {
+            Some(name) => {
+                let device = rodio::cpal::default_host()
+                    .output_devices()
+                    .map_err(|e| PlaybackError::DeviceError(e.to_string()))?
+                    .find(|d| d.name().is_ok_and(|n| n == name))
+                    .ok_or_else(|| PlaybackError::DeviceError(format!("no output device named {}", name)))?;
+                OutputStream::try_from_device(&device)
+            }
+            None => OutputStream::try_default(),
+        }
+        .map_err(|e| PlaybackError::DeviceError(e.to_string()))
+    }
+
+    /// Swaps in a new output stream and carries the current track over to it at the same
+    /// position, volume and play/pause state. The effects are shared with every source,
+    /// so EQ, tone and balance carry over by themselves.
+    fn replace_stream(&mut self, output: (OutputStream, rodio::OutputStreamHandle)) -> Result<(), PlaybackError> {
+        let position = self.get_current_position();
+        // The old stream is only dropped once the track has moved onto the new one
+        let _old_output = self.output.replace(output);
+        self.last_device_check = Instant::now();
+        if self.current_file.is_none() {
+            return Ok(());
+        }
+        if !self.is_seekable() {
+            // A stream can't be re-opened where it was, and its sink died with the old device
+            self.stop();
+            return Ok(());
+        }
+        self.seek(position)?;
+        // The old sink may have been part way through a fade; a fade still running
+        // carries on from `update_fades`
+        if let (Some(sink), None) = (&self.sink, &self.ramp) {
+            sink.set_volume(self.volume);
+        }
+        Ok(())
+    }
+
+    /// Falls back to the default device if the chosen one has gone away (e.g. unplugged
+    /// headphones), returning the lost device's name. Call regularly; the device list is
+    /// only checked every `DEVICE_CHECK_INTERVAL`.
+    pub fn check_output_device(&mut self) -> Option<String> {
+        if self.last_device_check.elapsed() < DEVICE_CHECK_INTERVAL {
+            return None;
+        }
+        self.last_device_check = Instant::now();
+        if self.output.is_none() {
+            // Pick up a device plugged in (or a driver fixed) since startup
+            if let Err(e) = self.rebuild_stream() {
+                debug!("Still no audio output: {}", e);
+            }
+            return None;
+        }
+        let name = self.output_device.clone()?;
+        if Self::list_devices().contains(&name) {
+            return None;
+        }
+        warn!("Output device {} disappeared, falling back to the default", name);
+        if let Err(e) = self.set_output_device(None) {
+            warn!("Could not open the default output device: {}", e);
+        }
+        Some(name)
+    }
+
+    /// How long the ending track and the next one overlap. Zero switches crossfading off.
+    pub fn set_crossfade(&mut self, duration: Duration) {
+        self.crossfade = duration;
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        self.crossfade
+    }
+
+    /// How long playback takes to fade in on play and resume, and out on pause and
+    /// stop, to avoid clicks. Zero makes them instant.
+    pub fn set_fade(&mut self, duration: Duration) {
+        self.fade = duration;
+    }
+
+    pub fn fade(&self) -> Duration {
+        self.fade
+    }
+
+    /// Starts playing `file_path`. Pass the song's `known_duration` if it has already
+    /// been read; with `None` the duration stays unknown until `set_duration` is called,
+    /// so probing it never delays the start of playback.
+    pub fn play_file(&mut self, file_path: &str, known_duration: Option<Duration>) -> Result<(), PlaybackError> {
         info!("Playing file: {}", file_path);
         
         // Stop current playback if any
         self.stop();
         
-        // Create a new sink
-        let sink = Sink::try_new(&self._stream_handle)?;
+        // Create a new sink, at the current volume from the very first sample
+        let sink = self.new_sink()?;
+        sink.set_volume(self.volume);
         
         // Open and decode the audio file
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-        let source = Decoder::new(reader)?;
+        let source = self.open_prefetched(file_path)?;
         
         // Play the audio
-        sink.append(source);
+        let source = source.fade_in(self.fade);
+        sink.append(self.track_position(source, Duration::ZERO));
         sink.play();
         
         self.sink = Some(sink);
         self.current_file = Some(file_path.to_string());
         self.is_playing = true;
         self.is_paused = false;
-        self.current_duration = Self::probe_duration(file_path);
+        self.current_duration = known_duration;
         
         Ok(())
     }
 
+    /// Connects to `url` and waits until enough has arrived to recognise the format.
+    /// This blocks on the network, so call it from a worker thread and pass the result
+    /// to `play_stream`.
+    pub fn open_url(url: &str) -> Result<(Decoder<HttpStream>, StreamStatus), PlaybackError> {
+        let (stream, info) = HttpStream::open(url)?;
+        let status = stream.status();
+        let unsupported = |e: rodio::decoder::DecoderError| PlaybackError::UnsupportedFormat(e.to_string());
+        // The server's content type picks the decoder; otherwise the data is sniffed
+        let decoder = match info.content_type.as_deref().unwrap_or("") {
+            "audio/mpeg" | "audio/mp3" => Decoder::new_mp3(stream),
+            "audio/ogg" | "audio/vorbis" | "application/ogg" => Decoder::new_vorbis(stream),
+            "audio/flac" | "audio/x-flac" => Decoder::new_flac(stream),
+            "audio/wav" | "audio/x-wav" | "audio/wave" => Decoder::new_wav(stream),
+            _ => Decoder::new(stream),
+        }
+        .map_err(unsupported)?;
+        info!("Opened stream {} ({})", url, info.content_type.as_deref().unwrap_or("unknown type"));
+        Ok((decoder, status))
+    }
+
+    /// Starts playing a stream opened with `open_url`.
+    pub fn play_stream(&mut self, url: &str, decoder: Decoder<HttpStream>, status: StreamStatus) -> Result<(), PlaybackError> {
+        self.stop();
+        let sink = self.new_sink()?;
+        sink.set_volume(self.volume);
+        let duration = decoder.total_duration();
+        let source = decoder.fade_in(self.fade);
+        sink.append(self.track_position(source, Duration::ZERO));
+        sink.play();
+
+        self.sink = Some(sink);
+        self.current_file = Some(url.to_string());
+        self.is_playing = true;
+        self.is_paused = false;
+        self.current_duration = duration;
+        self.stream = Some(status);
+        Ok(())
+    }
+
+    /// Streams `url` over HTTP. Blocks until the stream starts; see `open_url`.
+    pub fn play_url(&mut self, url: &str) -> Result<(), PlaybackError> {
+        let (decoder, status) = Self::open_url(url)?;
+        self.play_stream(url, decoder, status)
+    }
+
+    /// Whether a stream is playing but stalled waiting for the network.
+    pub fn is_buffering(&self) -> bool {
+        self.stream.as_ref().is_some_and(|s| s.is_buffering())
+    }
+
+    /// Streams are played as they download, so they can't be seeked.
+    pub fn is_seekable(&self) -> bool {
+        self.stream.is_none()
+    }
+
+    /// Starts `file_path` fading in over `length` while the current track fades out
+    /// over the same time, instead of cutting straight over like `play_file`. Callers
+    /// should start this no earlier than `length` before the current track ends.
+    pub fn crossfade_to(&mut self, file_path: &str, known_duration: Option<Duration>, length: Duration) -> Result<(), PlaybackError> {
+        info!("Crossfading into {} over {:.1}s", file_path, length.as_secs_f32());
+        let sink = self.new_sink()?;
+        let source = self.open_prefetched(file_path)?;
+        sink.set_volume(self.volume);
+
+        // Only one track fades at a time; anything still fading is cut off
+        if let Some(fade) = self.fading_out.take() {
+            fade.sink.stop();
+        }
+        self.queued = None;
+        self.ramp = None;
+        self.stream = None;
+        if let Some(old_sink) = self.sink.take() {
+            self.fading_out = Some(FadeOut {
+                sink: old_sink,
+                started: Instant::now(),
+                length,
+            });
+        }
+
+        let source = source.fade_in(length);
+        sink.append(self.track_position(source, Duration::ZERO));
+        sink.play();
+
+        self.sink = Some(sink);
+        self.current_file = Some(file_path.to_string());
+        self.is_playing = true;
+        self.is_paused = false;
+        self.current_duration = known_duration;
+        Ok(())
+    }
+
+    /// Whether a crossfade, pause, resume or stop fade is in progress.
+    pub fn is_fading(&self) -> bool {
+        self.fading_out.is_some() || self.ramp.is_some()
+    }
+
+    /// Steps the volume of any fade in progress: a track fading out is dropped once
+    /// silent, and a pause ramp pauses the sink at the bottom. Call regularly (every
+    /// frame) while `is_fading`.
+    pub fn update_fades(&mut self) {
+        self.update_ramp();
+        let Some(fade) = &self.fading_out else { return };
+        let progress = if fade.length.is_zero() {
+            1.0
+        } else {
+            fade.started.elapsed().as_secs_f32() / fade.length.as_secs_f32()
+        };
+        if progress >= 1.0 || fade.sink.empty() {
+            fade.sink.stop();
+            self.fading_out = None;
+        } else {
+            fade.sink.set_volume(self.volume * (1.0 - progress));
+        }
+    }
+
+    fn update_ramp(&mut self) {
+        let (Some(ramp), Some(sink)) = (&self.ramp, &self.sink) else {
+            self.ramp = None;
+            return;
+        };
+        let started = match ramp {
+            Ramp::Pausing(started) | Ramp::Resuming(started) => *started,
+        };
+        let progress = if self.fade.is_zero() {
+            1.0
+        } else {
+            (started.elapsed().as_secs_f32() / self.fade.as_secs_f32()).min(1.0)
+        };
+        match ramp {
+            Ramp::Pausing(_) if progress >= 1.0 => {
+                sink.pause();
+                // Back at full volume for when it's resumed
+                sink.set_volume(self.volume);
+                self.ramp = None;
+            }
+            Ramp::Pausing(_) => sink.set_volume(self.volume * (1.0 - progress)),
+            Ramp::Resuming(_) => {
+                sink.set_volume(self.volume * progress);
+                if progress >= 1.0 {
+                    self.ramp = None;
+                }
+            }
+        }
+    }
+
+    /// Appends `file_path` to the sink behind the current track so it starts the moment
+    /// the current one ends, with no gap. rodio converts between sample rates and
+    /// channel counts as it goes, so tracks in different formats can follow each other
+    /// on the same sink. Call `advance_to_queued` regularly to notice the switch.
+    pub fn queue_next(&mut self, file_path: &str, known_duration: Option<Duration>) -> Result<(), PlaybackError> {
+        if self.sink.is_none() {
+            return Ok(());
+        }
+        let source = self.open_prefetched(file_path)?;
+        let source = self.process(source);
+        let progress = Arc::<Progress>::default();
+        let sample_rate = source.sample_rate() as u64 * source.channels() as u64;
+        let Some(sink) = &self.sink else { return Ok(()) };
+        sink.append(PositionTracked {
+            inner: source,
+            progress: progress.clone(),
+        });
+        info!("Queued {} for gapless playback", file_path);
+        self.queued = Some(QueuedTrack {
+            file_path: file_path.to_string(),
+            duration: known_duration,
+            progress,
+            sample_rate,
+        });
+        Ok(())
+    }
+
+    pub fn queued_file(&self) -> Option<&str> {
+        self.queued.as_ref().map(|q| q.file_path.as_str())
+    }
+
+    /// Once the current track has run out and the queued one is playing, makes the
+    /// queued track current (file, duration and position) and returns `true`.
+    pub fn advance_to_queued(&mut self) -> bool {
+        let started = match (&self.sink, &self.queued) {
+            (Some(sink), Some(_)) => sink.len() <= 1,
+            _ => false,
+        };
+        if !started {
+            return false;
+        }
+        let queued = self.queued.take().unwrap();
+        info!("Gapless switch to {}", queued.file_path);
+        self.current_file = Some(queued.file_path);
+        self.current_duration = queued.duration;
+        self.stream = None;
+        self.progress = queued.progress;
+        self.sample_rate = queued.sample_rate;
+        self.position_offset = Duration::ZERO;
+        self.started_at = Instant::now();
+        true
+    }
+
+    /// Takes back the track queued by `queue_next`. A sink can't drop a source once it
+    /// has been appended, so the current track moves to a fresh sink where it was.
+    pub fn cancel_queued(&mut self) -> Result<(), PlaybackError> {
+        if self.queued.is_none() {
+            return Ok(());
+        }
+        self.seek(self.get_current_position())
+    }
+
+    /// Jumps to `position` in the current file. rodio 0.17 sources can't seek, so the
+    /// file is decoded again from the start and skipped forward on a fresh sink. The
+    /// volume and play/pause state of the old sink carry over.
+    pub fn seek(&mut self, position: Duration) -> Result<(), PlaybackError> {
+        let file_path = match &self.current_file {
+            Some(file_path) if self.is_seekable() => file_path.clone(),
+            _ => return Ok(()),
+        };
+
+        let sink = self.new_sink()?;
+        let source = Self::open_source(&file_path)?;
+        if let Some(old_sink) = &self.sink {
+            sink.set_volume(old_sink.volume());
+        }
+        let source = source.skip_duration(position);
+        sink.append(self.track_position(source, position));
+        if self.is_paused {
+            sink.pause();
+        }
+
+        if let Some(old_sink) = self.sink.replace(sink) {
+            old_sink.stop();
+        }
+        if let Some(fade) = self.fading_out.take() {
+            fade.sink.stop();
+        }
+        // The queued track was on the old sink
+        self.queued = None;
+        info!("Seeked to {:.1}s in {}", position.as_secs_f32(), file_path);
+        Ok(())
+    }
+
+    /// Wraps `source` in the effects chain and a fresh sample counter that starts at
+    /// `offset`.
+    fn track_position<S: Source<Item = i16>>(&mut self, source: S, offset: Duration) -> PositionTracked<Processed<S>> {
+        let source = self.process(source);
+        self.progress = Arc::default();
+        // Counted after the effects, which can turn mono into stereo
+        self.sample_rate = source.sample_rate() as u64 * source.channels() as u64;
+        self.position_offset = offset;
+        self.started_at = Instant::now();
+        PositionTracked {
+            inner: source,
+            progress: self.progress.clone(),
+        }
+    }
+
+    /// Runs `source` through the equalizer, mono downmix and balance, then the spectrum tap
+    /// and level meter so they see what is actually heard.
+    fn process<S: Source<Item = i16>>(&self, source: S) -> Processed<S> {
+        let equalized = Equalized::new(source, self.eq.clone());
+        let tapped = Tapped::new(ChannelMixed::new(equalized, self.channels.clone()), self.spectrum.clone());
+        Metered::new(tapped, self.meter.clone())
+    }
+
+    /// Starts opening `file_path` on a worker thread, so a later `play_file`,
+    /// `crossfade_to` or `queue_next` of that file doesn't wait on the disk or the format
+    /// probe. Only one file is kept: prefetching another discards the previous one.
+    pub fn prefetch(&mut self, file_path: &str) {
+        if self.prefetched.as_ref().is_some_and(|p| p.file_path == file_path) {
+            return;
+        }
+        let (sender, receiver) = bounded(1);
+        let path = file_path.to_string();
+        thread::spawn(move || {
+            let _ = sender.send(Self::open_source(&path));
+        });
+        info!("Prefetching {}", file_path);
+        self.prefetched = Some(Prefetch {
+            file_path: file_path.to_string(),
+            receiver,
+        });
+    }
+
+    /// Opens `file_path`, taking the prefetched source if it's for this file (waiting
+    /// for it if it isn't ready yet). A prefetch of any other file is dropped.
+    fn open_prefetched(&mut self, file_path: &str) -> Result<Decoder<BufReader<File>>, PlaybackError> {
+        match self.prefetched.take() {
+            Some(prefetch) if prefetch.file_path == file_path => {
+                prefetch.receiver.recv().unwrap_or_else(|_| Self::open_source(file_path))
+            }
+            _ => Self::open_source(file_path),
+        }
+    }
+
+    fn open_source(file_path: &str) -> Result<Decoder<BufReader<File>>, PlaybackError> {
+        let file = File::open(file_path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => PlaybackError::FileNotFound(file_path.to_string()),
+            _ => PlaybackError::Io(e),
+        })?;
+        let reader = BufReader::new(file);
+        Decoder::new(reader).map_err(|e| PlaybackError::UnsupportedFormat(e.to_string()))
+    }
+
     pub fn pause(&mut self) {
+        // Pausing mid-crossfade just finishes the fade
+        if let Some(fade) = self.fading_out.take() {
+            fade.sink.stop();
+        }
         if let Some(sink) = &self.sink {
-            sink.pause();
+            // The sink keeps playing until the fade-out reaches silence
+            if self.fade.is_zero() {
+                sink.pause();
+            } else {
+                self.ramp = Some(Ramp::Pausing(Instant::now()));
+            }
             self.is_paused = true;
             self.is_playing = false;
             info!("Audio paused");
@@ -73,6 +756,23 @@ impl AudioManager {
 
     pub fn resume(&mut self) {
         if let Some(sink) = &self.sink {
+            let started = match self.ramp.take() {
+                // Resumed mid fade-out: ramp back up from the current level
+                Some(Ramp::Pausing(started)) => Instant::now() - (self.fade.saturating_sub(started.elapsed())),
+                _ if sink.is_paused() => {
+                    sink.set_volume(0.0);
+                    Instant::now()
+                }
+                other => {
+                    self.ramp = other;
+                    return;
+                }
+            };
+            if !self.fade.is_zero() {
+                self.ramp = Some(Ramp::Resuming(started));
+            } else {
+                sink.set_volume(self.volume);
+            }
             sink.play();
             self.is_playing = true;
             self.is_paused = false;
@@ -80,11 +780,26 @@ impl AudioManager {
         }
     }
 
+    /// Stops playback. With a fade set, the track fades out in the background (see
+    /// `update_fades`) while the player already counts as stopped.
     pub fn stop(&mut self) {
-        if let Some(sink) = &self.sink {
-            sink.stop();
+        if let Some(fade) = self.fading_out.take() {
+            fade.sink.stop();
+        }
+        if let Some(sink) = self.sink.take() {
+            // Already silent (or on its way there for a pause)
+            if self.fade.is_zero() || sink.is_paused() || matches!(self.ramp, Some(Ramp::Pausing(_))) {
+                sink.stop();
+            } else {
+                self.fading_out = Some(FadeOut {
+                    sink,
+                    started: Instant::now(),
+                    length: self.fade,
+                });
+            }
         }
-        self.sink = None;
+        self.ramp = None;
+        self.queued = None;
         self.current_file = None;
         self.is_playing = false;
         self.is_paused = false;
@@ -93,11 +808,112 @@ impl AudioManager {
     }
 
     pub fn set_volume(&mut self, volume: f32) {
-        if let Some(sink) = &self.sink {
+        self.volume = volume;
+        // A ramp in progress picks up the new level on its next step
+        if let (Some(sink), None) = (&self.sink, &self.ramp) {
             sink.set_volume(volume);
         }
     }
 
+    /// Plays faster or slower, from 0.5x to 2x. Like a tape, the pitch changes with the
+    /// speed. Takes effect on the playing track straight away.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(PLAYBACK_SPEEDS[0], PLAYBACK_SPEEDS[PLAYBACK_SPEEDS.len() - 1]);
+        if let Some(sink) = &self.sink {
+            sink.set_speed(self.speed);
+        }
+        // A crossfade in progress keeps both tracks in step
+        if let Some(fade) = &self.fading_out {
+            fade.sink.set_speed(self.speed);
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Sets the equalizer band gains in dB, one per entry of `EQ_BANDS`. Takes effect on
+    /// the playing track within a few milliseconds.
+    pub fn set_eq_gains(&mut self, gains: &[f32]) {
+        self.eq.set_gains(gains);
+    }
+
+    pub fn eq_gains(&self) -> Vec<f32> {
+        self.eq.gains()
+    }
+
+    /// Turns the equalizer on or off. While off, samples bypass the filters entirely.
+    pub fn set_eq_enabled(&mut self, enabled: bool) {
+        self.eq.set_enabled(enabled);
+    }
+
+    pub fn eq_enabled(&self) -> bool {
+        self.eq.is_enabled()
+    }
+
+    /// Bass tone in dB, applied with a low shelf whether or not the EQ is enabled.
+    pub fn set_bass(&mut self, gain_db: f32) {
+        self.eq.set_bass(gain_db);
+    }
+
+    pub fn bass(&self) -> f32 {
+        self.eq.bass()
+    }
+
+    /// Treble tone in dB, applied with a high shelf whether or not the EQ is enabled.
+    pub fn set_treble(&mut self, gain_db: f32) {
+        self.eq.set_treble(gain_db);
+    }
+
+    pub fn treble(&self) -> f32 {
+        self.eq.treble()
+    }
+
+    /// Left-right balance from -1.0 (full left) to 1.0 (full right). Takes effect on
+    /// the playing track straight away.
+    pub fn set_balance(&mut self, balance: f32) {
+        self.channels.set_balance(balance);
+    }
+
+    pub fn balance(&self) -> f32 {
+        self.channels.balance()
+    }
+
+    /// Mixes every channel down to mono, for single-speaker setups. Takes effect on the
+    /// playing track straight away.
+    pub fn set_mono(&mut self, mono: bool) {
+        self.channels.set_mono(mono);
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.channels.is_mono()
+    }
+
+    /// The tap every playing source copies its samples into while it is enabled.
+    pub fn spectrum_tap(&self) -> Arc<SpectrumTap> {
+        self.spectrum.clone()
+    }
+
+    /// The current EQ, tone, balance and mono settings, for baking into a render.
+    pub fn render_effects(&self) -> RenderEffects {
+        RenderEffects {
+            eq: self.eq.clone(),
+            channels: self.channels.clone(),
+        }
+    }
+
+    /// Decodes `input` and writes it to `output` as a WAV file with the current effects
+    /// applied. Blocks until the whole file is written; `render::render_wav` can do the
+    /// same on a worker thread.
+    pub fn render_to_wav(&self, input: &str, output: &str) -> Result<(), PlaybackError> {
+        let track = RenderTrack { file_path: input.to_string(), gain: 1.0 };
+        render_wav(&[track], output, Some(&self.render_effects()))
+    }
+
+    pub fn level_meter(&self) -> &LevelMeter {
+        &self.meter
+    }
+
     pub fn is_playing(&self) -> bool {
         self.is_playing
     }
@@ -110,30 +926,43 @@ impl AudioManager {
         self.current_file.as_ref()
     }
 
-    pub fn get_position(&self) -> Duration {
-        if let Some(sink) = &self.sink {
-            Duration::from_secs_f32(sink.len() as f32 / 44100.0) // Approximate position
-        } else {
-            Duration::ZERO
-        }
-    }
-
+    /// Position in the current track, based on the samples actually handed to the output.
+    /// Increases while playing, holds still while paused, and accounts for seeks.
     pub fn get_current_position(&self) -> Duration {
-        if let Some(sink) = &self.sink {
-            // Get the current playback position
-            let samples_played = sink.len() as f32;
-            Duration::from_secs_f32(samples_played / 44100.0)
-        } else {
-            Duration::ZERO
+        if self.sink.is_none() || self.sample_rate == 0 {
+            return Duration::ZERO;
         }
+        let samples = self.progress.samples.load(Ordering::Relaxed);
+        self.position_offset + Duration::from_secs_f64(samples as f64 / self.sample_rate as f64)
     }
 
     pub fn get_total_duration(&self) -> Option<Duration> {
         self.current_duration
     }
 
-    fn probe_duration(file_path: &str) -> Option<Duration> {
+    /// Fills in a duration probed in the background. It's dropped if `file_path` is no
+    /// longer the current or queued track, since playback moved on in the meantime.
+    pub fn set_duration(&mut self, file_path: &str, duration: Duration) {
+        if self.current_file.as_deref() == Some(file_path) && self.stream.is_none() {
+            self.current_duration.get_or_insert(duration);
+        }
+        if let Some(queued) = self.queued.as_mut().filter(|q| q.file_path == file_path) {
+            queued.duration.get_or_insert(duration);
+        }
+    }
+
+    /// Reads the length of the first audio track from the container headers, without
+    /// decoding. `None` if the file can't be opened or doesn't record a frame count.
+    pub fn probe_duration(file_path: &str) -> Option<Duration> {
+        Self::probe_track_info(file_path)?.duration
+    }
+
+    /// Reads the codec, sample rate, channels, bit depth and length of the first audio
+    /// track from the container headers, without decoding. The bitrate is worked out
+    /// from the file size and length. `None` if the file can't be opened or probed.
+    pub fn probe_track_info(file_path: &str) -> Option<TrackInfo> {
         let file = File::open(file_path).ok()?;
+        let size = file.metadata().ok().map(|m| m.len());
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
         let probed = get_probe().format(
             &Default::default(),
@@ -142,18 +971,63 @@ impl AudioManager {
             &MetadataOptions::default(),
         ).ok()?;
         let format = probed.format;
-        let track = format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
-        let duration = track.codec_params.n_frames.and_then(|frames| {
-            track.codec_params.sample_rate.map(|rate| Duration::from_secs_f64(frames as f64 / rate as f64))
+        let params = &format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?.codec_params;
+        let duration = params.n_frames.and_then(|frames| {
+            params.sample_rate.map(|rate| Duration::from_secs_f64(frames as f64 / rate as f64))
         });
-        duration
+        let bitrate_kbps = match (size, duration) {
+            (Some(size), Some(duration)) if !duration.is_zero() => Some((size as f64 * 8.0 / duration.as_secs_f64() / 1000.0).round() as u32),
+            _ => None,
+        };
+        Some(TrackInfo {
+            codec: get_codecs().get_codec(params.codec).map(|c| c.short_name.to_string()),
+            sample_rate: params.sample_rate,
+            channels: params.channels.map(|c| c.count()),
+            bits_per_sample: params.bits_per_sample,
+            bitrate_kbps,
+            duration,
+        })
     }
 
+    /// Whether the current track has played to the end: the sink has drained and the
+    /// track's source itself reported running out, at least `MIN_PLAY_TIME` after it was
+    /// loaded. An empty sink alone isn't enough, since a sink that hasn't picked up a
+    /// just-appended source looks empty too. A sink that stays empty without the source
+    /// ever finishing is given up on after `STALL_TIMEOUT`.
     pub fn is_finished(&self) -> bool {
-        if let Some(sink) = &self.sink {
-            sink.len() == 0 && !sink.is_paused()
-        } else {
-            false
+        let Some(sink) = &self.sink else { return false };
+        if !sink.empty() || sink.is_paused() {
+            return false;
         }
+        self.progress.finished(self.started_at.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_is_not_finished_at_start_of_playback() {
+        let progress = Progress::default();
+        // Nothing pulled yet, as right after the source was appended
+        assert!(!progress.finished(Duration::ZERO));
+        assert!(!progress.finished(MIN_PLAY_TIME));
+
+        // Playing, but the source hasn't run out
+        progress.samples.store(44_100, Ordering::Relaxed);
+        assert!(!progress.finished(Duration::from_secs(1)));
+
+        // Run out, but within the minimum play time (e.g. a seek to the very end)
+        progress.exhausted.store(true, Ordering::Relaxed);
+        assert!(!progress.finished(Duration::from_millis(100)));
+        assert!(progress.finished(MIN_PLAY_TIME));
+    }
+
+    #[test]
+    fn stalled_track_is_given_up_on() {
+        let progress = Progress::default();
+        assert!(!progress.finished(STALL_TIMEOUT - Duration::from_millis(1)));
+        assert!(progress.finished(STALL_TIMEOUT));
     }
 } 
\ No newline at end of file
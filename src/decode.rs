@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::default::{get_codecs, get_probe};
+
+/// Decodes the first audio track of `file_path` to the end, calling `on_frame` with the
+/// interleaved `f32` samples of each frame (one sample per channel). Corrupt packets are
+/// skipped. Returns `None` if the file can't be opened or has no decodable track.
+///
+/// This is for offline analysis (waveforms, loudness); playback goes through rodio.
+pub fn for_each_frame<F: FnMut(&[f32])>(file_path: &str, mut on_frame: F) -> Option<()> {
+    let file = File::open(file_path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = Path::new(file_path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+    let mut format = probed.format;
+    let track = format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+    let track_id = track.id;
+    let mut decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default()).ok()?;
+
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    // An I/O error from next_packet is how symphonia reports the end of the stream
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue, // skip a corrupt packet
+            Err(_) => break,
+        };
+        let channels = decoded.spec().channels.count().max(1);
+        if !matches!(&buffer, Some(b) if b.capacity() >= decoded.capacity() * channels) {
+            buffer = Some(SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        }
+        let buffer = buffer.as_mut().unwrap();
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks(channels) {
+            on_frame(frame);
+        }
+    }
+    Some(())
+}
\ No newline at end of file
@@ -0,0 +1,134 @@
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Per-channel settings shared between the audio manager and every playing source.
+pub struct ChannelControl {
+    /// Balance as `f32` bits: -1.0 is full left, 0.0 center and 1.0 full right.
+    balance: AtomicU32,
+    /// Mix every channel down to the same signal.
+    mono: AtomicBool,
+}
+
+impl Default for ChannelControl {
+    fn default() -> Self {
+        Self {
+            balance: AtomicU32::new(0.0f32.to_bits()),
+            mono: AtomicBool::new(false),
+        }
+    }
+}
+
+impl ChannelControl {
+    pub fn set_balance(&self, balance: f32) {
+        self.balance.store(balance.clamp(-1.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn balance(&self) -> f32 {
+        f32::from_bits(self.balance.load(Ordering::Relaxed))
+    }
+
+    pub fn set_mono(&self, mono: bool) {
+        self.mono.store(mono, Ordering::Relaxed);
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.mono.load(Ordering::Relaxed)
+    }
+
+    /// Gain for `channel`. Only the first two channels are treated as left and right;
+    /// any others pass through unchanged.
+    fn gain(&self, channel: usize) -> f32 {
+        let balance = self.balance();
+        match channel {
+            0 => (1.0 - balance).min(1.0),
+            1 => (1.0 + balance).min(1.0),
+            _ => 1.0,
+        }
+    }
+}
+
+/// Applies the mono downmix and stereo balance to the wrapped source. Mono sources come
+/// out as stereo, with the one channel duplicated to both sides, so they can be
+/// balanced too. Works a frame at a time, since the downmix needs every channel.
+pub struct ChannelMixed<S> {
+    inner: S,
+    control: Arc<ChannelControl>,
+    frame: Vec<i16>,
+    /// Next sample of `frame` to hand out.
+    position: usize,
+}
+
+impl<S: Source<Item = i16>> ChannelMixed<S> {
+    pub fn new(inner: S, control: Arc<ChannelControl>) -> Self {
+        Self {
+            inner,
+            control,
+            frame: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Reads the next frame from the source. `None` once the source is exhausted.
+    fn fill_frame(&mut self) -> Option<()> {
+        let channels = self.inner.channels().max(1) as usize;
+        self.frame.clear();
+        self.position = 0;
+        self.frame.extend(self.inner.by_ref().take(channels));
+        match self.frame.len() {
+            0 => return None,
+            1 => self.frame.push(self.frame[0]),
+            // Averaged rather than taking one side, so nothing panned hard left or
+            // right goes missing
+            len if self.control.is_mono() => {
+                let sum: i32 = self.frame.iter().map(|&s| s as i32).sum();
+                let average = (sum / len as i32) as i16;
+                self.frame.fill(average);
+            }
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for ChannelMixed<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.position >= self.frame.len() {
+            self.fill_frame()?;
+        }
+        let channel = self.position;
+        self.position += 1;
+        let sample = self.frame[channel];
+        let gain = self.control.gain(channel);
+        if gain >= 1.0 {
+            return Some(sample);
+        }
+        Some((sample as f32 * gain) as i16)
+    }
+}
+
+impl<S: Source<Item = i16>> Source for ChannelMixed<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        let buffered = self.frame.len() - self.position;
+        if self.inner.channels() == 1 {
+            self.inner.current_frame_len().map(|len| len * 2 + buffered)
+        } else {
+            self.inner.current_frame_len().map(|len| len + buffered)
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels().max(2)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
\ No newline at end of file
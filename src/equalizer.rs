@@ -0,0 +1,295 @@
+use rodio::Source;
+use std::f32::consts::{PI, SQRT_2};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Centre frequencies of the graphic EQ bands, in Hz.
+pub const EQ_BANDS: [f32; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+
+/// Range of each band's gain, in dB.
+pub const EQ_MAX_GAIN_DB: f32 = 12.0;
+
+/// Named band settings offered in the UI, one gain in dB per entry of `EQ_BANDS`.
+pub const EQ_PRESETS: &[(&str, [f32; 10])] = &[
+    ("Flat", [0.0; 10]),
+    ("Bass Boost", [6.0, 5.0, 4.0, 2.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("Vocal", [-2.0, -2.0, -1.0, 0.5, 2.0, 3.0, 3.0, 1.5, 0.0, -1.0]),
+    ("Treble Boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 4.0, 5.0, 6.0]),
+];
+
+/// Range of the bass and treble tone controls, in dB.
+pub const TONE_MAX_GAIN_DB: f32 = 12.0;
+
+/// Corner frequencies of the bass and treble shelves, in Hz.
+const BASS_FREQUENCY: f32 = 100.0;
+const TREBLE_FREQUENCY: f32 = 10000.0;
+
+/// Bandwidth of each peaking filter. About one octave, matching the band spacing.
+const BAND_Q: f32 = 1.4;
+
+/// How many samples pass between checks for changed settings.
+const CONTROL_INTERVAL: usize = 1024;
+
+/// EQ and tone settings shared between the audio manager and every playing source, so
+/// changes apply to the track that is already playing.
+pub struct EqControl {
+    enabled: AtomicBool,
+    gains: Mutex<Vec<f32>>,
+    /// Bass and treble shelf gains in dB. These apply whether or not the EQ is enabled.
+    tone: Mutex<(f32, f32)>,
+    /// Bumped on every change so sources know to recompute their coefficients.
+    generation: AtomicU64,
+}
+
+impl Default for EqControl {
+    fn default() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            gains: Mutex::new(vec![0.0; EQ_BANDS.len()]),
+            tone: Mutex::new((0.0, 0.0)),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl EqControl {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Sets the band gains in dB. Missing bands are treated as flat and each gain is
+    /// clamped to `EQ_MAX_GAIN_DB`.
+    pub fn set_gains(&self, gains: &[f32]) {
+        let gains = (0..EQ_BANDS.len())
+            .map(|i| gains.get(i).copied().unwrap_or(0.0).clamp(-EQ_MAX_GAIN_DB, EQ_MAX_GAIN_DB))
+            .collect();
+        *self.gains.lock().unwrap() = gains;
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn gains(&self) -> Vec<f32> {
+        self.gains.lock().unwrap().clone()
+    }
+
+    pub fn set_bass(&self, gain_db: f32) {
+        self.tone.lock().unwrap().0 = gain_db.clamp(-TONE_MAX_GAIN_DB, TONE_MAX_GAIN_DB);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn bass(&self) -> f32 {
+        self.tone.lock().unwrap().0
+    }
+
+    pub fn set_treble(&self, gain_db: f32) {
+        self.tone.lock().unwrap().1 = gain_db.clamp(-TONE_MAX_GAIN_DB, TONE_MAX_GAIN_DB);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn treble(&self) -> f32 {
+        self.tone.lock().unwrap().1
+    }
+}
+
+/// Coefficients of one biquad section, normalised so a0 = 1.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    /// Peaking EQ from the RBJ audio EQ cookbook.
+    fn peaking(frequency: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let alpha = w0.sin() / (2.0 * BAND_Q);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha / a;
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: (-2.0 * cos_w0) / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: (-2.0 * cos_w0) / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+
+    /// Low shelf with a slope of 1, from the same cookbook.
+    fn low_shelf(frequency: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / 2.0 * SQRT_2;
+        let beta = 2.0 * a.sqrt() * alpha;
+        let a0 = (a + 1.0) + (a - 1.0) * cos_w0 + beta;
+        Self {
+            b0: a * ((a + 1.0) - (a - 1.0) * cos_w0 + beta) / a0,
+            b1: 2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0) / a0,
+            b2: a * ((a + 1.0) - (a - 1.0) * cos_w0 - beta) / a0,
+            a1: -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0) / a0,
+            a2: ((a + 1.0) + (a - 1.0) * cos_w0 - beta) / a0,
+        }
+    }
+
+    /// High shelf with a slope of 1, from the same cookbook.
+    fn high_shelf(frequency: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / 2.0 * SQRT_2;
+        let beta = 2.0 * a.sqrt() * alpha;
+        let a0 = (a + 1.0) - (a - 1.0) * cos_w0 + beta;
+        Self {
+            b0: a * ((a + 1.0) + (a - 1.0) * cos_w0 + beta) / a0,
+            b1: -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0) / a0,
+            b2: a * ((a + 1.0) + (a - 1.0) * cos_w0 - beta) / a0,
+            a1: 2.0 * ((a - 1.0) - (a + 1.0) * cos_w0) / a0,
+            a2: ((a + 1.0) - (a - 1.0) * cos_w0 - beta) / a0,
+        }
+    }
+}
+
+/// Filter memory for one band on one channel.
+#[derive(Clone, Copy, Default)]
+struct BiquadState {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl BiquadState {
+    fn process(&mut self, filter: &Biquad, x: f32) -> f32 {
+        let y = filter.b0 * x + filter.b1 * self.x1 + filter.b2 * self.x2 - filter.a1 * self.y1 - filter.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Runs the wrapped source through the EQ's chain of peaking filters and the tone
+/// shelves. While the EQ is off (or flat) and the tone is flat, samples are passed
+/// straight through.
+pub struct Equalized<S> {
+    inner: S,
+    control: Arc<EqControl>,
+    generation: u64,
+    active: bool,
+    filters: Vec<Biquad>,
+    /// `filters.len()` states per channel, channel-major.
+    states: Vec<BiquadState>,
+    channel: usize,
+    until_check: usize,
+}
+
+impl<S: Source<Item = i16>> Equalized<S> {
+    pub fn new(inner: S, control: Arc<EqControl>) -> Self {
+        let mut equalized = Self {
+            inner,
+            control,
+            generation: u64::MAX,
+            active: false,
+            filters: Vec::new(),
+            states: Vec::new(),
+            channel: 0,
+            until_check: 0,
+        };
+        equalized.refresh();
+        equalized
+    }
+
+    /// Picks up changed settings, rebuilding the filters only when something changed.
+    fn refresh(&mut self) {
+        self.until_check = CONTROL_INTERVAL;
+        let generation = self.control.generation.load(Ordering::Relaxed);
+        if generation == self.generation {
+            return;
+        }
+        self.generation = generation;
+
+        let gains = self.control.gains();
+        let (bass, treble) = *self.control.tone.lock().unwrap();
+        let sample_rate = self.inner.sample_rate() as f32;
+        self.filters.clear();
+        if self.control.is_enabled() && gains.iter().any(|g| g.abs() > 0.01) {
+            // Bands at or beyond Nyquist can't be filtered at this sample rate
+            self.filters.extend(
+                EQ_BANDS
+                    .iter()
+                    .zip(&gains)
+                    .filter(|(&frequency, _)| frequency < sample_rate * 0.45)
+                    .map(|(&frequency, &gain)| Biquad::peaking(frequency, gain, sample_rate)),
+            );
+        }
+        if bass.abs() > 0.01 {
+            self.filters.push(Biquad::low_shelf(BASS_FREQUENCY, bass, sample_rate));
+        }
+        if treble.abs() > 0.01 && TREBLE_FREQUENCY < sample_rate * 0.45 {
+            self.filters.push(Biquad::high_shelf(TREBLE_FREQUENCY, treble, sample_rate));
+        }
+        self.active = !self.filters.is_empty();
+        if !self.active {
+            return;
+        }
+        let channels = self.inner.channels().max(1) as usize;
+        if self.states.len() != channels * self.filters.len() {
+            self.states = vec![BiquadState::default(); channels * self.filters.len()];
+        }
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Equalized<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next()?;
+        let channels = self.inner.channels().max(1) as usize;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % channels;
+
+        self.until_check = self.until_check.saturating_sub(1);
+        if self.until_check == 0 && channel + 1 == channels {
+            // Only between frames so every channel switches settings together
+            self.refresh();
+        }
+        if !self.active {
+            return Some(sample);
+        }
+
+        let bands = self.filters.len();
+        let mut x = sample as f32 / i16::MAX as f32;
+        for (filter, state) in self.filters.iter().zip(&mut self.states[channel * bands..(channel + 1) * bands]) {
+            x = state.process(filter, x);
+        }
+        Some((x.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Equalized<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
\ No newline at end of file
@@ -1,7 +1,28 @@
 pub mod app;
+pub mod artwork;
 pub mod audio;
+pub mod decode;
+pub mod effects;
+pub mod equalizer;
+pub mod loudness;
+pub mod meter;
+#[cfg(all(feature = "mpris", target_os = "linux"))]
+pub mod mpris;
+pub mod now_playing;
+pub mod player;
 pub mod playlist;
+pub mod remote;
+pub mod render;
+pub mod scanner;
+pub mod session;
+pub mod settings;
+pub mod spectrum;
+pub mod stream;
+pub mod tags;
+pub mod toast;
 pub mod ui;
 pub mod utils;
+pub mod waveform;
  
-pub use app::MusicPlayerApp; 
\ No newline at end of file
+pub use app::MusicPlayerApp;
+pub use player::{PlaybackMode, Player, PlayerEvent, PlaylistSort, RepeatMode, SortKey}; 
\ No newline at end of file
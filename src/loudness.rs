@@ -0,0 +1,42 @@
+use crate::decode::for_each_frame;
+
+/// Loudness every track is brought to, as an RMS level in dBFS. Roughly where
+/// ReplayGain's reference level lands for typical music.
+pub const TARGET_RMS_DB: f32 = -18.0;
+
+/// Largest cut or boost applied, so near-silent or clipped files don't get extreme gain.
+const MAX_GAIN_DB: f32 = 12.0;
+
+/// Measures the track's RMS level and returns the gain in dB that brings it to
+/// `TARGET_RMS_DB`. A simple stand-in for full ReplayGain analysis, used when the file
+/// carries no ReplayGain tag. `None` if the file can't be decoded or is silent.
+pub fn analyze_track_gain(file_path: &str) -> Option<f32> {
+    let mut sum_squares = 0.0f64;
+    let mut samples = 0u64;
+    for_each_frame(file_path, |frame| {
+        for &sample in frame {
+            sum_squares += (sample as f64) * (sample as f64);
+        }
+        samples += frame.len() as u64;
+    })?;
+    if samples == 0 || sum_squares == 0.0 {
+        return None;
+    }
+    let rms_db = 10.0 * (sum_squares / samples as f64).log10() as f32;
+    Some((TARGET_RMS_DB - rms_db).clamp(-MAX_GAIN_DB, MAX_GAIN_DB))
+}
+
+/// Parses a ReplayGain tag value such as `-6.54 dB`.
+pub fn parse_replay_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    number.trim().parse().ok()
+}
+
+/// Converts a gain in dB to a linear volume factor.
+pub fn gain_factor(gain_db: f32) -> f32 {
+    10f32.powf(gain_db / 20.0)
+}
\ No newline at end of file
@@ -1,11 +1,147 @@
+use rust_music_player::remote::{is_instance_running, send_command, send_open, RemoteCommand, RemoteServer};
+use rust_music_player::session::WindowGeometry;
 use rust_music_player::MusicPlayerApp;
+use std::path::PathBuf;
 
 fn main() -> Result<(), eframe::Error> {
     tracing_subscriber::fmt::init();
-    let options = eframe::NativeOptions::default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (flags, paths) = split_args(args);
+    if !flags.is_empty() {
+        if !paths.is_empty() {
+            eprintln!("Files and folders can't be combined with commands");
+            print_usage();
+            std::process::exit(2);
+        }
+        std::process::exit(run_remote(&flags));
+    }
+    let requested = !paths.is_empty();
+    let paths = open_paths(paths);
+    if is_instance_running() {
+        if !requested {
+            eprintln!("Rust Music Player is already running. Control it with --play, --pause, --next, ... (see --help)");
+            return Ok(());
+        }
+        // Hand the files to the running window instead of opening another one
+        if paths.is_empty() {
+            std::process::exit(1);
+        }
+        if let Err(e) = send_open(&paths) {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let window = WindowGeometry::load();
+    let mut viewport = egui::ViewportBuilder::default();
+    if let Some(window) = window {
+        viewport = viewport.with_inner_size(window.size).with_maximized(window.maximized);
+        if let Some(position) = window.position {
+            viewport = viewport.with_position(position);
+        }
+    }
+    let options = eframe::NativeOptions {
+        viewport,
+        // Reports the OS light/dark preference for the "System" theme
+        follow_system_theme: true,
+        ..Default::default()
+    };
     eframe::run_native(
         "Rust Music Player",
         options,
-        Box::new(|_cc| Box::new(MusicPlayerApp::new())),
+        Box::new(move |cc| {
+            let app = MusicPlayerApp::new().with_restored_window(window).with_open_paths(paths);
+            #[cfg(all(feature = "mpris", target_os = "linux"))]
+            let app = {
+                let ctx = cc.egui_ctx.clone();
+                match rust_music_player::mpris::MprisServer::start(move || ctx.request_repaint()) {
+                    Ok(mpris) => app.with_mpris(mpris),
+                    Err(e) => {
+                        tracing::warn!("Media keys unavailable: {:#}", e);
+                        app
+                    }
+                }
+            };
+            let ctx = cc.egui_ctx.clone();
+            match RemoteServer::start(move || ctx.request_repaint()) {
+                Ok(remote) => Box::new(app.with_remote(remote)),
+                Err(e) => {
+                    tracing::warn!("Remote control unavailable: {:#}", e);
+                    Box::new(app)
+                }
+            }
+        }),
     )
-} 
\ No newline at end of file
+}
+
+/// Splits the arguments into command flags and files or folders to open. Everything
+/// after `--` is a file, so names starting with `-` can be opened too.
+fn split_args(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut flags = Vec::new();
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            paths.extend(args.by_ref());
+        } else if arg.starts_with('-') {
+            flags.push(arg);
+        } else {
+            paths.push(arg);
+        }
+    }
+    (flags, paths)
+}
+
+/// Resolves the files and folders given on the command line, leaving out ones that
+/// don't exist.
+fn open_paths(args: Vec<String>) -> Vec<PathBuf> {
+    args.into_iter()
+        .filter_map(|arg| match std::fs::canonicalize(&arg) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                eprintln!("Skipping {}: {}", arg, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Sends each command-line flag to the running player, returning the exit code.
+fn run_remote(args: &[String]) -> i32 {
+    let mut commands = Vec::new();
+    for arg in args {
+        if arg == "--help" || arg == "-h" {
+            print_usage();
+            return 0;
+        }
+        match RemoteCommand::from_arg(arg) {
+            Some(command) => commands.push(command),
+            None => {
+                eprintln!("Unknown option: {}", arg);
+                print_usage();
+                return 2;
+            }
+        }
+    }
+    for command in commands {
+        if let Err(e) = send_command(command) {
+            eprintln!("Error: {:#}. Start the player without arguments first.", e);
+            return 1;
+        }
+    }
+    0
+}
+
+fn print_usage() {
+    println!("Usage: rust_music_player [--] [FILE|FOLDER...]");
+    println!("       rust_music_player [COMMAND...]");
+    println!();
+    println!("Files and folders are added to the playlist, and the first one plays. If the player is");
+    println!("already running they're added to its window. Put -- before names that start with -.");
+    println!();
+    println!("Commands control the running player:");
+    for (command, help) in RemoteCommand::ALL {
+        println!("  --{:<10} {}", command.name(), help);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,138 @@
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Frames measured for each reading; about 23 ms at 44.1 kHz.
+const METER_WINDOW: usize = 1024;
+
+/// Readings older than this count as silence, so the meter drops to zero as soon as
+/// the sink stops pulling samples (paused, stopped or starved).
+const STALE_AFTER: Duration = Duration::from_millis(150);
+
+/// Peak and RMS level of the left and right channels as linear amplitudes in
+/// `0.0..=1.0`. A mono source reads the same on both.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Levels {
+    pub peak: [f32; 2],
+    pub rms: [f32; 2],
+}
+
+/// The latest levels measured from the playing source. Like the spectrum tap, it only
+/// looks at the samples and never changes them.
+#[derive(Default)]
+pub struct LevelMeter {
+    enabled: AtomicBool,
+    latest: Mutex<Option<(Levels, Instant)>>,
+}
+
+impl LevelMeter {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            *self.latest.lock().unwrap() = None;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn publish(&self, levels: Levels) {
+        // As with the spectrum tap, the audio thread never waits on the UI
+        if let Ok(mut latest) = self.latest.try_lock() {
+            *latest = Some((levels, Instant::now()));
+        }
+    }
+
+    pub fn levels(&self) -> Levels {
+        match *self.latest.lock().unwrap() {
+            Some((levels, at)) if at.elapsed() < STALE_AFTER => levels,
+            _ => Levels::default(),
+        }
+    }
+}
+
+/// Measures the samples of the wrapped source into a `LevelMeter` while it's enabled.
+/// Channels past the first two aren't metered.
+pub struct Metered<S> {
+    inner: S,
+    meter: Arc<LevelMeter>,
+    peak: [f32; 2],
+    sum_squares: [f32; 2],
+    frames: usize,
+    channel: u16,
+}
+
+impl<S: Source<Item = i16>> Metered<S> {
+    pub fn new(inner: S, meter: Arc<LevelMeter>) -> Self {
+        Self {
+            inner,
+            meter,
+            peak: [0.0; 2],
+            sum_squares: [0.0; 2],
+            frames: 0,
+            channel: 0,
+        }
+    }
+
+    fn measure(&mut self, slot: usize, value: f32) {
+        self.peak[slot] = self.peak[slot].max(value.abs());
+        self.sum_squares[slot] += value * value;
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Metered<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next()?;
+        if !self.meter.is_enabled() {
+            return Some(sample);
+        }
+        let channels = self.inner.channels().max(1);
+        let value = sample as f32 / i16::MAX as f32;
+        match (channels, self.channel) {
+            (1, _) => {
+                self.measure(0, value);
+                self.measure(1, value);
+            }
+            (_, channel) if channel < 2 => self.measure(channel as usize, value),
+            _ => {}
+        }
+        self.channel += 1;
+        if self.channel >= channels {
+            self.channel = 0;
+            self.frames += 1;
+            if self.frames >= METER_WINDOW {
+                let frames = self.frames as f32;
+                self.meter.publish(Levels {
+                    peak: self.peak,
+                    rms: self.sum_squares.map(|sum| (sum / frames).sqrt()),
+                });
+                self.peak = [0.0; 2];
+                self.sum_squares = [0.0; 2];
+                self.frames = 0;
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Metered<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
\ No newline at end of file
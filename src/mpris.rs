@@ -0,0 +1,287 @@
+use crate::remote::RemoteCommand;
+use anyhow::{Context, Result};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::interface;
+use zbus::zvariant::{ObjectPath, Value};
+
+/// Well-known bus name the player registers, as MPRIS requires.
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.rust_music_player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// What desktop media controls are shown about the player.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MprisState {
+    pub playing: bool,
+    pub paused: bool,
+    /// Index of the current song, used to build its track id.
+    pub track: Option<usize>,
+    pub title: String,
+    pub artist: String,
+    pub album: Option<String>,
+    pub length: Option<Duration>,
+    pub position: Duration,
+    pub volume: f32,
+}
+
+impl MprisState {
+    fn playback_status(&self) -> &'static str {
+        if self.playing {
+            "Playing"
+        } else if self.paused {
+            "Paused"
+        } else {
+            "Stopped"
+        }
+    }
+
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        let mut metadata = HashMap::new();
+        let Some(track) = self.track else {
+            let path = ObjectPath::from_static_str_unchecked("/org/mpris/MediaPlayer2/TrackList/NoTrack");
+            metadata.insert("mpris:trackid".to_string(), Value::from(path));
+            return metadata;
+        };
+        let path = format!("/org/rust_music_player/track/{}", track);
+        if let Ok(path) = ObjectPath::try_from(path) {
+            metadata.insert("mpris:trackid".to_string(), Value::from(path.into_owned()));
+        }
+        metadata.insert("xesam:title".to_string(), Value::from(self.title.clone()));
+        metadata.insert("xesam:artist".to_string(), Value::from(vec![self.artist.clone()]));
+        if let Some(album) = &self.album {
+            metadata.insert("xesam:album".to_string(), Value::from(album.clone()));
+        }
+        if let Some(length) = self.length {
+            metadata.insert("mpris:length".to_string(), Value::from(length.as_micros() as i64));
+        }
+        metadata
+    }
+}
+
+/// The `org.mpris.MediaPlayer2` root interface. The player has no window to raise and
+/// can't be quit from outside, so this is all fixed.
+struct Root;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "Rust Music Player"
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface. Method calls are handed to the GUI as
+/// remote commands; properties are read from the state the GUI last published.
+struct PlayerInterface {
+    state: Arc<Mutex<MprisState>>,
+    sender: Sender<RemoteCommand>,
+    notify: Box<dyn Fn() + Send + Sync>,
+}
+
+impl PlayerInterface {
+    fn send(&self, command: RemoteCommand) {
+        info!("MPRIS command: {}", command.name());
+        if self.sender.send(command).is_ok() {
+            (self.notify)();
+        }
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl PlayerInterface {
+    fn play(&self) {
+        self.send(RemoteCommand::Play);
+    }
+
+    fn pause(&self) {
+        self.send(RemoteCommand::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.send(RemoteCommand::TogglePause);
+    }
+
+    fn stop(&self) {
+        self.send(RemoteCommand::Stop);
+    }
+
+    fn next(&self) {
+        self.send(RemoteCommand::Next);
+    }
+
+    fn previous(&self) {
+        self.send(RemoteCommand::Previous);
+    }
+
+    // Seeking isn't offered (`CanSeek` is false), but the spec requires the methods
+    fn seek(&self, _offset: i64) {}
+
+    fn set_position(&self, _track_id: ObjectPath<'_>, _position: i64) {}
+
+    fn open_uri(&self, _uri: &str) {}
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &'static str {
+        self.state.lock().unwrap().playback_status()
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        self.state.lock().unwrap().metadata()
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        self.state.lock().unwrap().position.as_micros() as i64
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.state.lock().unwrap().volume as f64
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// Registers the player on the D-Bus session bus under MPRIS, so media keys and the
+/// desktop's media controls can drive it. Incoming calls arrive as `RemoteCommand`s,
+/// picked up with `poll` like those from `RemoteServer`.
+pub struct MprisServer {
+    connection: Connection,
+    receiver: Receiver<RemoteCommand>,
+    state: Arc<Mutex<MprisState>>,
+}
+
+impl MprisServer {
+    /// Connects to the session bus and claims the player's MPRIS name. `notify` is called
+    /// after each command is queued so a GUI can wake up and poll.
+    pub fn start<F>(notify: F) -> Result<Self>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let (sender, receiver) = unbounded();
+        let state = Arc::new(Mutex::new(MprisState::default()));
+        let player = PlayerInterface {
+            state: state.clone(),
+            sender,
+            notify: Box::new(notify),
+        };
+        let connection = Builder::session()
+            .and_then(|builder| builder.name(BUS_NAME))
+            .and_then(|builder| builder.serve_at(OBJECT_PATH, Root))
+            .and_then(|builder| builder.serve_at(OBJECT_PATH, player))
+            .and_then(|builder| builder.build())
+            .context("could not register on the D-Bus session bus")?;
+        info!("Registered {} for MPRIS", BUS_NAME);
+        Ok(Self { connection, receiver, state })
+    }
+
+    /// Drains the commands received since the last call.
+    pub fn poll(&self) -> Vec<RemoteCommand> {
+        self.receiver.try_iter().collect()
+    }
+
+    /// Publishes the player's current state, telling listeners when the playback status
+    /// or track changed. Cheap to call every frame.
+    pub fn update(&self, new: MprisState) {
+        let mut changed: HashMap<&str, Value<'static>> = HashMap::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            if new.playback_status() != state.playback_status() {
+                changed.insert("PlaybackStatus", Value::from(new.playback_status()));
+            }
+            if new.metadata() != state.metadata() {
+                changed.insert("Metadata", Value::from(new.metadata()));
+            }
+            if new.volume != state.volume {
+                changed.insert("Volume", Value::from(new.volume as f64));
+            }
+            *state = new;
+        }
+        if changed.is_empty() {
+            return;
+        }
+        let body = (PLAYER_INTERFACE, changed, Vec::<String>::new());
+        if let Err(e) = self.connection.emit_signal(None::<&str>, OBJECT_PATH, "org.freedesktop.DBus.Properties", "PropertiesChanged", &body) {
+            warn!("Could not send MPRIS property changes: {}", e);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+use crate::artwork::find_cover_art;
+use crate::playlist::Song;
+use crate::utils::config_dir;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Layout of the now-playing file read by streaming overlays such as OBS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NowPlayingFormat {
+    /// A single "Artist - Title" line, for a text source.
+    Text,
+    /// Title, artist, album and optionally the cover's path, for scripted overlays.
+    Json,
+}
+
+impl NowPlayingFormat {
+    fn default_file_name(self) -> &'static str {
+        match self {
+            NowPlayingFormat::Text => "now_playing.txt",
+            NowPlayingFormat::Json => "now_playing.json",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct NowPlayingJson<'a> {
+    title: &'a str,
+    artist: &'a str,
+    album: Option<&'a str>,
+    file_path: &'a str,
+    art_path: Option<String>,
+}
+
+/// Where the now-playing file goes: `custom` when set, otherwise a file in the config
+/// directory named after the format.
+pub fn now_playing_path(custom: Option<&str>, format: NowPlayingFormat) -> Option<PathBuf> {
+    match custom.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => config_dir().map(|dir| dir.join(format.default_file_name())),
+    }
+}
+
+/// Writes `song` to `path` in `format`, or empties the file when `song` is `None` so
+/// the overlay goes blank while nothing plays. With `include_art`, the JSON format
+/// also writes the song's embedded cover next to the file and points at it.
+pub fn write_now_playing(path: &Path, format: NowPlayingFormat, song: Option<&Song>, include_art: bool) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let Some(song) = song else {
+        std::fs::write(path, "")?;
+        return Ok(());
+    };
+    let content = match format {
+        NowPlayingFormat::Text => format!("{} - {}", song.artist, song.title),
+        NowPlayingFormat::Json => {
+            let art_path = if include_art { write_cover(path, song)? } else { None };
+            serde_json::to_string_pretty(&NowPlayingJson {
+                title: &song.title,
+                artist: &song.artist,
+                album: song.album.as_deref(),
+                file_path: &song.file_path,
+                art_path,
+            })?
+        }
+    };
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Saves the song's cover (embedded, or from its folder) as `<file stem>_cover.<ext>` beside `path` and
+/// returns where it went. `None` when the song has no art.
+fn write_cover(path: &Path, song: &Song) -> Result<Option<String>> {
+    let Some(bytes) = find_cover_art(&song.file_path) else { return Ok(None) };
+    let extension = match image::guess_format(&bytes) {
+        Ok(image::ImageFormat::Png) => "png",
+        _ => "jpg",
+    };
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "now_playing".to_string());
+    let art_path = path.with_file_name(format!("{}_cover.{}", stem, extension));
+    std::fs::write(&art_path, bytes)?;
+    Ok(Some(art_path.display().to_string()))
+}
\ No newline at end of file
@@ -0,0 +1,2004 @@
+use crate::audio::{AudioManager, PlaybackError, TrackInfo};
+use crate::loudness::{analyze_track_gain, gain_factor};
+use crate::playlist::{PlaylistManager, Song};
+use crate::session::{SavedPlaylist, Session};
+use crate::meter::Levels;
+use crate::render::{render_wav, RenderTrack};
+use crate::spectrum::SpectrumAnalyzer;
+use crate::stream::{is_stream_url, HttpStream, StreamStatus};
+use crate::utils::canonical_path;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rand::seq::SliceRandom;
+use rodio::Decoder;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortKey {
+    Title,
+    Artist,
+    Duration,
+    Rating,
+}
+
+/// A sort applied to the song list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistSort {
+    pub key: SortKey,
+    pub ascending: bool,
+}
+
+/// How long before the current track ends the next one is queued for gapless playback.
+const GAPLESS_PRELOAD: Duration = Duration::from_secs(5);
+
+/// How long before the current track ends the next one starts opening in the background.
+const PREFETCH_AHEAD: Duration = Duration::from_secs(10);
+
+/// Name of the playlist a fresh player starts with.
+pub const DEFAULT_PLAYLIST: &str = "Library";
+
+/// How long before the sleep timer fires the volume starts fading down.
+pub const SLEEP_FADE: Duration = Duration::from_secs(30);
+
+/// A track left this close to its end counts as finished, so it starts over next time
+/// rather than resuming for the last few seconds.
+const RESUME_END_MARGIN: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PlaybackMode {
+    #[default]
+    Sequential,
+    Shuffle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RepeatMode {
+    #[default]
+    None,
+    One,
+    All,
+}
+
+impl RepeatMode {
+    /// The next mode in the None → All → One cycle used by the repeat button.
+    pub fn cycle(self) -> Self {
+        match self {
+            RepeatMode::None => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::None,
+        }
+    }
+}
+
+/// Things that happened during a call that a frontend may want to surface.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    TrackStarted(usize),
+    /// The song at this index picked up from where it was left last time.
+    Resumed(usize, Duration),
+    Stopped,
+    /// A WAV export finished writing to this path.
+    Exported(String),
+    Error(String),
+}
+
+/// GUI-independent control surface for the player. Owns the audio engine, the playlist
+/// library and the current play list, and implements navigation (next/previous,
+/// shuffle, auto-advance) so any frontend — the egui UI, a CLI or tests — drives
+/// playback the same way.
+pub struct Player {
+    audio: AudioManager,
+    playlists: PlaylistManager,
+    songs: Vec<Song>,
+    current_index: Option<usize>,
+    is_playing: bool,
+    is_paused: bool,
+    volume: f32,
+    muted: bool,
+    /// Apply each song's `replay_gain_db` on top of the volume.
+    normalize: bool,
+    /// Prefer each song's album gain, falling back to its track gain.
+    album_gain: bool,
+    total_duration: Option<Duration>,
+    pending_next_time: Option<Instant>,
+    auto_advance_delay: Duration,
+    /// Skip the auto-advance delay when the next song is from the same album.
+    delay_between_albums_only: bool,
+    mode: PlaybackMode,
+    repeat_mode: RepeatMode,
+    shuffle_order: Vec<usize>,
+    shuffle_cursor: usize,
+    failed_attempts: usize,
+    /// Whether the next song has been queued (or tried) for gapless playback during the
+    /// current track.
+    gapless_attempted: bool,
+    /// Set when the next `play_current` should crossfade over this long instead of cutting.
+    pending_crossfade: Option<Duration>,
+    /// When the sleep timer stops playback.
+    sleep_deadline: Option<Instant>,
+    /// Stop once the current track ends instead of moving on.
+    stop_after_current: bool,
+    /// Songs picked to play next, by file path, ahead of the normal play order.
+    queue: VecDeque<String>,
+    /// A-B loop points within the current track.
+    loop_a: Option<Duration>,
+    loop_b: Option<Duration>,
+    events: Vec<PlayerEvent>,
+    /// Durations probed in the background, as (file path, duration) pairs.
+    duration_sender: Sender<(String, Option<Duration>)>,
+    duration_receiver: Receiver<(String, Option<Duration>)>,
+    /// Durations asked for that haven't come back yet.
+    probing: usize,
+    /// Loudness analysis results, as (file path, gain in dB) pairs.
+    gain_sender: Sender<(String, Option<f32>)>,
+    gain_receiver: Receiver<(String, Option<f32>)>,
+    analyzing: HashSet<String>,
+    /// Format details of the current song, by file path, read in the background.
+    track_info: Option<(String, TrackInfo)>,
+    info_sender: Sender<(String, Option<TrackInfo>)>,
+    info_receiver: Receiver<(String, Option<TrackInfo>)>,
+    /// URL of the stream being connected to, for the current song.
+    opening_stream: Option<String>,
+    stream_sender: Sender<(String, OpenedStream)>,
+    stream_receiver: Receiver<(String, OpenedStream)>,
+    /// Running while the spectrum analyzer is switched on.
+    spectrum: Option<SpectrumAnalyzer>,
+    /// Bar count and smoothing for the spectrum analyzer.
+    spectrum_options: (usize, f32),
+    /// Where each track was left when playback moved away from it, by file path.
+    resume_positions: HashMap<String, Duration>,
+    /// Only positions past this are remembered; `None` turns resuming off.
+    resume_after: Option<Duration>,
+    /// Finished WAV exports, as (output path, result) pairs.
+    export_sender: Sender<(String, Result<(), PlaybackError>)>,
+    export_receiver: Receiver<(String, Result<(), PlaybackError>)>,
+    exporting: usize,
+}
+
+/// An internet stream opened in the background, ready to hand to the audio engine.
+type OpenedStream = Result<(Decoder<HttpStream>, StreamStatus), PlaybackError>;
+
+/// Orders songs that `key` ranks equal: an artist's songs by album and track, songs
+/// with the same title by artist, and everything else (including songs of unknown
+/// duration) by title.
+fn sort_tiebreak(a: &Song, b: &Song, key: SortKey) -> std::cmp::Ordering {
+    let title = || a.title.to_lowercase().cmp(&b.title.to_lowercase());
+    match key {
+        SortKey::Artist => {
+            let album = |s: &Song| s.album.as_deref().map(str::to_lowercase);
+            let track = |s: &Song| (s.track_number.is_none(), s.track_number);
+            album(a).cmp(&album(b)).then_with(|| track(a).cmp(&track(b))).then_with(title)
+        }
+        SortKey::Title => a.artist.to_lowercase().cmp(&b.artist.to_lowercase()),
+        SortKey::Duration | SortKey::Rating => title(),
+    }
+}
+
+fn is_m3u(file_path: &str) -> bool {
+    std::path::Path::new(file_path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"))
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self::new(AudioManager::new())
+    }
+}
+
+impl Player {
+    pub fn new(audio: AudioManager) -> Self {
+        let (duration_sender, duration_receiver) = unbounded();
+        let (gain_sender, gain_receiver) = unbounded();
+        let (info_sender, info_receiver) = unbounded();
+        let (stream_sender, stream_receiver) = unbounded();
+        let (export_sender, export_receiver) = unbounded();
+        let mut playlists = PlaylistManager::new();
+        let _ = playlists.create_playlist(DEFAULT_PLAYLIST.to_string());
+        Self {
+            audio,
+            playlists,
+            songs: Vec::new(),
+            current_index: None,
+            is_playing: false,
+            is_paused: false,
+            volume: 1.0,
+            muted: false,
+            normalize: false,
+            album_gain: false,
+            total_duration: None,
+            pending_next_time: None,
+            auto_advance_delay: Duration::ZERO,
+            delay_between_albums_only: false,
+            mode: PlaybackMode::Sequential,
+            repeat_mode: RepeatMode::None,
+            shuffle_order: Vec::new(),
+            shuffle_cursor: 0,
+            failed_attempts: 0,
+            gapless_attempted: false,
+            pending_crossfade: None,
+            sleep_deadline: None,
+            stop_after_current: false,
+            queue: VecDeque::new(),
+            loop_a: None,
+            loop_b: None,
+            events: Vec::new(),
+            duration_sender,
+            duration_receiver,
+            probing: 0,
+            gain_sender,
+            gain_receiver,
+            analyzing: HashSet::new(),
+            track_info: None,
+            info_sender,
+            info_receiver,
+            opening_stream: None,
+            stream_sender,
+            stream_receiver,
+            spectrum: None,
+            spectrum_options: (32, 0.7),
+            resume_positions: HashMap::new(),
+            resume_after: None,
+            export_sender,
+            export_receiver,
+            exporting: 0,
+        }
+    }
+
+    // ------------------------------------------------------------------
+    // State
+
+    pub fn songs(&self) -> &[Song] {
+        &self.songs
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current_index
+    }
+
+    pub fn current_song(&self) -> Option<&Song> {
+        self.current_index.and_then(|idx| self.songs.get(idx))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing && !self.is_paused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// True while an internet stream is connecting or waiting for data.
+    pub fn is_buffering(&self) -> bool {
+        self.opening_stream.is_some() || self.audio.is_buffering()
+    }
+
+    /// False for internet streams, which play as they download.
+    pub fn is_seekable(&self) -> bool {
+        self.audio.is_seekable()
+    }
+
+    /// True while a finished track is waiting out the auto-advance delay.
+    pub fn is_waiting(&self) -> bool {
+        self.pending_next_time.is_some()
+    }
+
+    /// The user's chosen volume. While muted this is the level restored on unmute.
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn mode(&self) -> PlaybackMode {
+        self.mode
+    }
+
+    pub fn repeat_mode(&self) -> RepeatMode {
+        self.repeat_mode
+    }
+
+    pub fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    pub fn position(&self) -> Duration {
+        if self.is_waiting() {
+            return self.total_duration.unwrap_or(Duration::ZERO);
+        }
+        if !self.is_playing && !self.is_paused {
+            return Duration::ZERO;
+        }
+        let elapsed = self.audio.get_current_position();
+        match self.total_duration {
+            Some(total) => elapsed.min(total),
+            None => elapsed,
+        }
+    }
+
+    pub fn playlists(&self) -> &PlaylistManager {
+        &self.playlists
+    }
+
+    pub fn playlists_mut(&mut self) -> &mut PlaylistManager {
+        &mut self.playlists
+    }
+
+    /// Up to `limit` songs that have been played, most recent first, across every
+    /// playlist.
+    pub fn recently_played(&self, limit: usize) -> Vec<&Song> {
+        let working: HashSet<&str> = self.songs.iter().map(|s| s.file_path.as_str()).collect();
+        // The working list holds the latest counts for the current playlist's songs
+        let others = self.playlists.library_songs().filter(|s| !working.contains(s.file_path.as_str()));
+        let mut played: Vec<&Song> = self.songs.iter().filter(|s| s.last_played.is_some()).collect();
+        for song in others.filter(|s| s.last_played.is_some()) {
+            played.push(song);
+        }
+        played.sort_by_key(|s| std::cmp::Reverse(s.last_played));
+        let mut seen = HashSet::new();
+        played.retain(|s| seen.insert(s.file_path.as_str()));
+        played.truncate(limit);
+        played
+    }
+
+    pub fn current_playlist_name(&self) -> Option<&str> {
+        self.playlists.current_playlist_name()
+    }
+
+    /// Playlist names in alphabetical order.
+    pub fn playlist_names(&self) -> Vec<String> {
+        let mut names = self.playlists.get_playlist_names();
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+    }
+
+    /// Adds the songs at `indices` to the playlist `name`, skipping any it already has,
+    /// and returns how many were skipped.
+    pub fn add_songs_to_playlist(&mut self, name: &str, indices: &[usize]) -> anyhow::Result<usize> {
+        let songs: Vec<Song> = indices.iter().filter_map(|&i| self.songs.get(i).cloned()).collect();
+        if self.current_playlist_name() == Some(name) {
+            return Ok(self.add_songs(songs));
+        }
+        self.playlists.add_new_songs_to_playlist(name, songs)
+    }
+
+    /// Makes `name` the current playlist and shows its songs. The song list here is the
+    /// working copy of the current playlist, so it's written back before switching. A
+    /// playing track keeps playing; it stays the current song if the new playlist has it
+    /// too, otherwise playback continues from the top of the new list once it ends.
+    pub fn switch_playlist(&mut self, name: &str) -> anyhow::Result<()> {
+        if self.current_playlist_name() == Some(name) {
+            return Ok(());
+        }
+        self.store_current_playlist();
+        self.show_playlist(name)
+    }
+
+    /// Makes `name` current and loads its songs as the working list, without first
+    /// writing back the old one.
+    fn show_playlist(&mut self, name: &str) -> anyhow::Result<()> {
+        self.playlists.set_current_playlist(name)?;
+        let playing_file = self.current_song().map(|s| s.file_path.clone());
+        self.songs = self.playlists.playlist_songs(name).into_iter().cloned().collect();
+        self.current_index = playing_file.and_then(|path| self.songs.iter().position(|s| s.file_path == path));
+        self.on_songs_changed();
+        self.backfill_durations();
+        Ok(())
+    }
+
+    /// Creates an empty playlist and switches to it.
+    pub fn create_playlist(&mut self, name: &str) -> anyhow::Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("Playlist name can't be empty"));
+        }
+        self.store_current_playlist();
+        let previous = self.current_playlist_name().map(str::to_string);
+        self.playlists.create_playlist(name.to_string())?;
+        // create_playlist already made it current; go through switch_playlist for the rest
+        if let Some(previous) = previous {
+            self.playlists.set_current_playlist(&previous)?;
+        }
+        self.switch_playlist(name)
+    }
+
+    pub fn rename_playlist(&mut self, name: &str, new_name: &str) -> anyhow::Result<()> {
+        self.playlists.rename_playlist(name, new_name)
+    }
+
+    /// Deletes a playlist. Deleting the current one switches to another, and the last
+    /// remaining playlist can't be deleted.
+    pub fn delete_playlist(&mut self, name: &str) -> anyhow::Result<()> {
+        let names = self.playlist_names();
+        if names.len() <= 1 {
+            return Err(anyhow::anyhow!("Can't delete the only playlist"));
+        }
+        if self.current_playlist_name() == Some(name) {
+            let fallback = names.iter().find(|n| n.as_str() != name).cloned().unwrap_or_default();
+            self.switch_playlist(&fallback)?;
+        }
+        self.playlists.delete_playlist(name)
+    }
+
+    /// Saves the current playlist to `file_path`: M3U if the extension says so,
+    /// otherwise the player's own JSON format.
+    pub fn save_playlist(&mut self, file_path: &str) -> anyhow::Result<()> {
+        self.store_current_playlist();
+        let name = self
+            .current_playlist_name()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("No current playlist selected"))?;
+        if is_m3u(file_path) {
+            self.playlists.export_m3u(&name, file_path)
+        } else {
+            self.playlists.save_playlist(&name, file_path)
+        }
+    }
+
+    /// Loads a playlist file (JSON or M3U) and switches to it. It gets a numbered name
+    /// if one is already taken, so existing playlists are never replaced.
+    pub fn load_playlist(&mut self, file_path: &str) -> anyhow::Result<()> {
+        self.store_current_playlist();
+        let name = if is_m3u(file_path) {
+            self.playlists.import_m3u(file_path)?
+        } else {
+            self.playlists.load_playlist(file_path)?
+        };
+        self.show_playlist(&name)
+    }
+
+    /// Saves playlists with paths relative to `root` (see `PlaylistManager::set_library_root`).
+    pub fn set_library_root(&mut self, root: Option<PathBuf>) {
+        self.playlists.set_library_root(root);
+    }
+
+    /// Writes the working song list back into the current playlist.
+    fn store_current_playlist(&mut self) {
+        if let Some(name) = self.current_playlist_name().map(str::to_string) {
+            let _ = self.playlists.set_playlist_songs(&name, self.songs.clone());
+        }
+    }
+
+    /// Returns and clears the events raised since the last call.
+    pub fn take_events(&mut self) -> Vec<PlayerEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // ------------------------------------------------------------------
+    // Song list
+
+    /// Adds `song` unless the playlist already has a song for the same file, compared
+    /// by canonical path like library IDs. Returns whether it was added.
+    pub fn add_song(&mut self, mut song: Song) -> bool {
+        let id = canonical_path(&song.file_path);
+        if self.songs.iter().any(|s| canonical_path(&s.file_path) == id) {
+            info!("Skipped duplicate {}", song.file_path);
+            return false;
+        }
+        self.carry_over_rating(&mut song);
+        self.songs.push(song);
+        self.on_songs_changed();
+        true
+    }
+
+    /// Adds every song whose file isn't in the playlist yet and returns how many were
+    /// skipped as duplicates. Files are compared by canonical path, so `./a.mp3` and an
+    /// absolute path to it are one song. Songs added without a duration (e.g. a folder
+    /// scan that couldn't read one from the tags) have it probed in the background.
+    pub fn add_songs(&mut self, songs: Vec<Song>) -> usize {
+        let mut known: HashSet<String> = self.songs.iter().map(|s| canonical_path(&s.file_path)).collect();
+        let total = songs.len();
+        let mut songs: Vec<Song> = songs.into_iter().filter(|s| known.insert(canonical_path(&s.file_path))).collect();
+        let skipped = total - songs.len();
+        if skipped > 0 {
+            info!("Skipped {} duplicate songs", skipped);
+        }
+        if songs.is_empty() {
+            return skipped;
+        }
+        for song in &mut songs {
+            self.carry_over_rating(song);
+        }
+        let missing = songs.iter().filter(|s| s.duration.is_none()).map(|s| s.file_path.clone()).collect();
+        self.songs.extend(songs);
+        self.on_songs_changed();
+        self.probe_durations(missing);
+        skipped
+    }
+
+    /// Gives a freshly scanned song the rating and favorite flag the library already
+    /// has for its file, so re-adding a folder doesn't lose them.
+    fn carry_over_rating(&self, song: &mut Song) {
+        if song.rating > 0 || song.favorite {
+            return;
+        }
+        if let Some(known) = self.playlists.library_songs().find(|s| s.file_path == song.file_path) {
+            song.rating = known.rating;
+            song.favorite = known.favorite;
+        }
+    }
+
+    /// Probes, on a background thread, the duration of every song that doesn't have one
+    /// yet. Results are filled in by `update` as they arrive.
+    pub fn backfill_durations(&mut self) {
+        let missing = self.songs.iter().filter(|s| s.duration.is_none()).map(|s| s.file_path.clone()).collect();
+        self.probe_durations(missing);
+    }
+
+    /// Removes the songs at `indices`. The current song keeps playing, but loses its
+    /// selection if it was one of the removed entries.
+    pub fn remove_songs(&mut self, indices: &[usize]) {
+        if indices.is_empty() {
+            return;
+        }
+        // Keep pointing at the current song (and so at the front of a reshuffled order)
+        self.current_index = self
+            .current_index
+            .filter(|idx| !indices.contains(idx))
+            .map(|idx| idx - indices.iter().filter(|&&i| i < idx).count());
+        let mut i = 0;
+        self.songs.retain(|_| {
+            let keep = !indices.contains(&i);
+            i += 1;
+            keep
+        });
+        self.on_songs_changed();
+    }
+
+    /// Removes songs listed more than once in the current playlist, keeping the first
+    /// copy (see `Playlist::dedup_by_path`), and returns how many were removed. If the
+    /// playing song was a later copy, the first copy becomes the current song.
+    pub fn remove_duplicates(&mut self) -> usize {
+        self.store_current_playlist();
+        let Some(playlist) = self.playlists.get_current_playlist_mut() else { return 0 };
+        let removed = playlist.dedup_by_path();
+        if removed.is_empty() {
+            return 0;
+        }
+        let current = self.current_song().map(|s| canonical_path(&s.file_path));
+        let mut i = 0;
+        self.songs.retain(|_| {
+            let keep = !removed.contains(&i);
+            i += 1;
+            keep
+        });
+        self.current_index = current.and_then(|id| self.songs.iter().position(|s| canonical_path(&s.file_path) == id));
+        self.on_songs_changed();
+        removed.len()
+    }
+
+    /// Replaces the title, artist and album shown for the song at `index`. Only the
+    /// library entry changes; see `tags::write_tags` for updating the file itself.
+    pub fn update_song_metadata(&mut self, index: usize, title: String, artist: String, album: Option<String>) {
+        if let Some(song) = self.songs.get_mut(index) {
+            song.title = title;
+            song.artist = artist;
+            song.album = album;
+        }
+    }
+
+    /// Sets the star rating (0 to 5, 0 meaning unrated) of the song at `index`.
+    pub fn set_rating(&mut self, index: usize, rating: u8) {
+        if let Some(song) = self.songs.get_mut(index) {
+            song.rating = rating.min(5);
+        }
+    }
+
+    pub fn toggle_favorite(&mut self, index: usize) {
+        if let Some(song) = self.songs.get_mut(index) {
+            song.favorite = !song.favorite;
+        }
+    }
+
+    pub fn set_favorite(&mut self, index: usize, favorite: bool) {
+        if let Some(song) = self.songs.get_mut(index) {
+            song.favorite = favorite;
+        }
+    }
+
+    /// Moves the song at `from` so it ends up at index `to`. The current song, the
+    /// shuffle order and playback are left alone, just re-pointed at the new indices.
+    pub fn move_song(&mut self, from: usize, to: usize) {
+        if from >= self.songs.len() || to >= self.songs.len() || from == to {
+            return;
+        }
+        let song = self.songs.remove(from);
+        self.songs.insert(to, song);
+        let remap = |i: usize| {
+            if i == from {
+                to
+            } else if from < i && i <= to {
+                i - 1
+            } else if to <= i && i < from {
+                i + 1
+            } else {
+                i
+            }
+        };
+        self.current_index = self.current_index.map(remap);
+        for i in &mut self.shuffle_order {
+            *i = remap(*i);
+        }
+    }
+
+    /// Stable-sorts the song list. Titles and artists compare case-insensitively and songs
+    /// without a known duration sort last. The current song and shuffle order follow
+    /// their songs to the new positions. Returns each old index's new index so callers
+    /// can remap their own selections.
+    pub fn sort_songs(&mut self, sort: PlaylistSort) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.songs.len()).collect();
+        let songs = &self.songs;
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&songs[a], &songs[b]);
+            let ordering = match sort.key {
+                SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+                SortKey::Artist => a.artist.to_lowercase().cmp(&b.artist.to_lowercase()),
+                SortKey::Duration => match (a.duration, b.duration) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                },
+                SortKey::Rating => a.rating.cmp(&b.rating),
+            };
+            // Unknown durations stay at the end in either direction
+            let unknown = sort.key == SortKey::Duration && (a.duration.is_none() || b.duration.is_none());
+            let ordering = if sort.ascending || unknown { ordering } else { ordering.reverse() };
+            ordering.then_with(|| sort_tiebreak(a, b, sort.key))
+        });
+
+        let mut new_index = vec![0; order.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new;
+        }
+        let mut old_songs: Vec<Option<Song>> = std::mem::take(&mut self.songs).into_iter().map(Some).collect();
+        self.songs = order.iter().map(|&old| old_songs[old].take().unwrap()).collect();
+        self.current_index = self.current_index.map(|i| new_index[i]);
+        for i in &mut self.shuffle_order {
+            *i = new_index[*i];
+        }
+        info!("Sorted {} songs by {:?}", self.songs.len(), sort.key);
+        new_index
+    }
+
+    /// Swaps in a whole new song list, e.g. to undo an edit. The current song keeps
+    /// playing and stays current if it's in the new list.
+    pub fn replace_songs(&mut self, songs: Vec<Song>) {
+        let current = self.current_song().map(|s| s.file_path.clone());
+        self.songs = songs;
+        self.current_index = current.and_then(|path| self.songs.iter().position(|s| s.file_path == path));
+        self.on_songs_changed();
+    }
+
+    pub fn clear(&mut self) {
+        self.songs.clear();
+        self.current_index = None;
+        self.on_songs_changed();
+    }
+
+    /// Selects a song without starting it.
+    pub fn select(&mut self, index: Option<usize>) {
+        self.current_index = index.filter(|&i| i < self.songs.len());
+        self.sync_shuffle_cursor();
+    }
+
+    // ------------------------------------------------------------------
+    // Play queue
+
+    /// The songs waiting in the play queue, in the order they'll play.
+    pub fn queued_songs(&self) -> Vec<&Song> {
+        self.queue.iter().filter_map(|path| self.songs.iter().find(|s| &s.file_path == path)).collect()
+    }
+
+    /// Adds the song at `index` to the end of the queue.
+    pub fn add_to_queue(&mut self, index: usize) {
+        if let Some(song) = self.songs.get(index) {
+            self.queue.push_back(song.file_path.clone());
+        }
+    }
+
+    /// Puts the song at `index` at the front of the queue so it plays after the current one.
+    pub fn play_next(&mut self, index: usize) {
+        if let Some(song) = self.songs.get(index) {
+            self.queue.push_front(song.file_path.clone());
+        }
+    }
+
+    /// Removes the entry at `position` in the queue.
+    pub fn remove_from_queue(&mut self, position: usize) {
+        self.queue.remove(position);
+    }
+
+    /// Moves the queue entry at `from` to `to`.
+    pub fn move_in_queue(&mut self, from: usize, to: usize) {
+        if from == to || to >= self.queue.len() {
+            return;
+        }
+        if let Some(path) = self.queue.remove(from) {
+            self.queue.insert(to, path);
+        }
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Index of the song at the front of the queue.
+    fn peek_queued(&self) -> Option<usize> {
+        let path = self.queue.front()?;
+        self.songs.iter().position(|s| &s.file_path == path)
+    }
+
+    /// Takes the song at the front of the queue, if any.
+    fn take_queued(&mut self) -> Option<usize> {
+        let idx = self.peek_queued()?;
+        self.queue.pop_front();
+        Some(idx)
+    }
+
+    // ------------------------------------------------------------------
+    // Playback control
+
+    pub fn play_index(&mut self, index: usize) {
+        if index >= self.songs.len() {
+            return;
+        }
+        self.current_index = Some(index);
+        self.sync_shuffle_cursor();
+        self.play_current();
+    }
+
+    /// Starts the selected song, or the first one if nothing is selected.
+    pub fn play(&mut self) {
+        if self.songs.is_empty() {
+            return;
+        }
+        if self.current_index.is_none() {
+            self.select_next_song();
+        }
+        self.play_current();
+    }
+
+    pub fn pause(&mut self) {
+        if self.is_playing() {
+            self.audio.pause();
+            self.is_paused = true;
+            self.is_playing = false;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.is_paused {
+            self.audio.resume();
+            self.is_playing = true;
+            self.is_paused = false;
+        }
+    }
+
+    pub fn toggle_play_pause(&mut self) {
+        if self.is_playing() {
+            self.pause();
+        } else if self.is_paused {
+            self.resume();
+        } else {
+            self.play();
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.remember_resume_position();
+        self.audio.stop();
+        self.opening_stream = None;
+        self.is_playing = false;
+        self.is_paused = false;
+        self.total_duration = None;
+        self.pending_next_time = None;
+        self.clear_loop();
+        self.events.push(PlayerEvent::Stopped);
+    }
+
+    pub fn next(&mut self) {
+        if self.songs.is_empty() {
+            return;
+        }
+        let was_playing = self.is_playing();
+        match self.take_queued() {
+            Some(idx) => self.current_index = Some(idx),
+            None => self.select_next_song(),
+        }
+        // Auto-play the selected song if we were already playing
+        if was_playing {
+            self.play_current();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if self.songs.is_empty() {
+            return;
+        }
+        let was_playing = self.is_playing();
+        self.select_previous_song();
+        // Auto-play the selected song if we were already playing
+        if was_playing {
+            self.play_current();
+        }
+    }
+
+    pub fn seek(&mut self, position: Duration) -> Result<(), PlaybackError> {
+        if !self.is_playing && !self.is_paused {
+            return Ok(());
+        }
+        // Seeking rebuilds the sink, dropping anything queued for gapless playback
+        self.gapless_attempted = false;
+        self.audio.seek(position)
+    }
+
+    /// Moves output to another device (`None` for the system default) without
+    /// interrupting the current track.
+    pub fn set_output_device(&mut self, name: Option<&str>) -> Result<(), PlaybackError> {
+        // The track is re-opened on the new device, dropping anything queued
+        self.gapless_attempted = false;
+        self.audio.set_output_device(name)
+    }
+
+    pub fn output_device(&self) -> Option<&str> {
+        self.audio.output_device()
+    }
+
+    /// False while no audio device could be opened. Playlists can still be browsed and
+    /// edited, but nothing plays until a device turns up.
+    pub fn has_audio_output(&self) -> bool {
+        self.audio.has_output()
+    }
+
+    /// Marks the current position as the start of the A-B loop. A B point that would no
+    /// longer come after it is dropped.
+    pub fn set_loop_a(&mut self) {
+        self.set_loop_a_at(self.position());
+    }
+
+    /// Like `set_loop_a`, at `position` instead of the current position.
+    pub fn set_loop_a_at(&mut self, position: Duration) {
+        if self.loop_b.is_some_and(|b| b <= position) {
+            self.loop_b = None;
+        }
+        self.loop_a = Some(position);
+    }
+
+    /// Marks the current position as the end of the A-B loop. Ignored unless it comes
+    /// after A.
+    pub fn set_loop_b(&mut self) {
+        self.set_loop_b_at(self.position());
+    }
+
+    /// Like `set_loop_b`, at `position` instead of the current position.
+    pub fn set_loop_b_at(&mut self, position: Duration) {
+        if self.loop_a.is_some_and(|a| position > a) {
+            self.loop_b = Some(position);
+        }
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_a = None;
+        self.loop_b = None;
+    }
+
+    pub fn loop_points(&self) -> (Option<Duration>, Option<Duration>) {
+        (self.loop_a, self.loop_b)
+    }
+
+    /// The loop region, once both points are set.
+    fn active_loop(&self) -> Option<(Duration, Duration)> {
+        match (self.loop_a, self.loop_b) {
+            (Some(a), Some(b)) if a < b => Some((a, b)),
+            _ => None,
+        }
+    }
+
+    /// Changing the volume while muted also unmutes.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+        self.muted = false;
+        self.audio.set_volume(self.output_volume());
+    }
+
+    /// Silences the output without forgetting the volume, so unmuting restores it.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.audio.set_volume(self.output_volume());
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.set_muted(!self.muted);
+    }
+
+    /// Turns loudness normalization on or off. Songs without a ReplayGain tag are
+    /// analysed in the background the first time they play with it on.
+    pub fn set_normalize(&mut self, normalize: bool) {
+        self.normalize = normalize;
+        if normalize {
+            self.analyze_current_gain();
+        }
+        self.audio.set_volume(self.output_volume());
+    }
+
+    pub fn normalize(&self) -> bool {
+        self.normalize
+    }
+
+    /// While normalizing, uses album gain where a song is tagged with it, so an album
+    /// keeps its own loud and quiet tracks.
+    pub fn set_album_gain(&mut self, album_gain: bool) {
+        self.album_gain = album_gain;
+        self.audio.set_volume(self.output_volume());
+    }
+
+    /// The normalization gain in dB applied to `song`, if normalization is on and
+    /// one is known.
+    fn normalization_gain(&self, song: &Song) -> Option<f32> {
+        if !self.normalize {
+            return None;
+        }
+        match song.album_gain_db {
+            Some(gain) if self.album_gain => Some(gain),
+            _ => song.replay_gain_db,
+        }
+    }
+
+    /// Equalizer band gains in dB, one per entry of `EQ_BANDS`.
+    pub fn set_eq_gains(&mut self, gains: &[f32]) {
+        self.audio.set_eq_gains(gains);
+    }
+
+    pub fn eq_gains(&self) -> Vec<f32> {
+        self.audio.eq_gains()
+    }
+
+    pub fn set_eq_enabled(&mut self, enabled: bool) {
+        self.audio.set_eq_enabled(enabled);
+    }
+
+    pub fn eq_enabled(&self) -> bool {
+        self.audio.eq_enabled()
+    }
+
+    /// Bass and treble tone controls, in dB.
+    pub fn set_bass(&mut self, gain_db: f32) {
+        self.audio.set_bass(gain_db);
+    }
+
+    pub fn bass(&self) -> f32 {
+        self.audio.bass()
+    }
+
+    pub fn set_treble(&mut self, gain_db: f32) {
+        self.audio.set_treble(gain_db);
+    }
+
+    pub fn treble(&self) -> f32 {
+        self.audio.treble()
+    }
+
+    /// Left-right balance from -1.0 (full left) to 1.0 (full right).
+    pub fn set_balance(&mut self, balance: f32) {
+        self.audio.set_balance(balance);
+    }
+
+    pub fn balance(&self) -> f32 {
+        self.audio.balance()
+    }
+
+    pub fn set_mono(&mut self, mono: bool) {
+        self.audio.set_mono(mono);
+    }
+
+    /// Playback speed, 1.0 being normal. Positions and durations stay in track time.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.audio.set_speed(speed);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.audio.speed()
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.audio.is_mono()
+    }
+
+    /// Starts or stops the spectrum analyzer. While it's off the playing source isn't
+    /// tapped at all.
+    pub fn set_spectrum_enabled(&mut self, enabled: bool) {
+        if enabled == self.spectrum.is_some() {
+            return;
+        }
+        let tap = self.audio.spectrum_tap();
+        tap.set_enabled(enabled);
+        self.spectrum = enabled.then(|| {
+            let (bar_count, smoothing) = self.spectrum_options;
+            SpectrumAnalyzer::start(tap, bar_count, smoothing)
+        });
+    }
+
+    pub fn spectrum_enabled(&self) -> bool {
+        self.spectrum.is_some()
+    }
+
+    /// Number of spectrum bars, and how slowly they fall back (0.0 drops instantly).
+    pub fn set_spectrum_options(&mut self, bar_count: usize, smoothing: f32) {
+        self.spectrum_options = (bar_count, smoothing);
+        if let Some(spectrum) = &self.spectrum {
+            spectrum.set_options(bar_count, smoothing);
+        }
+    }
+
+    /// Current bar levels in `0.0..=1.0`, lowest frequency first. Empty while the
+    /// analyzer is off.
+    pub fn spectrum_bars(&self) -> Vec<f32> {
+        self.spectrum.as_ref().map(|s| s.bars()).unwrap_or_default()
+    }
+
+    /// Starts or stops measuring output levels. While it's off the playing source isn't
+    /// measured at all.
+    pub fn set_meter_enabled(&mut self, enabled: bool) {
+        self.audio.level_meter().set_enabled(enabled);
+    }
+
+    pub fn meter_enabled(&self) -> bool {
+        self.audio.level_meter().is_enabled()
+    }
+
+    /// Current output levels; zero while paused, stopped or with the meter off.
+    pub fn output_levels(&self) -> Levels {
+        if !self.is_playing() {
+            return Levels::default();
+        }
+        self.audio.level_meter().levels()
+    }
+
+    pub fn set_mode(&mut self, mode: PlaybackMode) {
+        if self.mode == mode {
+            return;
+        }
+        self.mode = mode;
+        if mode == PlaybackMode::Shuffle {
+            self.reshuffle(self.current_index);
+        } else {
+            // Sequential order simply resumes from the current song
+            self.shuffle_order.clear();
+            self.shuffle_cursor = 0;
+        }
+    }
+
+    /// Switches between shuffled and sequential order. Sequential play carries on from
+    /// the current song.
+    pub fn toggle_shuffle(&mut self) {
+        let mode = match self.mode {
+            PlaybackMode::Sequential => PlaybackMode::Shuffle,
+            PlaybackMode::Shuffle => PlaybackMode::Sequential,
+        };
+        self.set_mode(mode);
+    }
+
+    pub fn set_repeat_mode(&mut self, repeat_mode: RepeatMode) {
+        self.repeat_mode = repeat_mode;
+        info!("Repeat mode: {:?}", repeat_mode);
+    }
+
+    pub fn set_auto_advance_delay(&mut self, delay: Duration) {
+        self.auto_advance_delay = delay;
+    }
+
+    /// Whether the auto-advance delay only applies when the next song starts a
+    /// different album, so albums still play through without gaps.
+    pub fn set_delay_between_albums_only(&mut self, enabled: bool) {
+        self.delay_between_albums_only = enabled;
+    }
+
+    /// The pause before the next song. Songs without an album always get the delay.
+    fn advance_delay(&self) -> Duration {
+        let same_album = || {
+            let current = self.current_song()?.album.as_ref()?;
+            let next = self.songs.get(self.peek_next_index()?)?.album.as_ref()?;
+            Some(current == next)
+        };
+        if self.delay_between_albums_only && same_album().unwrap_or(false) {
+            Duration::ZERO
+        } else {
+            self.auto_advance_delay
+        }
+    }
+
+    /// Overlap between consecutive tracks when advancing automatically. While non-zero
+    /// the auto-advance delay doesn't apply; zero goes back to the plain cut.
+    pub fn set_crossfade(&mut self, duration: Duration) {
+        self.audio.set_crossfade(duration);
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        self.audio.crossfade()
+    }
+
+    /// Stops playback after `duration`, fading out over the last `SLEEP_FADE`. `None`
+    /// cancels a running timer.
+    pub fn set_sleep_timer(&mut self, duration: Option<Duration>) {
+        self.sleep_deadline = duration.map(|d| Instant::now() + d);
+        match duration {
+            Some(d) => info!("Sleep timer set for {} min", d.as_secs() / 60),
+            None => info!("Sleep timer cancelled"),
+        }
+        self.audio.set_volume(self.output_volume());
+    }
+
+    /// Stops playback when the current track ends, instead of moving on to the next
+    /// one. Applies once, then switches itself off.
+    pub fn set_stop_after_current(&mut self, enabled: bool) {
+        self.stop_after_current = enabled;
+        if enabled {
+            if let Err(e) = self.audio.cancel_queued() {
+                warn!("Could not take back the queued track: {}", e);
+            }
+        }
+        // Lets gapless playback queue the next track again once switched back off
+        self.gapless_attempted = false;
+    }
+
+    pub fn stop_after_current(&self) -> bool {
+        self.stop_after_current
+    }
+
+    /// Time left on the sleep timer, if one is running.
+    pub fn sleep_remaining(&self) -> Option<Duration> {
+        self.sleep_deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Length of the short fades on play, pause, resume and stop.
+    pub fn set_fade(&mut self, duration: Duration) {
+        self.audio.set_fade(duration);
+    }
+
+    pub fn fade(&self) -> Duration {
+        self.audio.fade()
+    }
+
+    pub fn is_crossfading(&self) -> bool {
+        self.audio.is_fading()
+    }
+
+    /// Advances the playback state machine. Frontends call this regularly (every frame
+    /// for the GUI); it detects finished tracks and moves on after the auto-advance delay.
+    pub fn update(&mut self) {
+        self.receive_durations();
+        self.receive_gains();
+        self.receive_track_info();
+        self.receive_streams();
+        self.receive_exports();
+        self.audio.update_fades();
+        if let Some(lost) = self.audio.check_output_device() {
+            self.gapless_attempted = false;
+            self.events.push(PlayerEvent::Error(format!("Output device \"{}\" is no longer available; switched to the default device", lost)));
+        }
+        if let Some(remaining) = self.sleep_remaining() {
+            if remaining.is_zero() {
+                info!("Sleep timer finished");
+                self.sleep_deadline = None;
+                self.pending_next_time = None;
+                self.stop();
+                self.audio.set_volume(self.output_volume());
+                return;
+            }
+            if remaining < SLEEP_FADE {
+                self.audio.set_volume(self.output_volume());
+            }
+        }
+
+        if let Some(since) = self.pending_next_time {
+            if since.elapsed() >= self.advance_delay() {
+                self.pending_next_time = None;
+                self.auto_advance_to_next_song();
+            }
+            return;
+        }
+
+        self.update_prefetch();
+        self.update_gapless();
+
+        if let Some((a, b)) = self.active_loop() {
+            if self.is_playing() && self.audio.get_current_position() >= b {
+                if let Err(e) = self.audio.seek(a) {
+                    warn!("Could not jump back to loop start: {}", e);
+                    self.clear_loop();
+                }
+                return;
+            }
+        }
+
+        if self.is_playing() && self.audio.is_finished() {
+            if std::mem::take(&mut self.stop_after_current) {
+                info!("Stopped after the current track");
+                self.stop();
+                return;
+            }
+            self.pending_next_time = Some(Instant::now());
+        } else if let Some(length) = self.crossfade_window() {
+            self.pending_crossfade = Some(length);
+            self.auto_advance_to_next_song();
+            self.pending_crossfade = None;
+        }
+    }
+
+    // ------------------------------------------------------------------
+    // Session
+
+    pub fn session_snapshot(&self) -> Session {
+        let has_track = self.is_playing || self.is_paused;
+        let current = self.current_playlist_name();
+        let playlists = self
+            .playlist_names()
+            .into_iter()
+            .map(|name| {
+                // The current playlist's latest contents are the working list
+                let songs = if Some(name.as_str()) == current {
+                    self.songs.clone()
+                } else {
+                    self.playlists.playlist_songs(&name).into_iter().cloned().collect()
+                };
+                SavedPlaylist { name, songs }
+            })
+            .collect();
+        let mut resume_positions = self.resume_positions.clone();
+        if let Some((file_path, Some(position))) = self.current_resume_position() {
+            resume_positions.insert(file_path, position);
+        }
+        Session {
+            songs: self.songs.clone(),
+            playlists,
+            current_playlist: current.map(str::to_string),
+            selected_song_index: self.current_index,
+            position: if has_track { Some(self.position()) } else { None },
+            was_playing: self.is_playing(),
+            volume: Some(self.volume),
+            muted: self.muted,
+            mode: self.mode,
+            repeat_mode: self.repeat_mode,
+            queue: self.queue.iter().cloned().collect(),
+            resume_positions,
+        }
+    }
+
+    /// Reloads the previous session's song list, selection, volume, mute,
+    /// shuffle/repeat modes and queue, leaving out songs whose files are gone. If a track was
+    /// loaded it is reopened at the saved position, paused unless `resume_playing` is
+    /// set and it was playing on exit. A track that can't be reopened simply leaves the
+    /// player stopped.
+    pub fn restore_session(&mut self, mut session: Session, resume_playing: bool) {
+        session.drop_missing_files();
+        self.restore_playlists(&session);
+        self.songs = session.songs;
+        self.current_index = session.selected_song_index.filter(|&i| i < self.songs.len());
+        self.on_songs_changed();
+        self.backfill_durations();
+        if let Some(volume) = session.volume {
+            self.set_volume(volume);
+        }
+        self.set_muted(session.muted);
+        self.set_mode(session.mode);
+        self.set_repeat_mode(session.repeat_mode);
+        self.resume_positions = session.resume_positions;
+        let songs = &self.songs;
+        self.queue = session.queue.into_iter().filter(|path| songs.iter().any(|s| &s.file_path == path)).collect();
+
+        let (idx, position) = match (self.current_index, session.position) {
+            (Some(idx), Some(position)) => (idx, position),
+            _ => return,
+        };
+        let resume_playing = session.was_playing && resume_playing;
+        let song = &self.songs[idx];
+        // Reopened at the session's position below; from here on it's the current track
+        self.resume_positions.remove(&song.file_path);
+        if let Err(e) = self.audio.play_file(&song.file_path, song.duration.map(Duration::from_secs_f64)) {
+            warn!("Could not restore '{}': {}", song.title, e);
+            return;
+        }
+        if !resume_playing {
+            self.audio.pause();
+        }
+        if let Err(e) = self.audio.seek(position) {
+            warn!("Could not restore position in '{}': {}", song.title, e);
+        }
+        self.audio.set_volume(self.output_volume());
+        self.is_playing = resume_playing;
+        self.is_paused = !resume_playing;
+        self.total_duration = self.audio.get_total_duration();
+        self.remember_duration(idx);
+        self.probe_current_info();
+    }
+
+    /// Rebuilds the playlists from a session. Older sessions only have the one song list,
+    /// which becomes the default playlist.
+    fn restore_playlists(&mut self, session: &Session) {
+        if session.playlists.is_empty() {
+            let _ = self.playlists.set_playlist_songs(DEFAULT_PLAYLIST, session.songs.clone());
+            return;
+        }
+        let root = self.playlists.library_root().map(Path::to_path_buf);
+        self.playlists = PlaylistManager::new();
+        self.playlists.set_library_root(root);
+        for saved in &session.playlists {
+            if self.playlists.create_playlist(saved.name.clone()).is_ok() {
+                let _ = self.playlists.set_playlist_songs(&saved.name, saved.songs.clone());
+            }
+        }
+        let current = session
+            .current_playlist
+            .clone()
+            .filter(|name| self.playlists.get_playlist_names().contains(name))
+            .or_else(|| self.playlist_names().into_iter().next());
+        if let Some(name) = current {
+            let _ = self.playlists.set_current_playlist(&name);
+        }
+    }
+
+    // ------------------------------------------------------------------
+    // Internals
+
+    /// If the current track is close enough to its end to start crossfading into the
+    /// next one, returns how long the fade should take: the crossfade setting, clamped
+    /// to the time left and to half the track so short tracks aren't swallowed whole.
+    fn crossfade_window(&self) -> Option<Duration> {
+        let crossfade = self.audio.crossfade();
+        if crossfade.is_zero() || self.stop_after_current || !self.is_playing() || !self.has_next_track() || self.active_loop().is_some() {
+            return None;
+        }
+        let total = self.total_duration?;
+        // The fade runs in real time, which away from normal speed isn't track time
+        let speed = self.audio.speed();
+        let remaining = total.saturating_sub(self.audio.get_current_position()).div_f32(speed);
+        if remaining <= crossfade.min(total.div_f32(2.0 * speed)) {
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+
+    /// Whether auto-advance would start another track rather than stop.
+    fn has_next_track(&self) -> bool {
+        let Some(idx) = self.current_index else { return false };
+        if self.peek_queued().is_some() {
+            return true;
+        }
+        match (self.repeat_mode, self.mode) {
+            (RepeatMode::One | RepeatMode::All, _) => true,
+            (RepeatMode::None, PlaybackMode::Shuffle) => self.shuffle_cursor + 1 < self.shuffle_order.len(),
+            (RepeatMode::None, PlaybackMode::Sequential) => idx + 1 < self.songs.len(),
+        }
+    }
+
+    /// Caches the duration play_file probed so the next play of this song skips it.
+    fn remember_duration(&mut self, idx: usize) {
+        if self.songs[idx].duration.is_none() {
+            self.songs[idx].duration = self.total_duration.map(|d| d.as_secs_f64());
+        }
+    }
+
+    fn probe_durations(&mut self, paths: Vec<String>) {
+        if paths.is_empty() {
+            return;
+        }
+        self.probing += paths.len();
+        let sender = self.duration_sender.clone();
+        thread::spawn(move || {
+            for path in paths {
+                let duration = AudioManager::probe_duration(&path);
+                if sender.send((path, duration)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Starts loudness analysis of the current song if normalization needs it.
+    fn analyze_current_gain(&mut self) {
+        let Some(song) = self.current_song() else { return };
+        if self.normalization_gain(song).is_some() || !self.normalize || is_stream_url(&song.file_path) {
+            return;
+        }
+        let path = song.file_path.clone();
+        if !self.analyzing.insert(path.clone()) {
+            return;
+        }
+        let sender = self.gain_sender.clone();
+        thread::spawn(move || {
+            let gain = analyze_track_gain(&path);
+            let _ = sender.send((path, gain));
+        });
+    }
+
+    /// Reads the current song's format details on a background thread, for
+    /// `current_track_info`.
+    fn probe_current_info(&mut self) {
+        let Some(song) = self.current_song() else { return };
+        let path = song.file_path.clone();
+        if is_stream_url(&path) || self.track_info.as_ref().is_some_and(|(p, _)| *p == path) {
+            return;
+        }
+        let sender = self.info_sender.clone();
+        thread::spawn(move || {
+            let info = AudioManager::probe_track_info(&path);
+            let _ = sender.send((path, info));
+        });
+    }
+
+    fn receive_track_info(&mut self) {
+        while let Ok((path, info)) = self.info_receiver.try_recv() {
+            if let Some(info) = info {
+                self.track_info = Some((path, info));
+            }
+        }
+    }
+
+    /// Codec, sample rate, channels and bitrate of the current song, once they have
+    /// been read. `None` for streams.
+    pub fn current_track_info(&self) -> Option<&TrackInfo> {
+        let song = self.current_song()?;
+        self.track_info.as_ref().filter(|(path, _)| *path == song.file_path).map(|(_, info)| info)
+    }
+
+    fn receive_gains(&mut self) {
+        let mut changed = false;
+        while let Ok((path, gain)) = self.gain_receiver.try_recv() {
+            self.analyzing.remove(&path);
+            let Some(gain) = gain else { continue };
+            info!("Measured gain {:+.1} dB for {}", gain, path);
+            for song in self.songs.iter_mut().filter(|s| s.file_path == path) {
+                song.replay_gain_db = Some(gain);
+            }
+            changed = true;
+        }
+        if changed {
+            self.audio.set_volume(self.output_volume());
+        }
+    }
+
+    fn receive_durations(&mut self) {
+        while let Ok((path, duration)) = self.duration_receiver.try_recv() {
+            self.probing = self.probing.saturating_sub(1);
+            let Some(duration) = duration else { continue };
+            for song in self.songs.iter_mut().filter(|s| s.file_path == path && s.duration.is_none()) {
+                song.duration = Some(duration.as_secs_f64());
+            }
+            self.audio.set_duration(&path, duration);
+            if self.current_song().is_some_and(|s| s.file_path == path) {
+                self.total_duration = self.audio.get_total_duration();
+            }
+        }
+    }
+
+    /// Writes the songs at `indices`, one after another, to a WAV file at `output` in the
+    /// background. With `bake_effects` the current EQ, tone, balance and mono settings are
+    /// applied, along with each song's normalization gain while normalization is on.
+    /// Reports back through `PlayerEvent::Exported` or `PlayerEvent::Error`.
+    pub fn export_wav(&mut self, indices: &[usize], output: String, bake_effects: bool) {
+        let tracks: Vec<RenderTrack> = indices
+            .iter()
+            .filter_map(|&i| self.songs.get(i))
+            .filter(|song| !is_stream_url(&song.file_path))
+            .map(|song| RenderTrack {
+                file_path: song.file_path.clone(),
+                gain: match self.normalization_gain(song) {
+                    Some(gain) if bake_effects => gain_factor(gain),
+                    _ => 1.0,
+                },
+            })
+            .collect();
+        if tracks.is_empty() {
+            self.events.push(PlayerEvent::Error("Nothing to export: internet streams can't be saved as WAV".to_string()));
+            return;
+        }
+        let effects = bake_effects.then(|| self.audio.render_effects());
+        let sender = self.export_sender.clone();
+        self.exporting += 1;
+        thread::spawn(move || {
+            let result = render_wav(&tracks, &output, effects.as_ref());
+            let _ = sender.send((output, result));
+        });
+    }
+
+    /// How many songs are still having their length read in the background.
+    pub fn probing_durations(&self) -> usize {
+        self.probing
+    }
+
+    /// True while a WAV export is being written.
+    pub fn is_exporting(&self) -> bool {
+        self.exporting > 0
+    }
+
+    fn receive_exports(&mut self) {
+        while let Ok((output, result)) = self.export_receiver.try_recv() {
+            self.exporting = self.exporting.saturating_sub(1);
+            match result {
+                Ok(()) => self.events.push(PlayerEvent::Exported(output)),
+                Err(e) => {
+                    warn!("Export to {} failed: {}", output, e);
+                    // A half-written file is no use to anyone
+                    let _ = std::fs::remove_file(&output);
+                    self.events.push(PlayerEvent::Error(format!("Could not export to {}: {}", output, e)));
+                }
+            }
+        }
+    }
+
+    /// Volume actually sent to the output: the user's volume scaled by the current
+    /// song's normalization gain, zero while muted, and ramping down as the sleep timer
+    /// runs out.
+    fn output_volume(&self) -> f32 {
+        if self.muted {
+            return 0.0;
+        }
+        let mut volume = self.volume;
+        if let Some(gain) = self.current_song().and_then(|s| self.normalization_gain(s)) {
+            volume *= gain_factor(gain);
+        }
+        match self.sleep_remaining() {
+            Some(remaining) if remaining < SLEEP_FADE => volume * remaining.as_secs_f32() / SLEEP_FADE.as_secs_f32(),
+            _ => volume,
+        }
+    }
+
+    fn play_current(&mut self) {
+        let idx = match self.current_index {
+            Some(idx) => idx,
+            None => return,
+        };
+        if self.audio.current_file() != Some(&self.songs[idx].file_path) {
+            self.remember_resume_position();
+        }
+        self.pending_next_time = None;
+        self.clear_loop();
+        self.opening_stream = None;
+        // New sinks start at this level, so the song's own normalization gain applies
+        // from its first sample rather than after it has started
+        self.audio.set_volume(self.output_volume());
+        let song = &self.songs[idx];
+        if is_stream_url(&song.file_path) {
+            self.open_stream(song.file_path.clone());
+            return;
+        }
+        let known_duration = song.duration.map(Duration::from_secs_f64);
+        let result = match self.pending_crossfade.take() {
+            Some(length) => self.audio.crossfade_to(&song.file_path, known_duration, length),
+            None => self.audio.play_file(&song.file_path, known_duration),
+        };
+        match result {
+            Ok(()) => {
+                if known_duration.is_none() {
+                    // The total time fills in once `receive_durations` gets it
+                    self.probe_durations(vec![self.songs[idx].file_path.clone()]);
+                }
+                self.on_track_started(idx);
+            }
+            Err(e) => {
+                self.is_playing = false;
+                self.is_paused = false;
+                self.handle_playback_error(idx, e);
+            }
+        }
+    }
+
+    /// Connects to an internet stream on a worker thread, since that can take a while.
+    /// `receive_streams` starts playback once it's ready.
+    fn open_stream(&mut self, url: String) {
+        self.audio.stop();
+        self.pending_crossfade = None;
+        self.is_playing = false;
+        self.is_paused = false;
+        self.total_duration = None;
+        info!("Opening stream {}", url);
+        self.opening_stream = Some(url.clone());
+        let sender = self.stream_sender.clone();
+        thread::spawn(move || {
+            let result = AudioManager::open_url(&url);
+            let _ = sender.send((url, result));
+        });
+    }
+
+    fn receive_streams(&mut self) {
+        while let Ok((url, result)) = self.stream_receiver.try_recv() {
+            // Anything else was superseded while it was connecting
+            if self.opening_stream.as_deref() != Some(url.as_str()) {
+                continue;
+            }
+            self.opening_stream = None;
+            let Some(idx) = self.current_index.filter(|&i| self.songs[i].file_path == url) else { continue };
+            match result.and_then(|(decoder, status)| self.audio.play_stream(&url, decoder, status)) {
+                Ok(()) => self.on_track_started(idx),
+                Err(e) => self.handle_playback_error(idx, e),
+            }
+        }
+    }
+
+    /// Bookkeeping once the audio engine has started the song at `idx`, whether from
+    /// `play_current` or a gapless switch.
+    fn on_track_started(&mut self, idx: usize) {
+        self.gapless_attempted = false;
+        self.audio.set_volume(self.output_volume());
+        self.is_playing = true;
+        self.is_paused = false;
+        self.total_duration = self.audio.get_total_duration();
+        self.remember_duration(idx);
+        self.analyze_current_gain();
+        self.probe_current_info();
+        let song = &mut self.songs[idx];
+        song.play_count += 1;
+        song.last_played = Some(chrono::Utc::now());
+        self.failed_attempts = 0;
+        self.events.push(PlayerEvent::TrackStarted(idx));
+        self.resume_saved_position(idx);
+    }
+
+    /// Turns on remembering where tracks were left off, for positions past `after`
+    /// (`None` turns it off). Remembered positions are kept either way.
+    pub fn set_resume_after(&mut self, after: Option<Duration>) {
+        self.resume_after = after;
+    }
+
+    /// Saves the current track's position so it can resume there, or forgets it if
+    /// the track is near its start or end.
+    fn remember_resume_position(&mut self) {
+        match self.current_resume_position() {
+            Some((file_path, Some(position))) => {
+                self.resume_positions.insert(file_path, position);
+            }
+            Some((file_path, None)) => {
+                self.resume_positions.remove(&file_path);
+            }
+            None => {}
+        }
+    }
+
+    /// The loaded track's file and the position to remember for it, `None` if it
+    /// should start over. `None` overall when there's nothing to update.
+    fn current_resume_position(&self) -> Option<(String, Option<Duration>)> {
+        let after = self.resume_after?;
+        // A crossfade only starts as the track runs out
+        if self.pending_crossfade.is_some() || !self.is_seekable() || !(self.is_playing || self.is_paused) {
+            return None;
+        }
+        let file_path = self.audio.current_file()?.clone();
+        let position = self.position();
+        let near_end = self.total_duration.is_some_and(|total| position + RESUME_END_MARGIN >= total);
+        let keep = !near_end && !self.is_waiting() && position >= after;
+        Some((file_path, keep.then_some(position)))
+    }
+
+    /// Seeks the just-started song at `idx` to where it was left. The position is
+    /// used up, so playing through to the end starts the song over next time.
+    fn resume_saved_position(&mut self, idx: usize) {
+        let Some(position) = self.resume_positions.remove(&self.songs[idx].file_path) else { return };
+        if self.resume_after.is_none() || !self.is_seekable() {
+            return;
+        }
+        match self.audio.seek(position) {
+            Ok(()) => {
+                // Seeking rebuilds the sink, dropping anything queued for gapless playback
+                self.gapless_attempted = false;
+                self.events.push(PlayerEvent::Resumed(idx, position));
+            }
+            Err(e) => warn!("Could not resume '{}': {}", self.songs[idx].title, e),
+        }
+    }
+
+    /// Gapless playback only applies when songs are meant to follow straight on: no
+    /// auto-advance delay, no crossfade, no A-B loop holding the current track, and
+    /// no stop after it.
+    fn gapless_enabled(&self) -> bool {
+        self.advance_delay().is_zero() && self.audio.crossfade().is_zero() && self.active_loop().is_none() && !self.stop_after_current
+    }
+
+    /// The song auto-advance would play next, if that can be known without changing
+    /// any state. A shuffle that is about to reshuffle returns `None`.
+    fn peek_next_index(&self) -> Option<usize> {
+        let idx = self.current_index?;
+        if let Some(queued) = self.peek_queued() {
+            return Some(queued);
+        }
+        if self.repeat_mode == RepeatMode::One {
+            return Some(idx);
+        }
+        match self.mode {
+            PlaybackMode::Shuffle => self.shuffle_order.get(self.shuffle_cursor + 1).copied(),
+            PlaybackMode::Sequential if idx + 1 < self.songs.len() => Some(idx + 1),
+            PlaybackMode::Sequential if self.repeat_mode == RepeatMode::All => Some(0),
+            PlaybackMode::Sequential => None,
+        }
+    }
+
+    /// Opens the next song in the background as the current one nears its end, so
+    /// switching to it, gapless or not, doesn't wait on the disk.
+    fn update_prefetch(&mut self) {
+        let Some(total) = self.total_duration else { return };
+        if !self.is_playing() || total.saturating_sub(self.audio.get_current_position()) > PREFETCH_AHEAD {
+            return;
+        }
+        let Some(next) = self.peek_next_index() else { return };
+        let path = &self.songs[next].file_path;
+        // A queued track has already used its prefetch
+        if is_stream_url(path) || self.audio.queued_file() == Some(path.as_str()) {
+            return;
+        }
+        self.audio.prefetch(path);
+    }
+
+    /// Queues the next song on the audio engine shortly before the current one ends,
+    /// and takes over once it has started playing.
+    fn update_gapless(&mut self) {
+        if let Some(queued) = self.audio.queued_file().map(str::to_string) {
+            if self.audio.advance_to_queued() {
+                self.clear_loop();
+                // The list may have changed since queueing, so find the song again
+                let from_queue = self.peek_queued().is_some_and(|i| self.songs[i].file_path == queued);
+                let idx = self
+                    .peek_next_index()
+                    .filter(|&i| self.songs[i].file_path == queued)
+                    .or_else(|| self.songs.iter().position(|s| s.file_path == queued));
+                self.current_index = idx;
+                if from_queue {
+                    // Shuffle carries on from where it was once the queue runs out
+                    self.queue.pop_front();
+                } else {
+                    self.sync_shuffle_cursor();
+                }
+                match idx {
+                    Some(idx) => self.on_track_started(idx),
+                    None => self.total_duration = self.audio.get_total_duration(),
+                }
+            }
+            return;
+        }
+
+        if self.gapless_attempted || !self.is_playing() || !self.gapless_enabled() {
+            return;
+        }
+        let Some(total) = self.total_duration else { return };
+        if total.saturating_sub(self.audio.get_current_position()) > GAPLESS_PRELOAD {
+            return;
+        }
+        let Some(next) = self.peek_next_index() else { return };
+        self.gapless_attempted = true;
+        let song = &self.songs[next];
+        if is_stream_url(&song.file_path) {
+            // Streams connect in the background; auto-advance handles them
+            return;
+        }
+        match self.audio.queue_next(&song.file_path, song.duration.map(Duration::from_secs_f64)) {
+            Ok(()) if song.duration.is_none() => {
+                let path = song.file_path.clone();
+                self.probe_durations(vec![path]);
+            }
+            Ok(()) => {}
+            // Leave it to the normal auto-advance, which reports and skips bad files
+            Err(e) => warn!("Could not queue '{}' for gapless playback: {}", song.title, e),
+        }
+    }
+
+    /// Reports a failed play attempt and reacts to the kind of failure: missing files
+    /// are dropped from the list and undecodable ones are skipped, so a single bad
+    /// entry doesn't stall playback.
+    fn handle_playback_error(&mut self, idx: usize, error: PlaybackError) {
+        let title = self.songs[idx].title.clone();
+        warn!("Failed to play '{}': {}", title, error);
+        self.failed_attempts += 1;
+        match error {
+            PlaybackError::FileNotFound(_) => {
+                self.events.push(PlayerEvent::Error(format!("'{}' no longer exists and was removed from the playlist", title)));
+                self.songs.remove(idx);
+                self.current_index = if idx < self.songs.len() { Some(idx) } else { None };
+                self.on_songs_changed();
+                if self.current_index.is_some() && self.failed_attempts < self.songs.len() {
+                    self.play_current();
+                }
+            }
+            PlaybackError::UnsupportedFormat(_) => {
+                self.events.push(PlayerEvent::Error(format!("Skipped '{}': the file format is unsupported or the file is corrupt", title)));
+                if self.failed_attempts < self.songs.len() {
+                    self.select_next_song();
+                    self.play_current();
+                }
+            }
+            PlaybackError::Network(e) => {
+                self.events.push(PlayerEvent::Error(format!("Could not stream '{}': {}", title, e)));
+            }
+            PlaybackError::DeviceError(e) => {
+                // The output stream may have died with its device; one fresh stream is
+                // worth a retry before giving up
+                if self.failed_attempts == 1 && self.audio.rebuild_stream().is_ok() {
+                    info!("Retrying '{}' on a rebuilt output stream", title);
+                    self.play_current();
+                    return;
+                }
+                self.events.push(PlayerEvent::Error(format!("Could not open the audio device to play '{}': {}", title, e)));
+            }
+            PlaybackError::Io(e) => {
+                self.events.push(PlayerEvent::Error(format!("Skipped '{}': could not read it ({})", title, e)));
+                // Don't get stuck on a song that can't be read; the counter stops this
+                // from looping when nothing in the playlist plays
+                if self.failed_attempts < self.songs.len() {
+                    self.select_next_song();
+                    self.play_current();
+                }
+            }
+        }
+    }
+
+    fn auto_advance_to_next_song(&mut self) {
+        if self.songs.is_empty() {
+            return;
+        }
+
+        // Songs queued by hand come before the normal play order
+        if let Some(idx) = self.take_queued() {
+            self.current_index = Some(idx);
+            self.play_current();
+            return;
+        }
+
+        // If no song is selected, start from the beginning of the play order
+        if self.current_index.is_none() {
+            self.select_next_song();
+            self.play_current();
+            return;
+        }
+
+        if self.repeat_mode == RepeatMode::One {
+            // Replay the song that just finished
+            self.play_current();
+            return;
+        }
+
+        if self.mode == PlaybackMode::Shuffle {
+            self.ensure_shuffle_order();
+            // A finished order has already been reshuffled, so repeat-all just carries on
+            if self.advance_shuffle_cursor() || self.repeat_mode == RepeatMode::All {
+                self.current_index = Some(self.shuffle_order[self.shuffle_cursor]);
+                self.play_current();
+            } else {
+                // Every song in the shuffled order has played
+                self.stop();
+            }
+            return;
+        }
+
+        let current_index = self.current_index.unwrap();
+        if current_index < self.songs.len() - 1 {
+            self.current_index = Some(current_index + 1);
+            self.play_current();
+        } else if self.repeat_mode == RepeatMode::All {
+            // Wrap around to the first song
+            self.current_index = Some(0);
+            self.play_current();
+        } else {
+            // No more songs, stop playback
+            info!("Reached the end of the playlist");
+            self.stop();
+        }
+    }
+
+    fn select_next_song(&mut self) {
+        if self.mode == PlaybackMode::Shuffle {
+            // Move forward in the shuffled order, reshuffling once every song has played
+            self.ensure_shuffle_order();
+            if self.current_index.is_some() {
+                self.advance_shuffle_cursor();
+            }
+            self.current_index = Some(self.shuffle_order[self.shuffle_cursor]);
+        } else {
+            // Move to next song, wrapping around to the beginning
+            self.current_index = match self.current_index {
+                Some(idx) if idx + 1 < self.songs.len() => Some(idx + 1),
+                _ => Some(0),
+            };
+        }
+    }
+
+    fn select_previous_song(&mut self) {
+        if self.mode == PlaybackMode::Shuffle {
+            // Walk back through the shuffled order, wrapping around to its end
+            self.ensure_shuffle_order();
+            if self.shuffle_cursor == 0 {
+                self.shuffle_cursor = self.shuffle_order.len() - 1;
+            } else {
+                self.shuffle_cursor -= 1;
+            }
+            self.current_index = Some(self.shuffle_order[self.shuffle_cursor]);
+        } else {
+            // Move to previous song, wrapping around to the end
+            self.current_index = match self.current_index {
+                Some(idx) if idx > 0 => Some(idx - 1),
+                _ => Some(self.songs.len() - 1),
+            };
+        }
+    }
+
+    /// Builds a new random permutation of the song list. `first` is placed at the
+    /// front so the song that is currently playing doesn't get played again.
+    fn reshuffle(&mut self, first: Option<usize>) {
+        let mut order: Vec<usize> = (0..self.songs.len()).collect();
+        order.shuffle(&mut rand::thread_rng());
+        if let Some(first) = first {
+            if let Some(pos) = order.iter().position(|&i| i == first) {
+                order.swap(0, pos);
+            }
+        }
+        self.shuffle_order = order;
+        self.shuffle_cursor = 0;
+    }
+
+    fn ensure_shuffle_order(&mut self) {
+        if self.shuffle_order.len() != self.songs.len() {
+            self.reshuffle(self.current_index);
+        }
+    }
+
+    /// Moves the cursor one step forward. Returns false when the whole order has been
+    /// played, in which case a fresh order is generated that doesn't start with the
+    /// song that just finished.
+    fn advance_shuffle_cursor(&mut self) -> bool {
+        if self.shuffle_cursor + 1 < self.shuffle_order.len() {
+            self.shuffle_cursor += 1;
+            return true;
+        }
+        let last = self.shuffle_order.last().copied();
+        self.reshuffle(None);
+        if self.shuffle_order.len() > 1 && self.shuffle_order.first().copied() == last {
+            let end = self.shuffle_order.len() - 1;
+            self.shuffle_order.swap(0, end);
+        }
+        false
+    }
+
+    /// Keeps the shuffle cursor in step with a song picked by hand.
+    fn sync_shuffle_cursor(&mut self) {
+        if self.mode != PlaybackMode::Shuffle {
+            return;
+        }
+        self.ensure_shuffle_order();
+        if let Some(idx) = self.current_index {
+            if let Some(pos) = self.shuffle_order.iter().position(|&i| i == idx) {
+                self.shuffle_cursor = pos;
+            }
+        }
+    }
+
+    /// Called whenever the song list changes so the shuffled order and the queue never
+    /// point at songs that no longer exist.
+    fn on_songs_changed(&mut self) {
+        self.queue.retain(|path| self.songs.iter().any(|s| &s.file_path == path));
+        if self.mode == PlaybackMode::Shuffle {
+            self.reshuffle(self.current_index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn relative_and_absolute_paths_to_one_file_are_duplicates() {
+        // Tests run from the crate root, so the relative path resolves to the same file
+        let relative = Path::new("tests/fixtures/untagged.wav");
+        let absolute = Path::new(env!("CARGO_MANIFEST_DIR")).join(relative);
+        let mut player = Player::new(AudioManager::new());
+
+        assert_eq!(player.add_songs(vec![Song::from_path(relative), Song::from_path(&absolute)]), 1);
+        assert!(!player.add_song(Song::from_path(&absolute)));
+        assert_eq!(player.songs().len(), 1);
+    }
+}
\ No newline at end of file
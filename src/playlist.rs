@@ -1,21 +1,230 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::Hint;
+use symphonia::default::{get_codecs, get_probe};
 use tracing::info;
 use walkdir::WalkDir;
 
+use crate::loudness::parse_replay_gain;
+use crate::stream::is_stream_url;
+use crate::utils::{canonical_path, has_extension, normalize_separators, relative_to_root};
+
+/// Songs are identified by their canonical file path, so one library entry is shared by
+/// every playlist that references the file.
+pub type SongId = String;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Song {
     pub title: String,
     pub artist: String,
     pub file_path: String,
     pub duration: Option<f64>,
+    #[serde(default)]
+    pub album: Option<String>,
+    /// Gain in dB that brings the track to the normalization level, from its ReplayGain
+    /// tag or from analysing the audio.
+    #[serde(default)]
+    pub replay_gain_db: Option<f32>,
+    /// The album's ReplayGain, which keeps the loudness steps between an album's
+    /// tracks. Only ever read from tags.
+    #[serde(default)]
+    pub album_gain_db: Option<f32>,
+    /// How many times playback of the song has started.
+    #[serde(default)]
+    pub play_count: u32,
+    #[serde(default)]
+    pub last_played: Option<chrono::DateTime<chrono::Utc>>,
+    /// Star rating from 0 (unrated) to 5.
+    #[serde(default)]
+    pub rating: u8,
+    #[serde(default)]
+    pub favorite: bool,
+    /// Position on the album, from the track number tag.
+    #[serde(default)]
+    pub track_number: Option<u32>,
+    /// Release year, from the date tag.
+    #[serde(default)]
+    pub year: Option<i32>,
+}
+
+impl Song {
+    /// Builds a song from the tags embedded in the file (ID3, Vorbis comments, MP4
+    /// atoms — whatever symphonia can read), along with its duration. Missing tags fall
+    /// back to the file stem for the title and "Unknown" for the artist.
+    pub fn from_path(path: &Path) -> Song {
+        Self::from_probe(path, probe_tags(path).unwrap_or_default())
+    }
+
+    /// Like `from_path`, but `None` when the file has no audio track symphonia can
+    /// decode, e.g. a corrupt download or a mislabelled extension.
+    pub fn probe(path: &Path) -> Option<Song> {
+        probe_tags(path).map(|probed| Self::from_probe(path, probed))
+    }
+
+    fn from_probe(path: &Path, (tags, duration): (Vec<Tag>, Option<f64>)) -> Song {
+        let fallback_title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let tag_value = |key: StandardTagKey| {
+            tags.iter()
+                .find(|tag| tag.std_key == Some(key))
+                .map(|tag| tag.value.to_string().trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Song {
+            title: tag_value(StandardTagKey::TrackTitle).unwrap_or(fallback_title),
+            artist: tag_value(StandardTagKey::Artist)
+                .or_else(|| tag_value(StandardTagKey::AlbumArtist))
+                .unwrap_or_else(|| "Unknown".to_string()),
+            file_path: path.display().to_string(),
+            duration,
+            album: tag_value(StandardTagKey::Album),
+            replay_gain_db: tag_value(StandardTagKey::ReplayGainTrackGain).and_then(|v| parse_replay_gain(&v)),
+            album_gain_db: tag_value(StandardTagKey::ReplayGainAlbumGain).and_then(|v| parse_replay_gain(&v)),
+            play_count: 0,
+            last_played: None,
+            rating: 0,
+            favorite: false,
+            track_number: tag_value(StandardTagKey::TrackNumber).and_then(|v| parse_track_number(&v)),
+            year: tag_value(StandardTagKey::Date)
+                .or_else(|| tag_value(StandardTagKey::ReleaseDate))
+                .or_else(|| tag_value(StandardTagKey::OriginalDate))
+                .and_then(|v| parse_year(&v)),
+        }
+    }
+
+    /// Builds a playlist entry for an internet stream. There are no tags to read up
+    /// front, so the title is taken from the URL.
+    pub fn from_url(url: &str) -> Song {
+        let url = url.trim();
+        let title = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+        Song {
+            title: title.to_string(),
+            artist: "Internet stream".to_string(),
+            file_path: url.to_string(),
+            duration: None,
+            album: None,
+            replay_gain_db: None,
+            album_gain_db: None,
+            play_count: 0,
+            last_played: None,
+            rating: 0,
+            favorite: false,
+            track_number: None,
+            year: None,
+        }
+    }
+}
+
+/// Totals over a list of songs, for the statistics panel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaylistStats {
+    pub songs: usize,
+    /// Combined length in seconds of the songs whose duration is known.
+    pub total_duration: f64,
+    pub unknown_durations: usize,
+    /// Distinct artists and albums, ignoring case. Untagged songs aren't counted.
+    pub artists: usize,
+    pub albums: usize,
+    /// Size on disk of the local files that could be found.
+    pub total_bytes: u64,
+    /// Local files that no longer exist.
+    pub missing_files: usize,
+}
+
+impl PlaylistStats {
+    /// Adds up `songs`. This reads each file's size from disk, so it's meant to be
+    /// cached rather than run every frame.
+    pub fn compute(songs: &[Song]) -> Self {
+        let mut artists = std::collections::HashSet::new();
+        let mut albums = std::collections::HashSet::new();
+        let mut stats = Self {
+            songs: songs.len(),
+            ..Self::default()
+        };
+        for song in songs {
+            match song.duration {
+                Some(duration) => stats.total_duration += duration,
+                None => stats.unknown_durations += 1,
+            }
+            if song.artist != "Unknown" && !song.artist.is_empty() {
+                artists.insert(song.artist.to_lowercase());
+            }
+            if let Some(album) = song.album.as_deref().filter(|a| !a.is_empty()) {
+                albums.insert(album.to_lowercase());
+            }
+            if is_stream_url(&song.file_path) {
+                continue;
+            }
+            match std::fs::metadata(&song.file_path) {
+                Ok(metadata) => stats.total_bytes += metadata.len(),
+                Err(_) => stats.missing_files += 1,
+            }
+        }
+        stats.artists = artists.len();
+        stats.albums = albums.len();
+        stats
+    }
+}
+
+/// Reads every tag symphonia finds in the file, plus the duration in seconds of the
+/// first audio track. Tags can live in the container (e.g. FLAC, OGG) or in a block
+/// read while probing (e.g. ID3v2 in front of an MP3), so both places are checked.
+/// `None` when the container can't be read or holds no track with a supported codec.
+fn probe_tags(path: &Path) -> Option<(Vec<Tag>, Option<f64>)> {
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut probed = get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    let mut tags = Vec::new();
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.extend(revision.tags().iter().cloned());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend(revision.tags().iter().cloned());
+    }
+
+    let track = probed.format.tracks().iter().find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+    // Setting up a decoder is cheap and catches codecs symphonia wasn't built with
+    get_codecs().make(&track.codec_params, &DecoderOptions::default()).ok()?;
+    let duration = track
+        .codec_params
+        .n_frames
+        .zip(track.codec_params.sample_rate)
+        .map(|(frames, rate)| frames as f64 / rate as f64);
+    Some((tags, duration))
+}
+
+/// Reads "3", "03" or "3/12" as track 3.
+fn parse_track_number(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok().filter(|&n| n > 0)
+}
+
+/// Reads the year from a date tag such as "2004", "2004-05-01" or "2004-05-01T00:00:00".
+fn parse_year(value: &str) -> Option<i32> {
+    let digits: String = value.trim().chars().take_while(char::is_ascii_digit).collect();
+    (digits.len() == 4).then(|| digits.parse().ok()).flatten()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Playlist {
     pub name: String,
-    pub songs: Vec<Song>,
+    pub song_ids: Vec<SongId>,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
@@ -23,47 +232,127 @@ impl Playlist {
     pub fn new(name: String) -> Self {
         Self {
             name,
-            songs: Vec::new(),
+            song_ids: Vec::new(),
             created_at: chrono::Utc::now(),
         }
     }
 
-    pub fn add_song(&mut self, song: Song) {
-        self.songs.push(song);
+    pub fn add_song(&mut self, id: SongId) {
+        self.song_ids.push(id);
     }
 
-    pub fn remove_song(&mut self, index: usize) -> Option<Song> {
-        if index < self.songs.len() {
-            Some(self.songs.remove(index))
+    pub fn remove_song(&mut self, index: usize) -> Option<SongId> {
+        if index < self.song_ids.len() {
+            Some(self.song_ids.remove(index))
         } else {
             None
         }
     }
 
-    pub fn get_song(&self, index: usize) -> Option<&Song> {
-        self.songs.get(index)
+    /// Removes every entry for a file that's already listed earlier, keeping the first
+    /// copy, and returns the positions that were removed. Paths are canonicalized before
+    /// comparing, so `./a.mp3` and an absolute path to the same file count as one.
+    pub fn dedup_by_path(&mut self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        let mut removed = Vec::new();
+        let mut i = 0;
+        self.song_ids.retain(|id| {
+            let keep = seen.insert(canonical_path(id));
+            if !keep {
+                removed.push(i);
+            }
+            i += 1;
+            keep
+        });
+        removed
+    }
+
+    pub fn get_song_id(&self, index: usize) -> Option<&SongId> {
+        self.song_ids.get(index)
     }
 
     pub fn len(&self) -> usize {
-        self.songs.len()
+        self.song_ids.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.songs.is_empty()
+        self.song_ids.is_empty()
     }
 }
 
+/// On-disk form of a playlist. Songs are written out in full so a saved file stands on
+/// its own and can be loaded into a library that has never seen them.
+#[derive(Serialize, Deserialize)]
+struct PlaylistFile {
+    name: String,
+    songs: Vec<Song>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// The library root that songs inside it were saved relative to. Their paths are
+    /// absolute when this is `None`.
+    #[serde(default)]
+    library_root: Option<String>,
+}
+
 pub struct PlaylistManager {
+    library: HashMap<SongId, Song>,
     playlists: HashMap<String, Playlist>,
     current_playlist: Option<String>,
+    /// Saved playlists store the paths of songs under this folder relative to it.
+    library_root: Option<PathBuf>,
+}
+
+impl Default for PlaylistManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PlaylistManager {
     pub fn new() -> Self {
         Self {
+            library: HashMap::new(),
             playlists: HashMap::new(),
             current_playlist: None,
+            library_root: None,
+        }
+    }
+
+    /// Makes saved playlists portable: songs under `root` are written relative to it
+    /// and loaded relative to whichever root is set at the time. `None` writes absolute
+    /// paths.
+    pub fn set_library_root(&mut self, root: Option<PathBuf>) {
+        self.library_root = root;
+    }
+
+    pub fn library_root(&self) -> Option<&Path> {
+        self.library_root.as_deref()
+    }
+
+    /// Turns a path read from a playlist file into one for this machine. Relative paths
+    /// are tried against `bases` in order and the first that exists wins; if none does,
+    /// the first base is used so the song shows up as missing rather than vanishing.
+    fn resolve_saved_path(stored: &str, bases: &[&Path]) -> String {
+        if is_stream_url(stored) {
+            return stored.to_string();
+        }
+        let path = normalize_separators(stored);
+        if Path::new(&path).is_absolute() || bases.is_empty() {
+            return path;
+        }
+        let candidates: Vec<PathBuf> = bases.iter().map(|base| base.join(&path)).collect();
+        let found = candidates.iter().find(|c| c.exists()).unwrap_or(&candidates[0]);
+        found.display().to_string()
+    }
+
+    /// `name`, or if a playlist already has it, the first free "name (2)", "name (3)", ...
+    fn unique_playlist_name(&self, name: &str) -> String {
+        if !self.playlists.contains_key(name) {
+            return name.to_string();
         }
+        (2..)
+            .map(|n| format!("{} ({})", name, n))
+            .find(|candidate| !self.playlists.contains_key(candidate))
+            .unwrap()
     }
 
     pub fn create_playlist(&mut self, name: String) -> Result<()> {
@@ -78,35 +367,119 @@ impl PlaylistManager {
         Ok(())
     }
 
-    pub fn add_song_to_current_playlist(&mut self, song: Song) -> Result<()> {
-        if let Some(playlist_name) = &self.current_playlist {
-            if let Some(playlist) = self.playlists.get_mut(playlist_name) {
-                playlist.add_song(song);
-                info!("Added song to playlist: {}", playlist_name);
-                return Ok(());
+    /// Adds `song` to the shared library and returns its ID. If the file is already in
+    /// the library the existing entry (and its metadata) is kept as is.
+    pub fn add_to_library(&mut self, song: Song) -> SongId {
+        let id = canonical_path(&song.file_path);
+        self.library.entry(id.clone()).or_insert(song);
+        id
+    }
+
+    /// Every song known to the library, in no particular order.
+    pub fn library_songs(&self) -> impl Iterator<Item = &Song> {
+        self.library.values()
+    }
+
+    pub fn get_song(&self, id: &str) -> Option<&Song> {
+        self.library.get(id)
+    }
+
+    /// Edits made through this reference are seen by every playlist holding the song.
+    pub fn get_song_mut(&mut self, id: &str) -> Option<&mut Song> {
+        self.library.get_mut(id)
+    }
+
+    pub fn add_song_to_playlist(&mut self, name: &str, song: Song) -> Result<()> {
+        if !self.playlists.contains_key(name) {
+            return Err(anyhow::anyhow!("Playlist '{}' not found", name));
+        }
+        let id = self.add_to_library(song);
+        if let Some(playlist) = self.playlists.get_mut(name) {
+            playlist.add_song(id);
+            info!("Added song to playlist: {}", name);
+        }
+        Ok(())
+    }
+
+    /// Whether the playlist `name` already holds the file at `file_path`.
+    pub fn playlist_contains(&self, name: &str, file_path: &str) -> bool {
+        let id = canonical_path(file_path);
+        self.playlists.get(name).is_some_and(|p| p.song_ids.contains(&id))
+    }
+
+    /// Adds each song to the playlist `name` unless it's already there, and returns how
+    /// many were skipped as duplicates.
+    pub fn add_new_songs_to_playlist(&mut self, name: &str, songs: Vec<Song>) -> Result<usize> {
+        if !self.playlists.contains_key(name) {
+            return Err(anyhow::anyhow!("Playlist '{}' not found", name));
+        }
+        let mut skipped = 0;
+        for song in songs {
+            if self.playlist_contains(name, &song.file_path) {
+                skipped += 1;
+                continue;
+            }
+            let id = self.add_to_library(song);
+            if let Some(playlist) = self.playlists.get_mut(name) {
+                playlist.add_song(id);
             }
         }
+        info!("Added songs to playlist {}, skipping {} duplicates", name, skipped);
+        Ok(skipped)
+    }
+
+    pub fn add_song_to_current_playlist(&mut self, song: Song) -> Result<()> {
+        if let Some(playlist_name) = self.current_playlist.clone() {
+            return self.add_song_to_playlist(&playlist_name, song);
+        }
         Err(anyhow::anyhow!("No current playlist selected"))
     }
 
     pub fn remove_song_from_current_playlist(&mut self, index: usize) -> Result<Song> {
         if let Some(playlist_name) = &self.current_playlist {
             if let Some(playlist) = self.playlists.get_mut(playlist_name) {
-                if let Some(song) = playlist.remove_song(index) {
+                if let Some(id) = playlist.remove_song(index) {
                     info!("Removed song from playlist: {}", playlist_name);
-                    return Ok(song);
+                    if let Some(song) = self.library.get(&id) {
+                        return Ok(song.clone());
+                    }
                 }
             }
         }
         Err(anyhow::anyhow!("Failed to remove song"))
     }
 
+    /// Resolves a playlist's song IDs against the library, in playlist order.
+    pub fn playlist_songs(&self, name: &str) -> Vec<&Song> {
+        self.playlists
+            .get(name)
+            .map(|playlist| {
+                playlist
+                    .song_ids
+                    .iter()
+                    .filter_map(|id| self.library.get(id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn current_playlist_songs(&self) -> Vec<&Song> {
+        self.current_playlist
+            .as_ref()
+            .map(|name| self.playlist_songs(name))
+            .unwrap_or_default()
+    }
+
     pub fn get_current_playlist(&self) -> Option<&Playlist> {
         self.current_playlist
             .as_ref()
             .and_then(|name| self.playlists.get(name))
     }
 
+    pub fn current_playlist_name(&self) -> Option<&str> {
+        self.get_current_playlist().map(|p| p.name.as_str())
+    }
+
     pub fn get_current_playlist_mut(&mut self) -> Option<&mut Playlist> {
         self.current_playlist
             .as_ref()
@@ -127,7 +500,69 @@ impl PlaylistManager {
         self.playlists.keys().cloned().collect()
     }
 
-    pub fn scan_music_directory(&mut self, directory: &str) -> Result<Vec<Song>> {
+    /// Renames a playlist, keeping it current if it was. Fails if the playlist doesn't
+    /// exist, the new name is blank or another playlist already has it.
+    pub fn rename_playlist(&mut self, name: &str, new_name: &str) -> Result<()> {
+        let new_name = new_name.trim();
+        if new_name.is_empty() {
+            return Err(anyhow::anyhow!("Playlist name can't be empty"));
+        }
+        if !self.playlists.contains_key(name) {
+            return Err(anyhow::anyhow!("Playlist '{}' not found", name));
+        }
+        if name == new_name {
+            return Ok(());
+        }
+        if self.playlists.contains_key(new_name) {
+            return Err(anyhow::anyhow!("Playlist '{}' already exists", new_name));
+        }
+        let mut playlist = self.playlists.remove(name).unwrap();
+        playlist.name = new_name.to_string();
+        self.playlists.insert(new_name.to_string(), playlist);
+        if self.current_playlist.as_deref() == Some(name) {
+            self.current_playlist = Some(new_name.to_string());
+        }
+        info!("Renamed playlist {} to {}", name, new_name);
+        Ok(())
+    }
+
+    /// Removes a playlist. Its songs stay in the library. Deleting the current playlist
+    /// leaves no playlist selected.
+    pub fn delete_playlist(&mut self, name: &str) -> Result<()> {
+        if self.playlists.remove(name).is_none() {
+            return Err(anyhow::anyhow!("Playlist '{}' not found", name));
+        }
+        if self.current_playlist.as_deref() == Some(name) {
+            self.current_playlist = None;
+        }
+        info!("Deleted playlist: {}", name);
+        Ok(())
+    }
+
+    /// Replaces a playlist's contents with `songs`, in order. Unlike `add_to_library`,
+    /// the library entries are updated with these songs' metadata, since they are the
+    /// most recent copies (e.g. with a duration filled in during playback).
+    pub fn set_playlist_songs(&mut self, name: &str, songs: Vec<Song>) -> Result<()> {
+        if !self.playlists.contains_key(name) {
+            return Err(anyhow::anyhow!("Playlist '{}' not found", name));
+        }
+        let ids = songs
+            .into_iter()
+            .map(|song| {
+                let id = canonical_path(&song.file_path);
+                self.library.insert(id.clone(), song);
+                id
+            })
+            .collect();
+        if let Some(playlist) = self.playlists.get_mut(name) {
+            playlist.song_ids = ids;
+        }
+        Ok(())
+    }
+
+    /// Reads every file under `directory` whose extension is one of `extensions`, such
+    /// as `Settings::enabled_formats`.
+    pub fn scan_music_directory<S: AsRef<str>>(&mut self, directory: &str, extensions: &[S]) -> Result<Vec<Song>> {
         let mut songs = Vec::new();
         
         for entry in WalkDir::new(directory)
@@ -136,23 +571,8 @@ impl PlaylistManager {
             .filter(|e| e.file_type().is_file())
         {
             let path = entry.path();
-            if let Some(extension) = path.extension() {
-                let ext = extension.to_string_lossy().to_lowercase();
-                if matches!(ext.as_str(), "mp3" | "wav" | "flac" | "ogg" | "m4a") {
-                    if let Some(file_name) = path.file_stem() {
-                        let title = file_name.to_string_lossy().to_string();
-                        let file_path = path.to_string_lossy().to_string();
-                        
-                        let song = Song {
-                            title,
-                            artist: "Unknown".to_string(),
-                            file_path,
-                            duration: None,
-                        };
-                        
-                        songs.push(song);
-                    }
-                }
+            if has_extension(path, extensions) {
+                songs.push(Song::from_path(path));
             }
         }
         
@@ -162,7 +582,21 @@ impl PlaylistManager {
 
     pub fn save_playlist(&self, name: &str, file_path: &str) -> Result<()> {
         if let Some(playlist) = self.playlists.get(name) {
-            let json = serde_json::to_string_pretty(playlist)?;
+            let mut songs: Vec<Song> = self.playlist_songs(name).into_iter().cloned().collect();
+            if let Some(root) = &self.library_root {
+                for song in &mut songs {
+                    if let Some(relative) = relative_to_root(&song.file_path, root) {
+                        song.file_path = relative;
+                    }
+                }
+            }
+            let file = PlaylistFile {
+                name: playlist.name.clone(),
+                songs,
+                created_at: playlist.created_at,
+                library_root: self.library_root.as_ref().map(|root| root.display().to_string()),
+            };
+            let json = serde_json::to_string_pretty(&file)?;
             std::fs::write(file_path, json)?;
             info!("Saved playlist '{}' to {}", name, file_path);
             Ok(())
@@ -171,11 +605,239 @@ impl PlaylistManager {
         }
     }
 
-    pub fn load_playlist(&mut self, file_path: &str) -> Result<()> {
+    /// Loads a playlist saved by `save_playlist` and returns its name. If a playlist
+    /// already has the saved name, the loaded one is numbered ("Mix (2)") rather than
+    /// replacing it.
+    pub fn load_playlist(&mut self, file_path: &str) -> Result<String> {
         let content = std::fs::read_to_string(file_path)?;
-        let playlist: Playlist = serde_json::from_str(&content)?;
-        self.playlists.insert(playlist.name.clone(), playlist.clone());
+        let file: PlaylistFile = serde_json::from_str(&content)?;
+        let mut playlist = Playlist::new(self.unique_playlist_name(&file.name));
+        playlist.created_at = file.created_at;
+        // Relative paths belong under the current library root, or failing that the
+        // root the playlist was saved from
+        let saved_root = file.library_root.map(|root| PathBuf::from(normalize_separators(&root)));
+        let root = self.library_root.clone();
+        let bases: Vec<&Path> = root.as_deref().into_iter().chain(saved_root.as_deref()).collect();
+        for mut song in file.songs {
+            song.file_path = Self::resolve_saved_path(&song.file_path, &bases);
+            let id = self.add_to_library(song);
+            playlist.add_song(id);
+        }
         info!("Loaded playlist '{}' from {}", playlist.name, file_path);
+        let name = playlist.name.clone();
+        self.playlists.insert(name.clone(), playlist);
+        Ok(name)
+    }
+
+    /// Writes a playlist as extended M3U (`#EXTINF:duration,artist - title` followed by
+    /// the file path) so it can be opened by VLC and other players.
+    pub fn export_m3u(&self, name: &str, file_path: &str) -> Result<()> {
+        if !self.playlists.contains_key(name) {
+            return Err(anyhow::anyhow!("Playlist '{}' not found", name));
+        }
+        let mut content = String::from("#EXTM3U\n");
+        for song in self.playlist_songs(name) {
+            // M3U uses -1 for an unknown length
+            let duration = song.duration.map(|d| d.round() as i64).unwrap_or(-1);
+            content.push_str(&format!("#EXTINF:{},{} - {}\n", duration, song.artist, song.title));
+            content.push_str(&song.file_path);
+            content.push('\n');
+        }
+        std::fs::write(file_path, content)?;
+        info!("Exported playlist '{}' to {}", name, file_path);
         Ok(())
     }
-} 
\ No newline at end of file
+
+    /// Reads an M3U/M3U8 file into a playlist named after the file, numbered ("Mix (2)")
+    /// if that name is taken. Relative entries are resolved against the directory
+    /// holding the M3U; `#EXTINF` lines supply the title, artist and duration, and
+    /// entries without one get their tags read from the file. Returns the new
+    /// playlist's name.
+    pub fn import_m3u(&mut self, file_path: &str) -> Result<String> {
+        let content = std::fs::read_to_string(file_path)?;
+        let m3u_path = Path::new(file_path);
+        let base_dir = m3u_path.parent().unwrap_or_else(|| Path::new(""));
+        let name = m3u_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Imported".to_string());
+
+        // Entries are relative to the M3U's folder, but a library root is tried too
+        let root = self.library_root.clone();
+        let bases: Vec<&Path> = std::iter::once(base_dir).chain(root.as_deref()).collect();
+
+        let mut playlist = Playlist::new(self.unique_playlist_name(&name));
+        let mut pending_info: Option<(Option<f64>, String)> = None;
+        for line in content.lines() {
+            let line = line.trim().trim_start_matches('\u{feff}');
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(info) = line.strip_prefix("#EXTINF:") {
+                pending_info = parse_extinf(info);
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let path = PathBuf::from(Self::resolve_saved_path(line, &bases));
+            let song = match pending_info.take() {
+                Some((duration, display)) => {
+                    let (artist, title) = match display.split_once(" - ") {
+                        Some((artist, title)) => (artist.trim().to_string(), title.trim().to_string()),
+                        None => ("Unknown".to_string(), display.trim().to_string()),
+                    };
+                    Song {
+                        title,
+                        artist,
+                        file_path: path.display().to_string(),
+                        duration,
+                        album: None,
+                        replay_gain_db: None,
+                        album_gain_db: None,
+                        play_count: 0,
+                        last_played: None,
+                        rating: 0,
+                        favorite: false,
+                        track_number: None,
+                        year: None,
+                    }
+                }
+                None => Song::from_path(&path),
+            };
+            let id = self.add_to_library(song);
+            playlist.add_song(id);
+        }
+        info!("Imported playlist '{}' ({} songs) from {}", playlist.name, playlist.len(), file_path);
+        let name = playlist.name.clone();
+        self.playlists.insert(name.clone(), playlist);
+        Ok(name)
+    }
+}
+
+/// Splits the part of an `#EXTINF:` line after the colon into its duration (negative
+/// meaning unknown) and display text.
+fn parse_extinf(info: &str) -> Option<(Option<f64>, String)> {
+    let (duration, display) = info.split_once(',')?;
+    // Extended attributes (`tvg-id="..."` and friends) may follow the duration
+    let duration = duration
+        .split_whitespace()
+        .next()
+        .and_then(|d| d.parse::<f64>().ok())
+        .filter(|d| *d >= 0.0);
+    Some((duration, display.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager_with(names: &[&str]) -> PlaylistManager {
+        let mut manager = PlaylistManager::new();
+        for name in names {
+            manager.create_playlist(name.to_string()).unwrap();
+            manager.add_song_to_current_playlist(Song::from_path(Path::new(&format!("/music/{}.mp3", name)))).unwrap();
+        }
+        manager
+    }
+
+    #[test]
+    fn renaming_the_current_playlist_keeps_it_current() {
+        let mut manager = manager_with(&["Mix"]);
+        manager.rename_playlist("Mix", "  Road Trip ").unwrap();
+
+        assert_eq!(manager.current_playlist_name(), Some("Road Trip"));
+        assert_eq!(manager.get_playlist_names(), vec!["Road Trip".to_string()]);
+        let songs = manager.playlist_songs("Road Trip");
+        assert_eq!(songs.len(), 1);
+        assert_eq!(songs[0].title, "Mix");
+        assert!(manager.playlist_songs("Mix").is_empty());
+    }
+
+    #[test]
+    fn renaming_rejects_blank_missing_and_taken_names() {
+        let mut manager = manager_with(&["Mix", "Chill"]);
+        assert!(manager.rename_playlist("Mix", "   ").is_err());
+        assert!(manager.rename_playlist("Nope", "Other").is_err());
+        assert!(manager.rename_playlist("Mix", "Chill").is_err());
+        assert_eq!(manager.current_playlist_name(), Some("Chill"));
+        assert_eq!(manager.playlist_songs("Mix").len(), 1);
+    }
+
+    #[test]
+    fn m3u_export_and_import_keep_durations() {
+        let dir = std::env::temp_dir().join(format!("rust_music_player-m3u-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let m3u = dir.join("Mix.m3u");
+        let mut manager = manager_with(&["Mix"]);
+        let mut second = Song::from_path(&dir.join("second.mp3"));
+        second.artist = "Someone".to_string();
+        second.title = "Second Song".to_string();
+        second.duration = Some(61.6);
+        manager.add_song_to_current_playlist(second).unwrap();
+        manager.get_song_mut(&canonical_path("/music/Mix.mp3")).unwrap().duration = Some(125.0);
+        manager.export_m3u("Mix", &m3u.display().to_string()).unwrap();
+
+        // Into a fresh library, so the songs come from the file rather than the entries
+        // already known. Its "Mix" is kept and the imported copy gets a numbered name
+        let mut imported = PlaylistManager::new();
+        imported.create_playlist("Mix".to_string()).unwrap();
+        let name = imported.import_m3u(&m3u.display().to_string()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(name, "Mix (2)");
+        assert!(imported.playlist_songs("Mix").is_empty());
+        let songs = imported.playlist_songs(&name);
+        let imported: Vec<(&str, &str, Option<f64>)> = songs.iter().map(|s| (s.artist.as_str(), s.title.as_str(), s.duration)).collect();
+        assert_eq!(imported, vec![("Unknown", "Mix", Some(125.0)), ("Someone", "Second Song", Some(62.0))]);
+    }
+
+    #[test]
+    fn loading_a_saved_playlist_keeps_one_with_the_same_name() {
+        let path = std::env::temp_dir().join(format!("rust_music_player-load-{}.json", std::process::id()));
+        let mut manager = manager_with(&["Library"]);
+        manager.save_playlist("Library", &path.display().to_string()).unwrap();
+        manager.create_playlist("Library (2)".to_string()).unwrap();
+
+        let name = manager.load_playlist(&path.display().to_string()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(name, "Library (3)");
+        assert_eq!(manager.playlist_songs("Library").len(), 1);
+        assert!(manager.playlist_songs("Library (2)").is_empty());
+        assert_eq!(manager.playlist_songs(&name).len(), 1);
+    }
+
+    #[test]
+    fn dedup_by_path_keeps_the_first_copy() {
+        let mut playlist = Playlist::new("Mix".to_string());
+        for id in ["/music/a.mp3", "/music/b.mp3", "/music/a.mp3", "/music/c.mp3", "/music/b.mp3"] {
+            playlist.add_song(id.to_string());
+        }
+        assert_eq!(playlist.dedup_by_path(), vec![2, 4]);
+        assert_eq!(playlist.song_ids, vec!["/music/a.mp3", "/music/b.mp3", "/music/c.mp3"]);
+        assert!(playlist.dedup_by_path().is_empty());
+    }
+
+    #[test]
+    fn scanning_only_picks_up_the_given_extensions() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let mut manager = PlaylistManager::new();
+        let titles = |songs: Vec<Song>| songs.into_iter().map(|s| s.title).collect::<Vec<_>>();
+        assert_eq!(titles(manager.scan_music_directory(fixtures, &["wav"]).unwrap()), vec!["untagged"]);
+        assert_eq!(manager.scan_music_directory(fixtures, &["MP3", "wav"]).unwrap().len(), 2);
+        assert!(manager.scan_music_directory(fixtures, &[] as &[&str]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn deleting_another_playlist_leaves_the_current_one() {
+        let mut manager = manager_with(&["Mix", "Chill"]);
+        manager.delete_playlist("Mix").unwrap();
+
+        assert_eq!(manager.current_playlist_name(), Some("Chill"));
+        assert_eq!(manager.get_playlist_names(), vec!["Chill".to_string()]);
+        assert_eq!(manager.playlist_songs("Chill").len(), 1);
+        // The deleted playlist's songs stay in the library
+        assert_eq!(manager.library_songs().count(), 2);
+        assert!(manager.delete_playlist("Mix").is_err());
+    }
+}
\ No newline at end of file
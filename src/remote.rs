@@ -0,0 +1,198 @@
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{unbounded, Receiver};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Loopback address the running player listens on for remote commands.
+pub const REMOTE_ADDR: &str = "127.0.0.1:47615";
+
+/// How long a client waits for the running player to answer.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A command sent to the running player. On the wire each one is a single lowercase
+/// word on its own line, answered with `ok` (or `pong` for `Ping`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteCommand {
+    Play,
+    Pause,
+    TogglePause,
+    Stop,
+    Next,
+    Previous,
+    /// Moves to the next repeat mode, as the repeat button does.
+    CycleRepeat,
+    ToggleShuffle,
+    ToggleMute,
+    /// Only checks that an instance is listening.
+    Ping,
+}
+
+impl RemoteCommand {
+    /// Every command that can be given on the command line, with its help text.
+    pub const ALL: &'static [(RemoteCommand, &'static str)] = &[
+        (RemoteCommand::Play, "Start or resume playback"),
+        (RemoteCommand::Pause, "Pause playback"),
+        (RemoteCommand::TogglePause, "Toggle between playing and paused"),
+        (RemoteCommand::Stop, "Stop playback"),
+        (RemoteCommand::Next, "Skip to the next song"),
+        (RemoteCommand::Previous, "Go back to the previous song"),
+        (RemoteCommand::CycleRepeat, "Cycle the repeat mode (off, all, one)"),
+        (RemoteCommand::ToggleShuffle, "Turn shuffle on or off"),
+        (RemoteCommand::ToggleMute, "Mute or unmute"),
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RemoteCommand::Play => "play",
+            RemoteCommand::Pause => "pause",
+            RemoteCommand::TogglePause => "toggle",
+            RemoteCommand::Stop => "stop",
+            RemoteCommand::Next => "next",
+            RemoteCommand::Previous => "previous",
+            RemoteCommand::CycleRepeat => "repeat",
+            RemoteCommand::ToggleShuffle => "shuffle",
+            RemoteCommand::ToggleMute => "mute",
+            RemoteCommand::Ping => "ping",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Ping].iter().chain(Self::ALL.iter().map(|(c, _)| c)).copied().find(|c| c.name() == name)
+    }
+
+    /// Parses a command-line flag such as `--next`.
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        arg.strip_prefix("--").and_then(Self::parse).filter(|c| *c != RemoteCommand::Ping)
+    }
+}
+
+/// What another invocation asks the running player to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteRequest {
+    Command(RemoteCommand),
+    /// Files and folders to open, as if they were dropped onto the window. On the wire
+    /// this is `open` followed by a JSON array of the paths, all on one line.
+    Open(Vec<PathBuf>),
+}
+
+impl RemoteRequest {
+    fn parse(line: &str) -> Option<Self> {
+        match line.strip_prefix("open ") {
+            Some(paths) => serde_json::from_str(paths).ok().map(RemoteRequest::Open),
+            None => RemoteCommand::parse(line).map(RemoteRequest::Command),
+        }
+    }
+}
+
+/// Sends `command` to the running player and returns its reply. Fails if no instance
+/// is listening.
+pub fn send_command(command: RemoteCommand) -> Result<String> {
+    send_line(command.name())
+}
+
+/// Asks the running player to open `paths`, which should be absolute since it may run
+/// in another directory.
+pub fn send_open(paths: &[PathBuf]) -> Result<()> {
+    send_line(&format!("open {}", serde_json::to_string(paths)?))?;
+    Ok(())
+}
+
+fn send_line(line: &str) -> Result<String> {
+    let mut stream = TcpStream::connect_timeout(&REMOTE_ADDR.parse()?, REPLY_TIMEOUT)
+        .context("no running instance of the player was found")?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+    writeln!(stream, "{}", line)?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let reply = reply.trim().to_string();
+    if let Some(message) = reply.strip_prefix("error: ") {
+        bail!("the running player rejected the command: {}", message);
+    }
+    Ok(reply)
+}
+
+/// Whether another instance of the player is already running and listening.
+pub fn is_instance_running() -> bool {
+    send_command(RemoteCommand::Ping).is_ok_and(|reply| reply == "pong")
+}
+
+/// Accepts remote requests on a background thread so the GUI can pick them up with
+/// `poll`. Holding the listening socket also marks this as the running instance.
+pub struct RemoteServer {
+    receiver: Receiver<RemoteRequest>,
+}
+
+impl RemoteServer {
+    /// Starts listening on `REMOTE_ADDR`. `notify` is called after each command is
+    /// queued so a GUI can wake up and poll.
+    pub fn start<F>(notify: F) -> Result<Self>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let listener = TcpListener::bind(REMOTE_ADDR).with_context(|| format!("could not listen on {}", REMOTE_ADDR))?;
+        info!("Listening for remote commands on {}", REMOTE_ADDR);
+        let (sender, receiver) = unbounded();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Remote connection failed: {}", e);
+                        continue;
+                    }
+                };
+                let _ = stream.set_read_timeout(Some(REPLY_TIMEOUT));
+                let mut line = String::new();
+                if BufReader::new(&stream).read_line(&mut line).is_err() {
+                    continue;
+                }
+                let reply = match RemoteRequest::parse(line.trim()) {
+                    Some(RemoteRequest::Command(RemoteCommand::Ping)) => "pong".to_string(),
+                    Some(request) => {
+                        match &request {
+                            RemoteRequest::Command(command) => info!("Remote command: {}", command.name()),
+                            RemoteRequest::Open(paths) => info!("Remote request to open {} paths", paths.len()),
+                        }
+                        if sender.send(request).is_err() {
+                            return;
+                        }
+                        notify();
+                        "ok".to_string()
+                    }
+                    None => format!("error: unknown command {:?}", line.trim()),
+                };
+                let _ = writeln!(stream, "{}", reply);
+            }
+        });
+
+        Ok(Self { receiver })
+    }
+
+    /// Drains the requests received since the last call.
+    pub fn poll(&self) -> Vec<RemoteRequest> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_and_open_requests() {
+        assert_eq!(RemoteRequest::parse("next"), Some(RemoteRequest::Command(RemoteCommand::Next)));
+        assert_eq!(RemoteRequest::parse("nope"), None);
+
+        // Names with spaces, newlines or a leading dash survive the one-line encoding
+        let paths = vec![PathBuf::from("/music/-live.mp3"), PathBuf::from("/music/odd\nname dir")];
+        let line = format!("open {}", serde_json::to_string(&paths).unwrap());
+        assert!(!line.contains('\n'));
+        assert_eq!(RemoteRequest::parse(&line), Some(RemoteRequest::Open(paths)));
+        assert_eq!(RemoteRequest::parse("open not json"), None);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,180 @@
+use crate::playlist::Song;
+use crate::utils::has_extension;
+use crossbeam_channel::{unbounded, Receiver};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tracing::{info, warn};
+use walkdir::{DirEntry, WalkDir};
+
+/// Folders that never hold music worth importing, skipped along with hidden files.
+const IGNORED_DIRECTORIES: &[&str] = &["node_modules", "__MACOSX", "$RECYCLE.BIN", "System Volume Information"];
+
+/// Limits on how far a folder scan walks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// How many folders deep to look below the chosen one; `None` for no limit.
+    pub max_depth: Option<usize>,
+    /// Skip dotfiles, dot-folders (e.g. `.git`) and `IGNORED_DIRECTORIES`.
+    pub skip_hidden: bool,
+    /// Walk into symlinked folders and pick up symlinked files. Symlink loops are
+    /// detected and skipped.
+    pub follow_symlinks: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            skip_hidden: true,
+            follow_symlinks: false,
+        }
+    }
+}
+
+impl ScanOptions {
+    fn walk(&self, folder: &Path) -> impl Iterator<Item = DirEntry> {
+        let mut walk = WalkDir::new(folder).follow_links(self.follow_symlinks);
+        if let Some(depth) = self.max_depth {
+            // Depth 1 is the files directly inside the folder
+            walk = walk.max_depth(depth + 1);
+        }
+        let skip_hidden = self.skip_hidden;
+        // The folder the user picked is always scanned, even if it's hidden itself
+        walk.into_iter()
+            .filter_entry(move |entry| entry.depth() == 0 || !skip_hidden || !is_ignored(entry))
+            .filter_map(|e| e.ok())
+    }
+}
+
+fn is_ignored(entry: &DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy();
+    name.starts_with('.') || (entry.file_type().is_dir() && IGNORED_DIRECTORIES.iter().any(|d| name.eq_ignore_ascii_case(d)))
+}
+
+/// Progress reported by a background folder scan.
+pub enum ScanMessage {
+    /// A file was looked at; carries the running count.
+    Scanned(usize),
+    /// An audio file was found and its tags read.
+    Found(Song),
+    /// A file with a matching extension that couldn't be decoded.
+    Unreadable(PathBuf),
+    /// The walk completed (or was cancelled).
+    Finished,
+}
+
+/// Walks folders on a background thread, reading tags for every matching file and
+/// streaming the resulting songs back so large libraries don't freeze the UI. Files
+/// that fail to decode are left out and listed in `unreadable`.
+pub struct FolderScan {
+    receiver: Receiver<ScanMessage>,
+    cancelled: Arc<AtomicBool>,
+    scanned: usize,
+    /// Songs delivered by `poll` so far.
+    found: usize,
+    unreadable: Vec<PathBuf>,
+    finished: bool,
+}
+
+impl FolderScan {
+    /// Starts scanning `folders`, one after another, for files whose extension is in
+    /// `formats`. `notify` is called after each message is sent so a GUI can wake up
+    /// and poll.
+    pub fn start<F>(folders: Vec<PathBuf>, formats: Vec<String>, options: ScanOptions, notify: F) -> Self
+    where
+        F: Fn() + Send + 'static,
+    {
+        let (sender, receiver) = unbounded();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancel_flag = cancelled.clone();
+
+        thread::spawn(move || {
+            let mut scanned = 0;
+            let entries = folders.iter().flat_map(|folder| options.walk(folder));
+            for entry in entries {
+                if cancel_flag.load(Ordering::Relaxed) {
+                    info!("Cancelled folder scan after {} files", scanned);
+                    break;
+                }
+                // Symlinks only look like files when they're being followed
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let path = entry.path();
+                scanned += 1;
+                if has_extension(path, &formats) {
+                    let message = match Song::probe(path) {
+                        Some(song) => ScanMessage::Found(song),
+                        None => {
+                            warn!("Skipped undecodable file {}", path.display());
+                            ScanMessage::Unreadable(path.to_path_buf())
+                        }
+                    };
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                }
+                if sender.send(ScanMessage::Scanned(scanned)).is_err() {
+                    return;
+                }
+                notify();
+            }
+            let _ = sender.send(ScanMessage::Finished);
+            notify();
+        });
+
+        Self {
+            receiver,
+            cancelled,
+            scanned: 0,
+            found: 0,
+            unreadable: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Drains whatever the scan thread has sent so far and returns the new songs.
+    pub fn poll(&mut self) -> Vec<Song> {
+        let mut songs = Vec::new();
+        while let Ok(message) = self.receiver.try_recv() {
+            match message {
+                ScanMessage::Scanned(count) => self.scanned = count,
+                ScanMessage::Found(song) => songs.push(song),
+                ScanMessage::Unreadable(path) => self.unreadable.push(path),
+                ScanMessage::Finished => self.finished = true,
+            }
+        }
+        if self.cancelled.load(Ordering::Relaxed) {
+            songs.clear();
+        }
+        self.found += songs.len();
+        songs
+    }
+
+    /// Asks the scan thread to stop; anything it has not delivered yet is discarded.
+    pub fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.finished = true;
+    }
+
+    pub fn scanned(&self) -> usize {
+        self.scanned
+    }
+
+    pub fn found(&self) -> usize {
+        self.found
+    }
+
+    /// Files skipped so far because they couldn't be decoded.
+    pub fn unreadable(&self) -> &[PathBuf] {
+        &self.unreadable
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
\ No newline at end of file
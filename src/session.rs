@@ -0,0 +1,158 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::player::{PlaybackMode, RepeatMode};
+use crate::playlist::Song;
+use crate::stream::is_stream_url;
+use crate::utils::{load_config_json, save_config_json};
+
+const SESSION_FILE: &str = "session.json";
+const WINDOW_FILE: &str = "window.json";
+
+/// A named playlist as stored in the session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedPlaylist {
+    pub name: String,
+    pub songs: Vec<Song>,
+}
+
+/// Snapshot of the player taken on exit so the next launch can pick up where the
+/// user left off.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Songs of the current playlist, as shown in the playlist panel.
+    pub songs: Vec<Song>,
+    /// Every playlist, including the current one. Empty in sessions saved before named
+    /// playlists existed.
+    pub playlists: Vec<SavedPlaylist>,
+    pub current_playlist: Option<String>,
+    pub selected_song_index: Option<usize>,
+    /// Position in the loaded track, or `None` if nothing was playing or paused.
+    pub position: Option<Duration>,
+    pub was_playing: bool,
+    /// Volume on exit; `None` in sessions written before it was saved, in which case
+    /// the startup volume from the settings applies.
+    pub volume: Option<f32>,
+    pub muted: bool,
+    pub mode: PlaybackMode,
+    pub repeat_mode: RepeatMode,
+    /// File paths of the songs in "Up Next", in play order.
+    pub queue: Vec<String>,
+    /// Where tracks were left off, by file path.
+    pub resume_positions: HashMap<String, Duration>,
+}
+
+impl Session {
+    pub fn load() -> Option<Self> {
+        let session: Self = load_config_json(SESSION_FILE)?;
+        info!("Restoring session with {} songs", session.songs.len());
+        Some(session)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = save_config_json(SESSION_FILE, self)?;
+        info!("Saved session to {}", path.display());
+        Ok(())
+    }
+
+    /// Drops songs whose files have been deleted or moved since the session was saved,
+    /// from the current song list and every playlist, and returns how many went. The
+    /// selection follows its song, or is cleared along with the saved position if that
+    /// song went. Internet streams are always kept.
+    ///
+    /// If no file at all can be found, the music is most likely on a drive that isn't
+    /// mounted right now, so nothing is dropped.
+    pub fn drop_missing_files(&mut self) -> usize {
+        let is_missing = |song: &Song| !is_stream_url(&song.file_path) && !Path::new(&song.file_path).exists();
+        let all_songs = || self.songs.iter().chain(self.playlists.iter().flat_map(|p| &p.songs));
+        let local = all_songs().filter(|s| !is_stream_url(&s.file_path)).count();
+        let missing = all_songs().filter(|s| is_missing(s)).count();
+        if missing == 0 {
+            return 0;
+        }
+        if missing == local {
+            warn!("None of the {} files in the session were found; keeping them in case the drive is just not mounted", local);
+            return 0;
+        }
+
+        let selected = self.selected_song_index.and_then(|i| self.songs.get(i)).map(|s| s.file_path.clone());
+        self.songs.retain(|s| !is_missing(s));
+        for playlist in &mut self.playlists {
+            playlist.songs.retain(|s| !is_missing(s));
+        }
+        self.selected_song_index = selected.and_then(|path| self.songs.iter().position(|s| s.file_path == path));
+        if self.selected_song_index.is_none() {
+            self.position = None;
+            self.was_playing = false;
+        }
+        warn!("Dropped {} songs from the session whose files no longer exist", missing);
+        missing
+    }
+}
+
+/// Size, position and maximized state of the main window, in points, saved on exit and
+/// applied to the viewport on the next launch.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Outer position; `None` leaves placement to the window manager.
+    pub position: Option<[f32; 2]>,
+    pub size: [f32; 2],
+    pub maximized: bool,
+    /// Size of the monitor the window was on, to notice when that monitor is gone.
+    pub monitor_size: Option<[f32; 2]>,
+}
+
+impl WindowGeometry {
+    pub fn load() -> Option<Self> {
+        load_config_json(WINDOW_FILE)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_config_json(WINDOW_FILE, self)?;
+        Ok(())
+    }
+
+    /// Reads the current geometry from `ctx`, starting from `previous` so the normal
+    /// size and position are kept while the window is maximized.
+    pub fn capture(ctx: &egui::Context, previous: Option<Self>) -> Option<Self> {
+        ctx.input(|i| {
+            let viewport = i.viewport();
+            let maximized = viewport.maximized.unwrap_or(false);
+            let monitor_size = viewport.monitor_size.map(|s| [s.x, s.y]);
+            if maximized || viewport.minimized == Some(true) {
+                return previous.map(|p| Self { maximized, ..p });
+            }
+            let size = viewport.inner_rect?.size();
+            Some(Self {
+                position: viewport.outer_rect.map(|r| [r.min.x, r.min.y]),
+                size: [size.x, size.y],
+                maximized,
+                monitor_size: monitor_size.or(previous.and_then(|p| p.monitor_size)),
+            })
+        })
+    }
+
+    /// The position and size to use on a monitor of `monitor` size. When the window was
+    /// saved on a different monitor (likely one that has since been disconnected) it is
+    /// pulled back inside this one so it can't open off-screen.
+    pub fn fit_to_monitor(&self, monitor: [f32; 2]) -> ([f32; 2], [f32; 2]) {
+        let position = self.position.unwrap_or_default();
+        let same_monitor = self
+            .monitor_size
+            .is_some_and(|saved| (saved[0] - monitor[0]).abs() < 1.0 && (saved[1] - monitor[1]).abs() < 1.0);
+        if same_monitor {
+            return (position, self.size);
+        }
+        let size = [self.size[0].min(monitor[0]), self.size[1].min(monitor[1])];
+        let position = [
+            position[0].clamp(0.0, monitor[0] - size[0]),
+            position[1].clamp(0.0, monitor[1] - size[1]),
+        ];
+        (position, size)
+    }
+}
\ No newline at end of file
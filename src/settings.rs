@@ -0,0 +1,187 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::equalizer::EQ_BANDS;
+use crate::now_playing::NowPlayingFormat;
+use crate::player::PlaylistSort;
+use crate::scanner::ScanOptions;
+use crate::utils::{has_extension, load_config_json, save_config_json, SUPPORTED_EXTENSIONS};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    Dark,
+    Light,
+    /// Follow the operating system's light/dark preference.
+    System,
+}
+
+impl Theme {
+    /// The next theme for the header toggle button.
+    pub fn cycle(self) -> Self {
+        match self {
+            Theme::Dark => Theme::Light,
+            Theme::Light => Theme::System,
+            Theme::System => Theme::Dark,
+        }
+    }
+}
+
+/// How the playlist panel lays out its songs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PlaylistView {
+    List,
+    /// Collapsible sections per album.
+    Album,
+    /// Collapsible sections per artist.
+    Artist,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub default_volume: f32,
+    pub crossfade_secs: f32,
+    /// Fade applied when starting, pausing, resuming or stopping playback.
+    pub fade_secs: f32,
+    pub auto_advance_delay_secs: f32,
+    /// Only pause between songs from different albums.
+    pub delay_between_albums_only: bool,
+    pub theme: Theme,
+    /// File extensions picked up when adding files. Besides the supported formats this
+    /// can hold extensions the user added by hand.
+    pub enabled_formats: Vec<String>,
+    /// Folder that saved playlists store paths relative to, so they keep working when
+    /// the library moves or is shared with another computer.
+    pub library_root: Option<String>,
+    /// Depth, hidden-file and symlink limits for "Add Folder" and dropped folders.
+    pub scan: ScanOptions,
+    pub normalize_volume: bool,
+    /// Normalize with album gain where songs are tagged with it.
+    pub album_gain: bool,
+    pub resume_playing_on_launch: bool,
+    /// Pick each track up where it was left, for positions past `resume_after_secs`.
+    pub resume_tracks: bool,
+    pub resume_after_secs: f32,
+    /// Last sort picked in the playlist header, marked there on the next launch.
+    pub playlist_sort: Option<PlaylistSort>,
+    pub playlist_view: PlaylistView,
+    /// Show each song's album after the artist in the playlist rows.
+    pub show_album: bool,
+    /// Show the time left instead of the total length next to the elapsed time.
+    pub show_remaining: bool,
+    pub eq_enabled: bool,
+    /// Gain in dB for each equalizer band.
+    pub eq_gains: Vec<f32>,
+    /// Output device picked in the controls panel; `None` uses the system default.
+    pub output_device: Option<String>,
+    /// Bass and treble tone controls in dB.
+    pub bass_db: f32,
+    pub treble_db: f32,
+    /// Left-right balance, -1.0 (full left) to 1.0 (full right).
+    pub balance: f32,
+    /// Write the current track to a file for streaming overlays.
+    pub now_playing_enabled: bool,
+    pub now_playing_format: NowPlayingFormat,
+    /// Custom location for the now-playing file; `None` puts it in the config directory.
+    pub now_playing_path: Option<String>,
+    /// Include the cover art's path in the JSON now-playing file.
+    pub now_playing_art: bool,
+    /// Sum all channels to mono.
+    pub mono: bool,
+    /// Playback speed, 1.0 being normal.
+    pub playback_speed: f32,
+    pub spectrum_enabled: bool,
+    pub meter_enabled: bool,
+    pub spectrum_bars: usize,
+    /// How slowly the spectrum bars fall back, from 0.0 (instantly) to 0.95.
+    pub spectrum_smoothing: f32,
+    /// Apply the EQ, tone, balance, mono and normalization settings to WAV exports.
+    pub export_effects: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            default_volume: 0.5,
+            crossfade_secs: 0.0,
+            fade_secs: 0.3,
+            auto_advance_delay_secs: 0.0,
+            delay_between_albums_only: false,
+            theme: Theme::Dark,
+            enabled_formats: SUPPORTED_EXTENSIONS.iter().map(|s| s.to_string()).collect(),
+            scan: ScanOptions::default(),
+            library_root: None,
+            normalize_volume: false,
+            album_gain: false,
+            resume_playing_on_launch: false,
+            resume_tracks: false,
+            resume_after_secs: 60.0,
+            playlist_sort: None,
+            playlist_view: PlaylistView::List,
+            show_album: false,
+            show_remaining: false,
+            eq_enabled: false,
+            eq_gains: vec![0.0; EQ_BANDS.len()],
+            output_device: None,
+            bass_db: 0.0,
+            treble_db: 0.0,
+            balance: 0.0,
+            now_playing_enabled: false,
+            now_playing_format: NowPlayingFormat::Text,
+            now_playing_path: None,
+            now_playing_art: false,
+            mono: false,
+            playback_speed: 1.0,
+            spectrum_enabled: false,
+            meter_enabled: false,
+            spectrum_bars: 32,
+            spectrum_smoothing: 0.7,
+            export_effects: true,
+        }
+    }
+}
+
+impl Settings {
+    /// The threshold for `Player::set_resume_after`, or `None` when resuming is off.
+    pub fn resume_after(&self) -> Option<std::time::Duration> {
+        self.resume_tracks.then(|| std::time::Duration::from_secs_f32(self.resume_after_secs))
+    }
+
+    pub fn library_root(&self) -> Option<std::path::PathBuf> {
+        self.library_root.as_ref().map(std::path::PathBuf::from)
+    }
+
+    /// Loads the settings file, falling back to defaults when it is missing or unreadable.
+    pub fn load() -> Self {
+        load_config_json(SETTINGS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = save_config_json(SETTINGS_FILE, self)?;
+        info!("Saved settings to {}", path.display());
+        Ok(())
+    }
+
+    pub fn is_format_enabled(&self, extension: &str) -> bool {
+        self.enabled_formats.iter().any(|f| f.eq_ignore_ascii_case(extension))
+    }
+
+    /// Whether `path` has one of the enabled extensions.
+    pub fn accepts_file(&self, path: &std::path::Path) -> bool {
+        has_extension(path, &self.enabled_formats)
+    }
+
+    /// Enables a user-supplied extension such as "opus" or ".WMA". Returns false if it
+    /// isn't a plausible extension or is already enabled.
+    pub fn add_format(&mut self, extension: &str) -> bool {
+        let extension = extension.trim().trim_start_matches('.').to_lowercase();
+        if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) || self.is_format_enabled(&extension) {
+            return false;
+        }
+        self.enabled_formats.push(extension);
+        true
+    }
+} 
\ No newline at end of file
@@ -0,0 +1,273 @@
+use rodio::Source;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Samples per FFT; about 46 ms of audio at 44.1 kHz. Must be a power of two.
+pub const FFT_SIZE: usize = 2048;
+
+/// Range offered for the number of bars.
+pub const SPECTRUM_BAR_RANGE: std::ops::RangeInclusive<usize> = 8..=96;
+
+/// Frames a tapped source collects before handing them over, so the audio thread
+/// only touches the shared buffer every few milliseconds.
+const TAP_CHUNK: usize = 512;
+
+/// How often the analyzer recomputes the bars.
+const ANALYSIS_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Frequency range covered by the bars, spread on a log scale.
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+
+/// Levels at or below this are drawn as empty bars.
+const FLOOR_DB: f32 = -70.0;
+
+/// Recent samples copied out of the playing source for the analyzer. The tap never
+/// changes the samples passing through, so it adds no latency.
+#[derive(Default)]
+pub struct SpectrumTap {
+    enabled: AtomicBool,
+    recent: Mutex<Recent>,
+}
+
+#[derive(Default)]
+struct Recent {
+    /// The last `FFT_SIZE` frames, mixed down to mono.
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+    /// Set when samples arrive and cleared when the analyzer reads them, so the bars
+    /// fall away while nothing is playing.
+    fresh: bool,
+}
+
+impl SpectrumTap {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.recent.lock().unwrap().samples.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn push(&self, samples: &[f32], sample_rate: u32) {
+        // The audio thread never waits on the analyzer; a skipped chunk only costs
+        // one slightly stale set of bars
+        let Ok(mut recent) = self.recent.try_lock() else { return };
+        if recent.sample_rate != sample_rate {
+            recent.samples.clear();
+            recent.sample_rate = sample_rate;
+        }
+        recent.samples.extend(samples);
+        let excess = recent.samples.len().saturating_sub(FFT_SIZE);
+        recent.samples.drain(..excess);
+        recent.fresh = true;
+    }
+
+    /// The most recent window of samples, if new ones arrived since the last call.
+    fn take_window(&self) -> Option<(Vec<f32>, u32)> {
+        let mut recent = self.recent.lock().unwrap();
+        if !recent.fresh || recent.samples.len() < FFT_SIZE {
+            return None;
+        }
+        recent.fresh = false;
+        Some((recent.samples.iter().copied().collect(), recent.sample_rate))
+    }
+}
+
+/// Copies the samples of the wrapped source into a `SpectrumTap` while it's enabled.
+pub struct Tapped<S> {
+    inner: S,
+    tap: Arc<SpectrumTap>,
+    pending: Vec<f32>,
+    frame_sum: f32,
+    channel: u16,
+}
+
+impl<S: Source<Item = i16>> Tapped<S> {
+    pub fn new(inner: S, tap: Arc<SpectrumTap>) -> Self {
+        Self {
+            inner,
+            tap,
+            pending: Vec::with_capacity(TAP_CHUNK),
+            frame_sum: 0.0,
+            channel: 0,
+        }
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Tapped<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next()?;
+        if !self.tap.is_enabled() {
+            return Some(sample);
+        }
+        let channels = self.inner.channels().max(1);
+        self.frame_sum += sample as f32 / i16::MAX as f32;
+        self.channel += 1;
+        if self.channel >= channels {
+            self.pending.push(self.frame_sum / channels as f32);
+            self.frame_sum = 0.0;
+            self.channel = 0;
+            if self.pending.len() >= TAP_CHUNK {
+                self.tap.push(&self.pending, self.inner.sample_rate());
+                self.pending.clear();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Tapped<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// In-place iterative radix-2 FFT. Both slices must have the same power-of-two length.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Levels in `0.0..=1.0` for `bar_count` log-spaced frequency bands of `samples`.
+pub fn compute_bars(samples: &[f32], sample_rate: u32, bar_count: usize) -> Vec<f32> {
+    let n = samples.len();
+    if n < 2 || bar_count == 0 {
+        return vec![0.0; bar_count];
+    }
+    // Hann window, to keep each band from leaking into its neighbours
+    let mut re: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / (n - 1) as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+
+    let bin_width = sample_rate as f32 / n as f32;
+    let max_frequency = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+    let ratio = (max_frequency / MIN_FREQUENCY).max(1.0);
+    // The window halves the amplitude and the other half of a sine lands in the
+    // mirrored bin, so this brings a full-scale sine to 0 dB
+    let scale = 4.0 / n as f32;
+    (0..bar_count)
+        .map(|bar| {
+            let low = MIN_FREQUENCY * ratio.powf(bar as f32 / bar_count as f32);
+            let high = MIN_FREQUENCY * ratio.powf((bar + 1) as f32 / bar_count as f32);
+            let first = ((low / bin_width) as usize).clamp(1, n / 2 - 1);
+            let last = ((high / bin_width) as usize).clamp(first + 1, n / 2);
+            let peak = (first..last).map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt()).fold(0.0, f32::max) * scale;
+            let db = 20.0 * peak.max(1e-9).log10();
+            ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+struct AnalyzerState {
+    running: AtomicBool,
+    /// Bar count and smoothing factor.
+    options: Mutex<(usize, f32)>,
+    bars: Mutex<Vec<f32>>,
+}
+
+/// Turns the tapped samples into bar levels on a background thread, which runs until
+/// the analyzer is dropped.
+pub struct SpectrumAnalyzer {
+    state: Arc<AnalyzerState>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn start(tap: Arc<SpectrumTap>, bar_count: usize, smoothing: f32) -> Self {
+        let state = Arc::new(AnalyzerState {
+            running: AtomicBool::new(true),
+            options: Mutex::new((bar_count, smoothing.clamp(0.0, 0.95))),
+            bars: Mutex::new(vec![0.0; bar_count]),
+        });
+        let worker = state.clone();
+        thread::spawn(move || {
+            while worker.running.load(Ordering::Relaxed) {
+                thread::sleep(ANALYSIS_INTERVAL);
+                let (bar_count, smoothing) = *worker.options.lock().unwrap();
+                let target = match tap.take_window() {
+                    Some((samples, sample_rate)) => compute_bars(&samples, sample_rate, bar_count),
+                    None => vec![0.0; bar_count],
+                };
+                let mut bars = worker.bars.lock().unwrap();
+                if bars.len() != bar_count {
+                    *bars = vec![0.0; bar_count];
+                }
+                // Bars jump up with the music and fall back at a rate set by the smoothing
+                for (bar, target) in bars.iter_mut().zip(target) {
+                    *bar = if target > *bar { target } else { *bar * smoothing + target * (1.0 - smoothing) };
+                }
+            }
+        });
+        Self { state }
+    }
+
+    pub fn set_options(&self, bar_count: usize, smoothing: f32) {
+        *self.state.options.lock().unwrap() = (bar_count, smoothing.clamp(0.0, 0.95));
+    }
+
+    pub fn bars(&self) -> Vec<f32> {
+        self.state.bars.lock().unwrap().clone()
+    }
+}
+
+impl Drop for SpectrumAnalyzer {
+    fn drop(&mut self) {
+        self.state.running.store(false, Ordering::Relaxed);
+    }
+}
\ No newline at end of file
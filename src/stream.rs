@@ -0,0 +1,352 @@
+use crate::audio::PlaybackError;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Redirects (and playlist files pointing at the real stream) followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A server that sends nothing for this long is treated as gone.
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How much already-read data is kept behind the read position for the decoder to seek
+/// back into. Nothing is dropped until reading gets this far, which covers the probe.
+const KEEP_BEHIND: u64 = 4 * 1024 * 1024;
+
+/// Old data is dropped in steps of at least this much, rather than on every read.
+const DROP_STEP: u64 = 1024 * 1024;
+
+/// Whether `path` is an HTTP(S) URL rather than a file on disk.
+pub fn is_stream_url(path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Whether `url` uses HTTPS, which streams can't be played from yet: there's no TLS
+/// support, so only plain `http://` URLs can be added.
+pub fn is_https_url(url: &str) -> bool {
+    url.to_ascii_lowercase().starts_with("https://")
+}
+
+/// What the server said about the stream.
+#[derive(Debug, Clone, Default)]
+pub struct StreamInfo {
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+}
+
+/// Data downloaded so far, shared between the download thread and the reader.
+#[derive(Default)]
+struct Buffer {
+    state: Mutex<BufferState>,
+    ready: Condvar,
+    cancelled: AtomicBool,
+}
+
+#[derive(Default)]
+struct BufferState {
+    data: Vec<u8>,
+    /// Stream offset of `data[0]`; everything before it has been dropped.
+    start: u64,
+    /// The download has ended, normally or not.
+    done: bool,
+    /// The reader is blocked waiting for more data.
+    waiting: bool,
+}
+
+/// Downloads an HTTP stream on a background thread and reads it like a file, so it can
+/// be handed to rodio's `Decoder`. Reads past what has arrived block until more data
+/// comes in. Only the last few MB behind the read position are kept, so a radio stream
+/// left playing doesn't grow without bound; seeking back further than that fails.
+pub struct HttpStream {
+    buffer: Arc<Buffer>,
+    pos: u64,
+    length: Option<u64>,
+}
+
+/// Cheap handle for checking on an `HttpStream` after it has been given to the decoder.
+#[derive(Clone)]
+pub struct StreamStatus(Arc<Buffer>);
+
+impl StreamStatus {
+    /// Whether playback is held up waiting for data from the network.
+    pub fn is_buffering(&self) -> bool {
+        let state = self.0.state.lock().unwrap();
+        state.waiting && !state.done
+    }
+}
+
+impl HttpStream {
+    /// Connects to `url`, following redirects, and starts downloading. Only plain
+    /// `http://` URLs are supported.
+    pub fn open(url: &str) -> Result<(Self, StreamInfo), PlaybackError> {
+        let (reader, info) = connect(url)?;
+        let buffer = Arc::new(Buffer::default());
+        let download = buffer.clone();
+        let url = url.to_string();
+        thread::spawn(move || download_into(reader, &download, &url));
+        let stream = Self {
+            buffer,
+            pos: 0,
+            length: info.content_length,
+        };
+        Ok((stream, info))
+    }
+
+    pub fn status(&self) -> StreamStatus {
+        StreamStatus(self.buffer.clone())
+    }
+}
+
+impl Drop for HttpStream {
+    fn drop(&mut self) {
+        self.buffer.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.buffer.state.lock().unwrap();
+        while self.pos >= state.start + state.data.len() as u64 && !state.done {
+            state.waiting = true;
+            state = self.buffer.ready.wait(state).unwrap();
+        }
+        state.waiting = false;
+        let start = (self.pos - state.start).min(state.data.len() as u64) as usize;
+        let n = buf.len().min(state.data.len() - start);
+        buf[..n].copy_from_slice(&state.data[start..start + n]);
+        self.pos += n as u64;
+
+        let behind = self.pos - state.start;
+        if behind >= KEEP_BEHIND + DROP_STEP {
+            let drop = (behind - KEEP_BEHIND) as usize;
+            state.data.drain(..drop);
+            state.start += drop as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl Seek for HttpStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let state = self.buffer.state.lock().unwrap();
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset as i64),
+            SeekFrom::Current(delta) => Some(self.pos as i64 + delta),
+            SeekFrom::End(delta) => {
+                // Without a Content-Length the end is only known once the download is done
+                let length = self.length.or_else(|| state.done.then_some(state.start + state.data.len() as u64));
+                length.map(|length| length as i64 + delta)
+            }
+        };
+        match target {
+            Some(target) if target >= 0 && (target as u64) < state.start => {
+                Err(io::Error::new(io::ErrorKind::Unsupported, "seek back to data that has already been dropped"))
+            }
+            Some(target) if target >= 0 => {
+                self.pos = target as u64;
+                Ok(self.pos)
+            }
+            Some(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the stream")),
+            None => Err(io::Error::new(io::ErrorKind::Unsupported, "the stream's length isn't known")),
+        }
+    }
+}
+
+fn download_into(mut reader: impl Read, buffer: &Buffer, url: &str) {
+    let mut chunk = [0u8; 16 * 1024];
+    loop {
+        if buffer.cancelled.load(Ordering::Relaxed) {
+            info!("Stopped downloading {}", url);
+            break;
+        }
+        match reader.read(&mut chunk) {
+            Ok(0) => {
+                info!("Finished downloading {}", url);
+                break;
+            }
+            Ok(n) => {
+                buffer.state.lock().unwrap().data.extend_from_slice(&chunk[..n]);
+                buffer.ready.notify_all();
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                warn!("Stream {} broke off: {}", url, e);
+                break;
+            }
+        }
+    }
+    buffer.state.lock().unwrap().done = true;
+    buffer.ready.notify_all();
+}
+
+/// Sends the request and reads the response headers, following redirects and
+/// playlist files (M3U/PLS, as internet radio links often are) to the audio itself.
+fn connect(url: &str) -> Result<(BufReader<TcpStream>, StreamInfo), PlaybackError> {
+    let mut url = url.trim().to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let (host, port, path) = parse_url(&url)?;
+        let network = |e: io::Error| PlaybackError::Network(format!("{}: {}", host, e));
+        let addr = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(network)?
+            .next()
+            .ok_or_else(|| PlaybackError::Network(format!("could not resolve {}", host)))?;
+        let mut socket = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(network)?;
+        socket.set_read_timeout(Some(READ_TIMEOUT)).map_err(network)?;
+        // HTTP/1.0 so the body isn't chunked and the server closes the connection at the end
+        let request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: rust_music_player\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+            path, host
+        );
+        io::Write::write_all(&mut socket, request.as_bytes()).map_err(network)?;
+
+        let mut reader = BufReader::new(socket);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).map_err(network)?;
+        // Shoutcast servers answer "ICY 200 OK" instead of an HTTP status line
+        let status: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| PlaybackError::Network(format!("unexpected response from {}", host)))?;
+
+        let mut info = StreamInfo::default();
+        let mut location = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).map_err(network)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else { continue };
+            let value = value.trim().to_string();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-type" => info.content_type = Some(value.to_ascii_lowercase()),
+                "content-length" => info.content_length = value.parse().ok(),
+                "location" => location = Some(value),
+                _ => {}
+            }
+        }
+
+        match status {
+            200..=299 => {}
+            301 | 302 | 303 | 307 | 308 => {
+                let location = location.ok_or_else(|| PlaybackError::Network("redirect without a location".to_string()))?;
+                url = resolve_location(&url, &location);
+                info!("Redirected to {}", url);
+                continue;
+            }
+            _ => return Err(PlaybackError::Network(format!("the server answered {}", status_line.trim()))),
+        }
+
+        let content_type = info.content_type.as_deref().unwrap_or("");
+        if content_type.starts_with("text/html") {
+            return Err(PlaybackError::UnsupportedFormat("the URL points to a web page, not audio".to_string()));
+        }
+        if content_type.contains("mpegurl") || content_type.contains("scpls") {
+            url = first_playlist_entry(&mut reader).ok_or_else(|| PlaybackError::UnsupportedFormat("the playlist has no stream URLs".to_string()))?;
+            info!("Following playlist entry {}", url);
+            continue;
+        }
+        return Ok((reader, info));
+    }
+    Err(PlaybackError::Network("too many redirects".to_string()))
+}
+
+/// Splits an `http://` URL into host, port and path.
+fn parse_url(url: &str) -> Result<(String, u16, String), PlaybackError> {
+    if is_https_url(url) {
+        return Err(PlaybackError::Network("HTTPS streams aren't supported yet; try the http:// address".to_string()));
+    }
+    let rest = url
+        .get(..7)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("http://"))
+        .map(|_| &url[7..])
+        .ok_or_else(|| PlaybackError::Network(format!("not an http:// URL: {}", url)))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| PlaybackError::Network(format!("bad port in {}", url)))?),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(PlaybackError::Network(format!("no host in {}", url)));
+    }
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+/// Turns a redirect's `Location` (absolute, or a path on the same server) into a URL.
+fn resolve_location(current: &str, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+    let after_scheme = current.find("://").map_or(0, |i| i + 3);
+    let origin_end = current[after_scheme..].find('/').map_or(current.len(), |i| after_scheme + i);
+    format!("{}/{}", &current[..origin_end], location.trim_start_matches('/'))
+}
+
+/// Reads an M3U or PLS body and returns the first stream URL in it.
+fn first_playlist_entry(reader: &mut BufReader<TcpStream>) -> Option<String> {
+    let mut body = String::new();
+    reader.take(64 * 1024).read_to_string(&mut body).ok()?;
+    body.lines()
+        .map(|line| line.split_once('=').filter(|(key, _)| key.trim().to_ascii_lowercase().starts_with("file")).map_or(line, |(_, value)| value))
+        .map(str::trim)
+        .find(|line| is_stream_url(line))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stream fed from `data` in memory instead of a socket.
+    fn stream_of(data: Vec<u8>) -> HttpStream {
+        let length = Some(data.len() as u64);
+        let buffer = Arc::new(Buffer::default());
+        download_into(io::Cursor::new(data), &buffer, "test");
+        HttpStream { buffer, pos: 0, length }
+    }
+
+    #[test]
+    fn probing_can_seek_back_to_the_start() {
+        let mut stream = stream_of((0..=255).cycle().take(64 * 1024).collect());
+        let mut head = [0; 4096];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(stream.seek(SeekFrom::Start(0)).unwrap(), 0);
+        let mut again = [0; 4096];
+        stream.read_exact(&mut again).unwrap();
+        assert_eq!(head, again);
+    }
+
+    #[test]
+    fn old_data_is_dropped_and_cannot_be_seeked_to() {
+        let len = 3 * KEEP_BEHIND;
+        let mut stream = stream_of((0..len).map(|i| (i % 251) as u8).collect());
+        let mut chunk = vec![0; 64 * 1024];
+        while stream.read(&mut chunk).unwrap() > 0 {}
+
+        let state = stream.buffer.state.lock().unwrap();
+        assert!(state.start > 0 && state.data.len() as u64 <= KEEP_BEHIND + DROP_STEP);
+        assert_eq!(state.start + state.data.len() as u64, len);
+        drop(state);
+
+        let err = stream.seek(SeekFrom::Start(0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        // Up to KEEP_BEHIND back is still there, and reads back the right bytes
+        let target = len - KEEP_BEHIND;
+        assert_eq!(stream.seek(SeekFrom::Start(target)).unwrap(), target);
+        let mut byte = [0];
+        stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], (target % 251) as u8);
+        assert_eq!(stream.seek(SeekFrom::End(0)).unwrap(), len);
+    }
+}
\ No newline at end of file
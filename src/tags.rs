@@ -0,0 +1,448 @@
+use crate::playlist::Song;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::info;
+
+#[derive(Debug, Error)]
+pub enum TagError {
+    #[error("writing tags isn't supported for {0} files")]
+    UnsupportedFormat(String),
+    #[error("{0} is read-only")]
+    ReadOnly(String),
+    #[error("the existing tags can't be read: {0}")]
+    Corrupt(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Writes `song`'s title, artist and album into the tags of the file at `path`,
+/// keeping every other tag as it was. MP3 (ID3v2) and FLAC (Vorbis comments) are
+/// supported. The file is rewritten through a temporary copy so a failure part-way
+/// never leaves it truncated. A symlink is followed and the file it points at tagged,
+/// keeping its permissions.
+pub fn write_tags(path: &str, song: &Song) -> Result<(), TagError> {
+    let extension = Path::new(path).extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    // Renaming over a symlink would replace the link and leave the real file as it was
+    let target = fs::canonicalize(path)?;
+    let data = fs::read(&target)?;
+    let permissions = fs::metadata(&target)?.permissions();
+    if permissions.readonly() {
+        return Err(TagError::ReadOnly(path.to_string()));
+    }
+    let tagged = match extension.as_str() {
+        "mp3" => write_id3(&data, song)?,
+        "flac" => write_flac(&data, song)?,
+        _ => return Err(TagError::UnsupportedFormat(format!(".{}", extension))),
+    };
+
+    let mut temp = target.clone().into_os_string();
+    temp.push(".tagging");
+    let temp = PathBuf::from(temp);
+    let result = fs::write(&temp, &tagged)
+        .and_then(|_| fs::set_permissions(&temp, permissions))
+        .and_then(|_| fs::rename(&temp, &target));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(match e.kind() {
+            ErrorKind::PermissionDenied => TagError::ReadOnly(path.to_string()),
+            _ => TagError::Io(e),
+        });
+    }
+    info!("Wrote tags to {}", path);
+    Ok(())
+}
+
+/// Text frames this editor owns: (ID3v2 frame id, value).
+fn id3_text_frames(song: &Song) -> [(&'static [u8; 4], Option<&str>); 3] {
+    [
+        (b"TIT2", Some(song.title.as_str())),
+        (b"TPE1", Some(song.artist.as_str())),
+        (b"TALB", song.album.as_deref()),
+    ]
+}
+
+fn read_syncsafe(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |acc, &b| (acc << 7) | (b & 0x7f) as usize)
+}
+
+fn syncsafe(size: usize) -> [u8; 4] {
+    [(size >> 21) as u8 & 0x7f, (size >> 14) as u8 & 0x7f, (size >> 7) as u8 & 0x7f, size as u8 & 0x7f]
+}
+
+/// Replaces the title, artist and album frames of the ID3v2 tag at the start of
+/// `data` (adding a v2.3 tag if there is none) and returns the new file contents.
+fn write_id3(data: &[u8], song: &Song) -> Result<Vec<u8>, TagError> {
+    let mut version = 3;
+    let mut frames = Vec::new();
+    let mut audio = data;
+
+    if data.len() >= 10 && &data[..3] == b"ID3" {
+        version = data[3];
+        let flags = data[5];
+        let tag_end = 10 + read_syncsafe(&data[6..10]);
+        // A v2.4 footer is dropped along with the old tag; the new one has none
+        let audio_start = if flags & 0x10 != 0 { tag_end + 10 } else { tag_end };
+        if !(3..=4).contains(&version) {
+            return Err(TagError::Corrupt(format!("ID3v2.{} tags can't be rewritten", version)));
+        }
+        // Unsynchronised and extended-header tags are rare and not worth supporting
+        if flags & 0xc0 != 0 || audio_start > data.len() {
+            return Err(TagError::Corrupt("unsupported ID3v2 tag layout".to_string()));
+        }
+        let mut pos = 10;
+        while pos + 10 <= tag_end && data[pos] != 0 {
+            let size_bytes = &data[pos + 4..pos + 8];
+            let size = if version == 4 {
+                read_syncsafe(size_bytes)
+            } else {
+                u32::from_be_bytes(size_bytes.try_into().unwrap()) as usize
+            };
+            let end = pos + 10 + size;
+            if end > tag_end {
+                return Err(TagError::Corrupt("ID3v2 frame runs past the end of the tag".to_string()));
+            }
+            let owned = id3_text_frames(song).iter().any(|(id, _)| &data[pos..pos + 4] == *id);
+            if !owned {
+                frames.extend_from_slice(&data[pos..end]);
+            }
+            pos = end;
+        }
+        audio = &data[audio_start..];
+    }
+
+    for (id, value) in id3_text_frames(song) {
+        let Some(value) = value else { continue };
+        let mut body = Vec::new();
+        if version == 4 {
+            // UTF-8
+            body.push(3);
+            body.extend_from_slice(value.as_bytes());
+        } else {
+            // UTF-16 with a byte order mark, the only Unicode encoding v2.3 has
+            body.extend_from_slice(&[1, 0xff, 0xfe]);
+            body.extend(value.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+        frames.extend_from_slice(id);
+        if version == 4 {
+            frames.extend_from_slice(&syncsafe(body.len()));
+        } else {
+            frames.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        }
+        frames.extend_from_slice(&[0, 0]);
+        frames.extend_from_slice(&body);
+    }
+
+    let mut out = Vec::with_capacity(10 + frames.len() + audio.len());
+    out.extend_from_slice(&[b'I', b'D', b'3', version, 0, 0]);
+    out.extend_from_slice(&syncsafe(frames.len()));
+    out.extend_from_slice(&frames);
+    out.extend_from_slice(audio);
+    Ok(out)
+}
+
+const FLAC_STREAMINFO: u8 = 0;
+const FLAC_VORBIS_COMMENT: u8 = 4;
+
+/// Replaces the TITLE, ARTIST and ALBUM comments of a FLAC file (adding a comment
+/// block if there is none) and returns the new file contents.
+fn write_flac(data: &[u8], song: &Song) -> Result<Vec<u8>, TagError> {
+    if data.len() < 4 || &data[..4] != b"fLaC" {
+        return Err(TagError::Corrupt("not a FLAC file".to_string()));
+    }
+    let mut blocks: Vec<(u8, &[u8])> = Vec::new();
+    let mut pos = 4;
+    loop {
+        if pos + 4 > data.len() {
+            return Err(TagError::Corrupt("FLAC metadata is truncated".to_string()));
+        }
+        let header = data[pos];
+        let len = u32::from_be_bytes([0, data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let start = pos + 4;
+        if start + len > data.len() {
+            return Err(TagError::Corrupt("FLAC metadata block runs past the end of the file".to_string()));
+        }
+        blocks.push((header & 0x7f, &data[start..start + len]));
+        pos = start + len;
+        if header & 0x80 != 0 {
+            break;
+        }
+    }
+    let audio = &data[pos..];
+
+    let (vendor, mut comments) = match blocks.iter().find(|(kind, _)| *kind == FLAC_VORBIS_COMMENT) {
+        Some((_, body)) => parse_vorbis_comments(body)?,
+        None => ("rust_music_player".to_string(), Vec::new()),
+    };
+    let owned = ["TITLE", "ARTIST", "ALBUM"];
+    comments.retain(|c| !owned.iter().any(|key| c.split('=').next().is_some_and(|k| k.eq_ignore_ascii_case(key))));
+    comments.push(format!("TITLE={}", song.title));
+    comments.push(format!("ARTIST={}", song.artist));
+    if let Some(album) = &song.album {
+        comments.push(format!("ALBUM={}", album));
+    }
+
+    let mut comment_block = Vec::new();
+    comment_block.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    comment_block.extend_from_slice(vendor.as_bytes());
+    comment_block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in &comments {
+        comment_block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        comment_block.extend_from_slice(comment.as_bytes());
+    }
+
+    // Keep every other block, putting the comments straight after STREAMINFO
+    let mut new_blocks: Vec<(u8, &[u8])> = Vec::new();
+    for &(kind, body) in &blocks {
+        if kind == FLAC_VORBIS_COMMENT {
+            continue;
+        }
+        new_blocks.push((kind, body));
+        if kind == FLAC_STREAMINFO {
+            new_blocks.push((FLAC_VORBIS_COMMENT, &comment_block));
+        }
+    }
+
+    let mut out = Vec::with_capacity(data.len() + comment_block.len());
+    out.extend_from_slice(b"fLaC");
+    let last = new_blocks.len() - 1;
+    for (i, (kind, body)) in new_blocks.iter().enumerate() {
+        let len = body.len() as u32;
+        if len >= 1 << 24 {
+            return Err(TagError::Corrupt("FLAC metadata block is too large".to_string()));
+        }
+        out.push(if i == last { kind | 0x80 } else { *kind });
+        out.extend_from_slice(&len.to_be_bytes()[1..]);
+        out.extend_from_slice(body);
+    }
+    out.extend_from_slice(audio);
+    Ok(out)
+}
+
+fn parse_vorbis_comments(body: &[u8]) -> Result<(String, Vec<String>), TagError> {
+    let truncated = || TagError::Corrupt("Vorbis comment block is truncated".to_string());
+    let mut pos = 0;
+    let read_string = |pos: &mut usize| -> Result<String, TagError> {
+        let len_bytes = body.get(*pos..*pos + 4).ok_or_else(truncated)?;
+        let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+        let bytes = body.get(*pos + 4..*pos + 4 + len).ok_or_else(truncated)?;
+        *pos += 4 + len;
+        Ok(String::from_utf8_lossy(bytes).to_string())
+    };
+    let vendor = read_string(&mut pos)?;
+    let count_bytes = body.get(pos..pos + 4).ok_or_else(truncated)?;
+    let count = u32::from_le_bytes(count_bytes.try_into().unwrap());
+    pos += 4;
+    let comments = (0..count).map(|_| read_string(&mut pos)).collect::<Result<_, _>>()?;
+    Ok((vendor, comments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory for one test's files.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_music_player-tags-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn song(title: &str, artist: &str, album: Option<&str>) -> Song {
+        let mut song = Song::from_path(Path::new("unused.mp3"));
+        song.title = title.to_string();
+        song.artist = artist.to_string();
+        song.album = album.map(str::to_string);
+        song
+    }
+
+    /// Ten silent MPEG-1 layer III frames (128 kbps, 44.1 kHz, mono).
+    fn mp3_audio() -> Vec<u8> {
+        let mut frame = vec![0; 417];
+        frame[..4].copy_from_slice(&[0xff, 0xfb, 0x90, 0xc4]);
+        frame.repeat(10)
+    }
+
+    /// An ISO-8859-1 text frame.
+    fn id3_frame(version: u8, id: &[u8; 4], text: &str) -> Vec<u8> {
+        let body = [&[0], text.as_bytes()].concat();
+        let size = if version == 4 { syncsafe(body.len()) } else { (body.len() as u32).to_be_bytes() };
+        [&id[..], &size, &[0, 0], &body].concat()
+    }
+
+    fn id3_tag(version: u8, flags: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let frames = frames.concat();
+        [&[b'I', b'D', b'3', version, 0, flags][..], &syncsafe(frames.len()), &frames].concat()
+    }
+
+    fn flac_block(kind: u8, last: bool, body: &[u8]) -> Vec<u8> {
+        let header = if last { kind | 0x80 } else { kind };
+        [&[header][..], &(body.len() as u32).to_be_bytes()[1..], body].concat()
+    }
+
+    /// STREAMINFO for 16-bit stereo at 44.1 kHz with an unknown length.
+    fn streaminfo() -> Vec<u8> {
+        let packed: u64 = (44_100 << 44) | (1 << 41) | (15 << 36);
+        [&[0x10, 0, 0x10, 0, 0, 0, 0, 0, 0, 0][..], &packed.to_be_bytes(), &[0; 16]].concat()
+    }
+
+    fn vorbis_comments(vendor: &str, comments: &[&str]) -> Vec<u8> {
+        let mut body = [&(vendor.len() as u32).to_le_bytes()[..], vendor.as_bytes(), &(comments.len() as u32).to_le_bytes()].concat();
+        for comment in comments {
+            body.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            body.extend_from_slice(comment.as_bytes());
+        }
+        body
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    /// Writes `data` to `name` in `dir` and reads it back the way the playlist does.
+    fn read_back(dir: &Path, name: &str, data: &[u8]) -> Song {
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        Song::from_path(&path)
+    }
+
+    fn assert_new_tags(song: &Song) {
+        assert_eq!(song.title, "New Title");
+        assert_eq!(song.artist, "New Artist");
+        assert_eq!(song.album.as_deref(), Some("New Album"));
+    }
+
+    #[test]
+    fn id3v23_round_trip_keeps_other_frames_and_audio() {
+        let dir = temp_dir("id3v23");
+        let year = id3_frame(3, b"TYER", "2004");
+        let track = id3_frame(3, b"TRCK", "3/12");
+        let old_title = id3_frame(3, b"TIT2", "Old Title");
+        let data = [id3_tag(3, 0, &[old_title.clone(), year.clone(), track.clone()]), mp3_audio()].concat();
+
+        let out = write_id3(&data, &song("New Title", "New Artist", Some("New Album"))).unwrap();
+        assert_eq!(&out[..4], b"ID3\x03");
+        assert!(out.ends_with(&mp3_audio()));
+        assert!(contains(&out, &year) && contains(&out, &track));
+        assert!(!contains(&out, &old_title));
+
+        let song = read_back(&dir, "v23.mp3", &out);
+        assert_new_tags(&song);
+        assert_eq!((song.year, song.track_number), (Some(2004), Some(3)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn id3v24_round_trip_keeps_other_frames_and_audio() {
+        let dir = temp_dir("id3v24");
+        let date = id3_frame(4, b"TDRC", "2004-05-01");
+        let data = [id3_tag(4, 0, &[id3_frame(4, b"TPE1", "Old Artist"), date.clone()]), mp3_audio()].concat();
+
+        let out = write_id3(&data, &song("New Title", "New Artist", Some("New Album"))).unwrap();
+        assert_eq!(&out[..4], b"ID3\x04");
+        assert!(out.ends_with(&mp3_audio()));
+        assert!(contains(&out, &date));
+        assert!(!contains(&out, b"Old Artist"));
+
+        let song = read_back(&dir, "v24.mp3", &out);
+        assert_new_tags(&song);
+        assert_eq!(song.year, Some(2004));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mp3_without_a_tag_gets_one() {
+        let dir = temp_dir("id3-none");
+        let out = write_id3(&mp3_audio(), &song("New Title", "New Artist", Some("New Album"))).unwrap();
+        assert_eq!(&out[..4], b"ID3\x03");
+        assert!(out.ends_with(&mp3_audio()));
+        assert_new_tags(&read_back(&dir, "untagged.mp3", &out));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flac_round_trip_keeps_other_blocks_comments_and_audio() {
+        let dir = temp_dir("flac");
+        let application = [&b"test"[..], &[1, 2, 3, 4]].concat();
+        // A frame header matching STREAMINFO (4096 samples, 44.1 kHz, 16-bit stereo) with
+        // its CRC-8, so symphonia can sync on it when reading back
+        let audio = [&[0xff, 0xf8, 0xc9, 0x18, 0x00, 0xc2][..], &[0; 32]].concat();
+        let data = [
+            &b"fLaC"[..],
+            &flac_block(FLAC_STREAMINFO, false, &streaminfo()),
+            &flac_block(FLAC_VORBIS_COMMENT, false, &vorbis_comments("ref", &["TITLE=Old Title", "GENRE=Jazz", "album=Old Album"])),
+            &flac_block(2, false, &application),
+            &flac_block(1, true, &[0; 16]),
+            &audio,
+        ]
+        .concat();
+
+        let out = write_flac(&data, &song("New Title", "New Artist", Some("New Album"))).unwrap();
+        assert!(out.ends_with(&audio));
+        // STREAMINFO stays first, and the other blocks keep their contents
+        assert_eq!(&out[4..8 + 34], &flac_block(FLAC_STREAMINFO, false, &streaminfo())[..]);
+        assert!(contains(&out, &flac_block(2, false, &application)));
+        assert!(contains(&out, &flac_block(1, true, &[0; 16])));
+        let comment_len = u32::from_be_bytes([0, out[43], out[44], out[45]]) as usize;
+        assert_eq!(out[42], FLAC_VORBIS_COMMENT);
+        let (vendor, comments) = parse_vorbis_comments(&out[46..46 + comment_len]).unwrap();
+        assert_eq!(vendor, "ref");
+        assert_eq!(comments, vec!["GENRE=Jazz", "TITLE=New Title", "ARTIST=New Artist", "ALBUM=New Album"]);
+
+        assert_new_tags(&read_back(&dir, "tagged.flac", &out));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flac_without_comments_gets_a_block() {
+        let data = [&b"fLaC"[..], &flac_block(FLAC_STREAMINFO, true, &streaminfo()), &[0xff, 0xf8]].concat();
+        let out = write_flac(&data, &song("New Title", "New Artist", None)).unwrap();
+        assert_eq!(out[4], FLAC_STREAMINFO);
+        assert_eq!(out[42], FLAC_VORBIS_COMMENT | 0x80);
+        assert!(contains(&out, b"TITLE=New Title") && !contains(&out, b"ALBUM="));
+        assert!(out.ends_with(&[0xff, 0xf8]));
+    }
+
+    #[test]
+    fn rejected_layouts_are_corrupt_and_leave_the_file_alone() {
+        let dir = temp_dir("rejected");
+        let truncated_comments = vorbis_comments("ref", &["TITLE=Old Title"])[..12].to_vec();
+        let flac = [&b"fLaC"[..], &flac_block(FLAC_STREAMINFO, false, &streaminfo()), &flac_block(FLAC_VORBIS_COMMENT, true, &truncated_comments), &[0xff, 0xf8]].concat();
+        let cases = [
+            ("unsynchronised.mp3", [id3_tag(3, 0x80, &[id3_frame(3, b"TIT2", "Old Title")]), mp3_audio()].concat()),
+            ("extended.mp3", [id3_tag(3, 0x40, &[id3_frame(3, b"TIT2", "Old Title")]), mp3_audio()].concat()),
+            ("truncated.flac", flac),
+        ];
+        for (name, data) in cases {
+            let path = dir.join(name);
+            fs::write(&path, &data).unwrap();
+            let result = write_tags(&path.display().to_string(), &song("New Title", "New Artist", None));
+            assert!(matches!(result, Err(TagError::Corrupt(_))), "{}: {:?}", name, result);
+            assert_eq!(fs::read(&path).unwrap(), data, "{} was changed", name);
+        }
+        // No temporary files left behind either
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writing_through_a_symlink_tags_the_target_and_keeps_its_mode() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        let dir = temp_dir("symlink");
+        let target = dir.join("real.mp3");
+        let link = dir.join("link.mp3");
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tagged.mp3"), &target).unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_tags(&link.display().to_string(), &song("New Title", "New Artist", None)).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o640);
+        assert_eq!(Song::from_path(&target).title, "New Title");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
\ No newline at end of file
@@ -0,0 +1,95 @@
+use egui::{style::Margin, Align2, Color32, Context, FontId, RichText};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Toasts on screen at once; older ones make way for new ones.
+const MAX_TOASTS: usize = 5;
+
+/// How long each kind of toast stays up unless dismissed.
+const INFO_DURATION: Duration = Duration::from_secs(5);
+const ERROR_DURATION: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ToastKind {
+    Info,
+    Error,
+}
+
+struct Toast {
+    kind: ToastKind,
+    message: String,
+    expires: Instant,
+}
+
+/// Short-lived messages shown in the bottom-right corner of the window, so failures
+/// and confirmations reach GUI users instead of only the log.
+#[derive(Default)]
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.push(ToastKind::Info, message.into(), INFO_DURATION);
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.push(ToastKind::Error, message.into(), ERROR_DURATION);
+    }
+
+    fn push(&mut self, kind: ToastKind, message: String, duration: Duration) {
+        // The same failure repeating (e.g. every frame) refreshes its toast rather
+        // than filling the corner with copies
+        self.toasts.retain(|t| t.message != message);
+        self.toasts.push_back(Toast {
+            kind,
+            message,
+            expires: Instant::now() + duration,
+        });
+        while self.toasts.len() > MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let now = Instant::now();
+        self.toasts.retain(|t| t.expires > now);
+        if self.toasts.is_empty() {
+            return;
+        }
+        let mut dismissed = None;
+        egui::Area::new("toasts")
+            .anchor(Align2::RIGHT_BOTTOM, [-16.0, -16.0])
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (i, toast) in self.toasts.iter().enumerate() {
+                    let (icon, fill) = match toast.kind {
+                        ToastKind::Info => ("ℹ", Color32::from_rgb(40, 80, 160)),
+                        ToastKind::Error => ("⚠", Color32::from_rgb(90, 30, 30)),
+                    };
+                    egui::Frame::none()
+                        .fill(fill)
+                        .rounding(6.0)
+                        .inner_margin(Margin::same(8.0))
+                        .show(ui, |ui| {
+                            ui.set_max_width(380.0);
+                            ui.horizontal(|ui| {
+                                let text = RichText::new(format!("{} {}", icon, toast.message)).font(FontId::proportional(16.0)).color(Color32::WHITE);
+                                ui.add(egui::Label::new(text).wrap(true));
+                                if ui.small_button("✖").clicked() {
+                                    dismissed = Some(i);
+                                }
+                            });
+                        });
+                    ui.add_space(4.0);
+                }
+            });
+        if let Some(i) = dismissed {
+            self.toasts.remove(i);
+        }
+        // Come back to clear the next toast when it runs out
+        if let Some(next) = self.toasts.iter().map(|t| t.expires).min() {
+            ctx.request_repaint_after(next.saturating_duration_since(now));
+        }
+    }
+}
\ No newline at end of file
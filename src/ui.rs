@@ -1,186 +1,1360 @@
-use crate::audio::AudioManager;
-use crate::playlist::{PlaylistManager, Song};
+use crate::artwork::AlbumArt;
+use crate::audio::{AudioManager, PLAYBACK_SPEEDS};
+use crate::equalizer::{EQ_BANDS, EQ_MAX_GAIN_DB, EQ_PRESETS, TONE_MAX_GAIN_DB};
+use crate::now_playing::{now_playing_path, write_now_playing, NowPlayingFormat};
+use crate::player::{PlaybackMode, Player, PlayerEvent, PlaylistSort, RepeatMode, SortKey};
+use crate::playlist::{PlaylistStats, Song};
+use crate::scanner::FolderScan;
+use crate::settings::{PlaylistView, Settings, Theme};
+use crate::spectrum::SPECTRUM_BAR_RANGE;
+use crate::stream::{is_https_url, is_stream_url};
+use crate::tags::write_tags;
+use crate::toast::Toasts;
+use crate::utils::{canonical_path, format_duration, format_file_size, format_time_ago, has_extension, reveal_in_file_manager, SUPPORTED_EXTENSIONS};
+use crate::waveform::WaveformCache;
 use egui::{Context, ScrollArea, Ui, RichText, Color32, FontId, Visuals, style::Margin};
-use std::sync::Arc;
-use tokio::sync::Mutex;
 use rfd::FileDialog;
-use walkdir::WalkDir;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+/// Key bindings shown in the `?` help overlay, kept in step with
+/// `handle_keyboard_shortcuts`.
+const SHORTCUTS: &[(&str, &str)] = &[
+    ("Space", "Play / pause"),
+    ("← / →", "Seek back / forward 5 s"),
+    ("↑ / ↓", "Volume up / down"),
+    ("Alt+↑ / Alt+↓", "Move the selected song up / down"),
+    ("N / P", "Next / previous song"),
+    ("S", "Stop"),
+    ("R", "Cycle repeat: off, all, one"),
+    ("H", "Shuffle on / off"),
+    ("M", "Mute / unmute"),
+    ("Ctrl+Z / Ctrl+Y", "Undo / redo a playlist edit"),
+    ("Ctrl+F", "Search the playlist"),
+    ("?", "Show or hide this help"),
+];
+
+/// Songs listed under "Recently Played".
+const RECENTLY_PLAYED_LIMIT: usize = 10;
+
+/// Playlist edits that can be undone.
+const UNDO_LIMIT: usize = 50;
+
+const SEEK_STEP: std::time::Duration = std::time::Duration::from_secs(5);
+const VOLUME_STEP: f32 = 0.05;
+
+/// How long the level meter's peak tick stays put before following the level down.
+const PEAK_HOLD: std::time::Duration = std::time::Duration::from_millis(1500);
+
+/// Bottom of the level meter's scale, in dB.
+const METER_FLOOR_DB: f32 = -60.0;
+
+/// Drag-and-drop payload for reordering the playlist: the index of the dragged song.
+struct DraggedSong(usize);
+
+/// What the user did to the playlist rows this frame, applied once the list is drawn.
+#[derive(Default)]
+struct RowClicks {
+    selected: Option<usize>,
+    /// Dragged song and the index it was dropped at.
+    dropped: Option<(usize, usize)>,
+    /// Songs to append to the queue, from a group header.
+    queue: Option<Vec<usize>>,
+    action: Option<(usize, SongAction)>,
+}
+
+/// An action picked from a song's right-click menu or its row.
+enum SongAction {
+    Play,
+    PlayNext,
+    AddToQueue,
+    Remove,
+    ShowInFileManager,
+    CopyPath,
+    EditMetadata,
+    Rate(u8),
+    ToggleFavorite,
+    ExportWav,
+}
+
+/// Fields of the "Edit Metadata" window, for the song at `file_path`.
+struct MetadataEdit {
+    file_path: String,
+    title: String,
+    artist: String,
+    album: String,
+}
+
+/// The song list and selection from before a playlist edit, for undo and redo.
+struct PlaylistSnapshot {
+    playlist: Option<String>,
+    songs: Vec<Song>,
+    selected: Vec<usize>,
+}
+
+/// A change to the play queue picked in the "Up Next" list.
+enum QueueAction {
+    Move(usize, usize),
+    Remove(usize),
+    Clear,
+}
 
 pub struct MusicPlayerUI {
-    volume: f32,
-    selected_song_index: Option<usize>,
-    is_playing: bool,
-    is_paused: bool,
-    demo_songs: Vec<Song>,
     selected_songs: Vec<usize>,
-    current_position: std::time::Duration,
-    total_duration: Option<std::time::Duration>,
-    playback_start: Option<std::time::Instant>,
-    paused_at: Option<std::time::Duration>,
-    pending_next: bool,
-    pending_next_time: Option<std::time::Instant>,
+    toasts: Toasts,
+    scrub_position: Option<std::time::Duration>,
+    /// Where the seek bar was right-clicked, for placing loop points from its menu.
+    loop_menu_position: Option<std::time::Duration>,
+    settings: Settings,
+    show_settings: bool,
+    show_shortcuts: bool,
+    /// The song whose metadata is being edited, while the window is open.
+    metadata_edit: Option<MetadataEdit>,
+    /// Name being typed for a new playlist, while the field is open.
+    new_playlist_name: Option<String>,
+    /// New name being typed for the current playlist, while the rename field is open.
+    rename_playlist: Option<String>,
+    /// Stream URL being typed, while the "Add URL" field is open.
+    stream_url: Option<String>,
+    folder_scan: Option<FolderScan>,
+    /// Songs the running folder scan found that were already in the playlist.
+    skipped_duplicates: usize,
+    /// Play the first song the running folder scan finds, for folders opened from the
+    /// command line.
+    play_scanned: bool,
+    /// Hide songs that aren't marked as favorites.
+    favorites_only: bool,
+    /// Only songs whose title, artist or album contain this are listed.
+    search: String,
+    /// Set by Ctrl+F to move the keyboard focus to the search field.
+    focus_search: bool,
+    /// Extension being typed into the settings window's "Formats" row.
+    new_format: String,
+    /// Statistics panel contents and the fingerprint of the song list they were
+    /// computed from.
+    stats: Option<(u64, PlaylistStats)>,
+    /// The level meter's held peak per channel and when it was set.
+    peak_hold: [(f32, std::time::Instant); 2],
+    undo_stack: Vec<PlaylistSnapshot>,
+    redo_stack: Vec<PlaylistSnapshot>,
+    waveforms: WaveformCache,
+    album_art: AlbumArt,
+    /// The OS light/dark preference, if the platform reports one; used by `Theme::System`.
+    system_dark_mode: Option<bool>,
+    /// Whether the style last applied was the dark one, so it's only rebuilt on a change.
+    applied_dark: Option<bool>,
+    /// Output device names, refreshed whenever the device dropdown is opened.
+    output_devices: Vec<String>,
+}
+
+impl Default for MusicPlayerUI {
+    fn default() -> Self {
+        Self::new(Settings::default())
+    }
 }
 
 impl MusicPlayerUI {
-    pub fn new() -> Self {
+    pub fn new(settings: Settings) -> Self {
         Self {
-            volume: 0.5,
-            selected_song_index: None,
-            is_playing: false,
-            is_paused: false,
-            demo_songs: Vec::new(),
             selected_songs: Vec::new(),
-            current_position: std::time::Duration::from_secs(0),
-            total_duration: None,
-            playback_start: None,
-            paused_at: None,
-            pending_next: false,
-            pending_next_time: None,
-        }
-    }
-
-    pub fn update(
-        &mut self,
-        ctx: &Context,
-        audio_manager: Arc<Mutex<AudioManager>>,
-        _playlist_manager: Arc<Mutex<PlaylistManager>>,
-    ) {
-        // Apply a professional dark theme with accent color
-        let mut style = (*ctx.style()).clone();
-        style.visuals = Visuals::dark();
-        style.visuals.widgets.active.bg_fill = Color32::from_rgb(40, 80, 160); // accent blue
-        style.visuals.widgets.hovered.bg_fill = Color32::from_rgb(60, 100, 200);
-        style.visuals.widgets.inactive.bg_fill = Color32::from_rgb(30, 30, 40);
-        style.visuals.widgets.noninteractive.bg_fill = Color32::from_rgb(24, 24, 28);
-        style.visuals.selection.bg_fill = Color32::from_rgb(40, 80, 160);
-        style.visuals.selection.stroke = egui::Stroke::new(2.0, Color32::from_rgb(80, 180, 255));
-        style.spacing.item_spacing = egui::vec2(12.0, 8.0);
-        style.spacing.button_padding = egui::vec2(16.0, 8.0);
-        style.visuals.window_rounding = 8.0.into();
-        style.visuals.window_shadow = egui::epaint::Shadow::big_dark();
-        ctx.set_style(style);
+            toasts: Toasts::default(),
+            scrub_position: None,
+            loop_menu_position: None,
+            settings,
+            show_settings: false,
+            show_shortcuts: false,
+            metadata_edit: None,
+            new_playlist_name: None,
+            rename_playlist: None,
+            stream_url: None,
+            folder_scan: None,
+            skipped_duplicates: 0,
+            play_scanned: false,
+            favorites_only: false,
+            search: String::new(),
+            focus_search: false,
+            new_format: String::new(),
+            stats: None,
+            peak_hold: [(0.0, std::time::Instant::now()); 2],
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            waveforms: WaveformCache::new(),
+            album_art: AlbumArt::default(),
+            output_devices: Vec::new(),
+            system_dark_mode: None,
+            applied_dark: None,
+        }
+    }
+
+    /// Passes on the OS light/dark preference (`None` if unknown) for `Theme::System`.
+    pub fn set_system_dark_mode(&mut self, dark: Option<bool>) {
+        self.system_dark_mode = dark;
+    }
+
+    pub fn update(&mut self, ctx: &Context, player: &mut Player) {
+        let dark = match self.settings.theme {
+            Theme::Dark => true,
+            Theme::Light => false,
+            // Dark when the platform doesn't report a preference
+            Theme::System => self.system_dark_mode.unwrap_or(true),
+        };
+        if self.applied_dark != Some(dark) {
+            apply_theme(ctx, dark);
+            self.applied_dark = Some(dark);
+        }
+        let panel_fill = ctx.style().visuals.panel_fill;
 
         // Always update playback state and auto-advance
-        self.update_playback_state(&audio_manager);
+        player.update();
+        self.handle_player_events(player);
+        self.poll_folder_scan(player);
+        self.handle_keyboard_shortcuts(ctx, player);
+        if player.is_crossfading() || ((player.spectrum_enabled() || player.meter_enabled()) && player.is_playing()) {
+            // Fade volumes are stepped once per frame, and the spectrum bars and level
+            // meter animate
+            ctx.request_repaint_after(std::time::Duration::from_millis(30));
+        } else if player.is_playing() || player.is_waiting() || player.is_buffering() || player.sleep_remaining().is_some() {
+            // Keep the progress display and auto-advance ticking without user input
+            ctx.request_repaint_after(std::time::Duration::from_millis(200));
+        }
 
         egui::CentralPanel::default().frame(
-            egui::Frame::none().fill(Color32::from_rgb(24, 24, 28)).inner_margin(Margin::same(16.0))
+            egui::Frame::none().fill(panel_fill).inner_margin(Margin::same(16.0))
         ).show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.heading(RichText::new("🎵 Rust Music Player").font(FontId::proportional(32.0)).color(Color32::from_rgb(80, 180, 255)));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.add(egui::Button::new(RichText::new("⚙").font(FontId::proportional(20.0)))).on_hover_text("Settings").clicked() {
+                        self.show_settings = !self.show_settings;
+                    }
+                    if ui.add(egui::Button::new(RichText::new("⌨").font(FontId::proportional(20.0)))).on_hover_text("Keyboard shortcuts (?)").clicked() {
+                        self.show_shortcuts = !self.show_shortcuts;
+                    }
+                    let (icon, hint) = match self.settings.theme {
+                        Theme::Dark => ("🌙", "Theme: dark (click for light)"),
+                        Theme::Light => ("☀", "Theme: light (click to follow the system)"),
+                        Theme::System => ("🖥", "Theme: system (click for dark)"),
+                    };
+                    if ui.add(egui::Button::new(RichText::new(icon).font(FontId::proportional(20.0)))).on_hover_text(hint).clicked() {
+                        self.settings.theme = self.settings.theme.cycle();
+                        if let Err(e) = self.settings.save() {
+                            self.toasts.error(format!("Could not save settings: {}", e));
+                        }
+                    }
+                });
             });
             ui.add_space(8.0);
             ui.separator();
             ui.columns(2, |columns| {
-                self.render_playlist_panel(&mut columns[0]);
-                self.render_controls_panel(&mut columns[1], audio_manager.clone());
+                self.render_playlist_panel(&mut columns[0], player);
+                self.render_controls_panel(&mut columns[1], player);
             });
         });
+
+        self.render_settings_window(ctx, player);
+        self.render_shortcuts_window(ctx);
+        self.render_metadata_window(ctx, player);
+        Self::render_drop_overlay(ctx);
+        self.handle_dropped_files(ctx, player);
+        // Events raised by this frame's clicks are shown right away
+        self.handle_player_events(player);
+        self.toasts.show(ctx);
+    }
+
+    /// Runs before any widget is drawn so keys like Space act on playback rather than
+    /// on whichever button has focus. Nothing fires while a text field has focus.
+    fn handle_keyboard_shortcuts(&mut self, ctx: &Context, player: &mut Player) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        use egui::{Key, Modifiers};
+        let pressed = |key: Key| ctx.input_mut(|i| i.consume_key(Modifiers::NONE, key));
+        // Before the plain arrows, which would otherwise match with Alt held too
+        let alt = |key: Key| ctx.input_mut(|i| i.consume_key(Modifiers::ALT, key));
+        let move_up = alt(Key::ArrowUp);
+        let move_down = alt(Key::ArrowDown);
+        // Only where dragging would be allowed too: the plain list, unfiltered
+        let reorderable = self.settings.playlist_view == PlaylistView::List && !self.favorites_only && self.search.trim().is_empty();
+        match self.selected_songs[..] {
+            [selected] if reorderable && move_up && selected > 0 => self.move_song(selected, selected - 1, player),
+            [selected] if reorderable && move_down && selected + 1 < player.songs().len() => self.move_song(selected, selected + 2, player),
+            _ => {}
+        }
+
+        if pressed(Key::Space) {
+            player.toggle_play_pause();
+        }
+        if pressed(Key::ArrowLeft) {
+            self.handle_seek(player.position().saturating_sub(SEEK_STEP), player);
+        }
+        if pressed(Key::ArrowRight) {
+            let target = player.position() + SEEK_STEP;
+            let target = player.total_duration().map_or(target, |total| target.min(total));
+            self.handle_seek(target, player);
+        }
+        if pressed(Key::ArrowUp) {
+            player.set_volume(player.volume() + VOLUME_STEP);
+        }
+        if pressed(Key::ArrowDown) {
+            player.set_volume(player.volume() - VOLUME_STEP);
+        }
+        if pressed(Key::N) {
+            player.next();
+        }
+        if pressed(Key::P) {
+            player.previous();
+        }
+        if pressed(Key::S) {
+            player.stop();
+        }
+        if pressed(Key::R) {
+            let mode = player.repeat_mode().cycle();
+            player.set_repeat_mode(mode);
+            let name = match mode {
+                RepeatMode::None => "off",
+                RepeatMode::All => "all",
+                RepeatMode::One => "one",
+            };
+            self.toasts.info(format!("Repeat: {}", name));
+        }
+        if pressed(Key::H) {
+            player.toggle_shuffle();
+            let state = if player.mode() == PlaybackMode::Shuffle { "on" } else { "off" };
+            self.toasts.info(format!("Shuffle: {}", state));
+        }
+        if pressed(Key::M) {
+            player.toggle_mute();
+        }
+        let command = |key: Key| ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, key));
+        let command_shift = |key: Key| ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, key));
+        if command(Key::F) {
+            self.focus_search = true;
+        }
+        if command_shift(Key::Z) || command(Key::Y) {
+            self.redo(player);
+        } else if command(Key::Z) {
+            self.undo(player);
+        }
+        // `?` arrives as Shift+Slash on most layouts, so match the typed text instead
+        let help = ctx.input(|i| i.events.iter().any(|e| matches!(e, egui::Event::Text(t) if t == "?")));
+        if help {
+            self.show_shortcuts = !self.show_shortcuts;
+        }
+    }
+
+    fn render_shortcuts_window(&mut self, ctx: &Context) {
+        let mut open = self.show_shortcuts;
+        egui::Window::new("Keyboard Shortcuts")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("shortcuts_grid").num_columns(2).spacing([24.0, 6.0]).show(ui, |ui| {
+                    for (keys, action) in SHORTCUTS {
+                        ui.label(RichText::new(*keys).strong().color(Color32::from_rgb(80, 180, 255)));
+                        ui.label(*action);
+                        ui.end_row();
+                    }
+                });
+            });
+        self.show_shortcuts = open;
+    }
+
+    /// Blanks the now-playing file so an overlay doesn't keep showing the last track
+    /// after the player is closed.
+    pub fn on_exit(&mut self) {
+        self.export_now_playing(None);
     }
 
-    fn update_playback_state(&mut self, audio_manager: &Arc<Mutex<AudioManager>>) {
-        if self.pending_next {
-            if let Some(start) = self.pending_next_time {
-                if start.elapsed().as_secs_f32() >= 2.0 {
-                    self.pending_next = false;
-                    self.pending_next_time = None;
-                    self.auto_advance_to_next_song(audio_manager.clone());
+    fn handle_player_events(&mut self, player: &mut Player) {
+        for event in player.take_events() {
+            match event {
+                PlayerEvent::TrackStarted(idx) => {
+                    let song = player.songs().get(idx).cloned();
+                    self.export_now_playing(song.as_ref());
                 }
+                PlayerEvent::Resumed(idx, position) => {
+                    if let Some(song) = player.songs().get(idx) {
+                        self.toasts.info(format!("Resumed \"{}\" at {}", song.title, format_duration(position.as_secs_f64())));
+                    }
+                }
+                PlayerEvent::Stopped => self.export_now_playing(None),
+                PlayerEvent::Exported(path) => self.toasts.info(format!("Exported to {}", path)),
+                PlayerEvent::Error(message) => self.toasts.error(message),
             }
+        }
+    }
+
+    /// Writes `song` to the now-playing file, if that's turned on in the settings.
+    fn export_now_playing(&mut self, song: Option<&Song>) {
+        if !self.settings.now_playing_enabled {
             return;
         }
-        if let Ok(manager) = audio_manager.try_lock() {
-            self.is_playing = manager.is_playing();
-            self.is_paused = manager.is_paused();
+        let format = self.settings.now_playing_format;
+        let Some(path) = now_playing_path(self.settings.now_playing_path.as_deref(), format) else { return };
+        if let Err(e) = write_now_playing(&path, format, song, self.settings.now_playing_art) {
+            self.toasts.error(format!("Could not write now-playing file {}: {}", path.display(), e));
+        }
+    }
 
-            // Check if current song has finished and set pending_next
-            if self.is_playing && !self.is_paused && manager.is_finished() {
-                if let Some(total) = self.total_duration {
-                    self.current_position = total;
-                }
-                self.pending_next = true;
-                self.pending_next_time = Some(std::time::Instant::now());
-                return;
+    fn render_settings_window(&mut self, ctx: &Context, player: &mut Player) {
+        let mut open = self.show_settings;
+        let mut changed = false;
+        let mut export_changed = false;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("settings_grid").num_columns(2).spacing([16.0, 8.0]).show(ui, |ui| {
+                    ui.label("Startup volume").on_hover_text("Used when there is no saved session to restore the volume from");
+                    changed |= ui.add(egui::Slider::new(&mut self.settings.default_volume, 0.0..=1.0)).changed();
+                    ui.end_row();
+
+                    ui.label("Crossfade");
+                    changed |= ui.add(egui::Slider::new(&mut self.settings.crossfade_secs, 0.0..=10.0).suffix(" s")).changed();
+                    ui.end_row();
+
+                    ui.label("Play/pause fade").on_hover_text("Fade in and out on play, pause, resume and stop to avoid clicks");
+                    changed |= ui.add(egui::Slider::new(&mut self.settings.fade_secs, 0.0..=2.0).suffix(" s")).changed();
+                    ui.end_row();
+
+                    ui.label("Auto-advance delay").on_hover_text("Pause between songs. At 0 s songs play back to back without a gap");
+                    changed |= ui.add(egui::Slider::new(&mut self.settings.auto_advance_delay_secs, 0.0..=5.0).suffix(" s")).changed();
+                    ui.end_row();
+
+                    ui.label("");
+                    changed |= ui
+                        .checkbox(&mut self.settings.delay_between_albums_only, "Only between albums")
+                        .on_hover_text("Songs from the same album follow straight on")
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Theme");
+                    ui.horizontal(|ui| {
+                        changed |= ui.radio_value(&mut self.settings.theme, Theme::Dark, "Dark").changed();
+                        changed |= ui.radio_value(&mut self.settings.theme, Theme::Light, "Light").changed();
+                        changed |= ui.radio_value(&mut self.settings.theme, Theme::System, "System").changed();
+                    });
+                    ui.end_row();
+
+                    ui.label("Formats").on_hover_text("File extensions picked up when adding files and folders");
+                    ui.horizontal_wrapped(|ui| {
+                        // Extensions added by hand stay listed until they're unticked
+                        let custom = self.settings.enabled_formats.iter().filter(|f| !SUPPORTED_EXTENSIONS.iter().any(|s| s.eq_ignore_ascii_case(f)));
+                        let formats: Vec<String> = SUPPORTED_EXTENSIONS.iter().map(|s| s.to_string()).chain(custom.cloned()).collect();
+                        for format in formats {
+                            let mut enabled = self.settings.is_format_enabled(&format);
+                            if ui.checkbox(&mut enabled, &format).changed() {
+                                if enabled {
+                                    self.settings.enabled_formats.push(format);
+                                } else {
+                                    self.settings.enabled_formats.retain(|f| !f.eq_ignore_ascii_case(&format));
+                                }
+                                changed = true;
+                            }
+                        }
+                        let field = ui.add(egui::TextEdit::singleline(&mut self.new_format).hint_text("opus").desired_width(50.0));
+                        let submitted = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if (ui.button("Add").clicked() || submitted) && !self.new_format.trim().is_empty() {
+                            if self.settings.add_format(&self.new_format) {
+                                changed = true;
+                            }
+                            self.new_format.clear();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Library folder").on_hover_text("Saved playlists store songs in this folder by relative path, so they still load after the folder moves or on another computer");
+                    ui.horizontal(|ui| {
+                        let text = self.settings.library_root.as_deref().unwrap_or("None: playlists store full paths");
+                        ui.label(RichText::new(text).color(Color32::GRAY));
+                        if ui.button("Browse…").clicked() {
+                            if let Some(folder) = FileDialog::new().pick_folder() {
+                                self.settings.library_root = Some(folder.display().to_string());
+                                changed = true;
+                            }
+                        }
+                        if self.settings.library_root.is_some() && ui.button("Clear").clicked() {
+                            self.settings.library_root = None;
+                            changed = true;
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Folder scan");
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            let mut limited = self.settings.scan.max_depth.is_some();
+                            if ui.checkbox(&mut limited, "Only look").changed() {
+                                self.settings.scan.max_depth = limited.then_some(3);
+                                changed = true;
+                            }
+                            let mut depth = self.settings.scan.max_depth.unwrap_or(3);
+                            let field = egui::DragValue::new(&mut depth).clamp_range(0..=32);
+                            if ui.add_enabled(limited, field).changed() {
+                                self.settings.scan.max_depth = Some(depth);
+                                changed = true;
+                            }
+                            ui.label("folders deep");
+                        });
+                        changed |= ui.checkbox(&mut self.settings.scan.skip_hidden, "Skip hidden files and folders like .git and node_modules").changed();
+                        changed |= ui.checkbox(&mut self.settings.scan.follow_symlinks, "Follow symbolic links").changed();
+                    });
+                    ui.end_row();
+
+                    ui.label("Normalization");
+                    ui.vertical(|ui| {
+                        changed |= ui.checkbox(&mut self.settings.normalize_volume, "Normalize loudness between tracks").changed();
+                        ui.add_enabled_ui(self.settings.normalize_volume, |ui| {
+                            changed |= ui
+                                .checkbox(&mut self.settings.album_gain, "Use album gain where tagged")
+                                .on_hover_text("Keeps the loudness differences between tracks of the same album")
+                                .changed();
+                        });
+                    });
+                    ui.end_row();
+
+                    ui.label("Playlist");
+                    changed |= ui.checkbox(&mut self.settings.show_album, "Show the album after the artist").changed();
+                    ui.end_row();
+
+                    ui.label("WAV export");
+                    changed |= ui.checkbox(&mut self.settings.export_effects, "Apply EQ, balance and normalization").changed();
+                    ui.end_row();
+
+                    ui.label("On launch");
+                    changed |= ui.checkbox(&mut self.settings.resume_playing_on_launch, "Resume playing the last track").changed();
+                    ui.end_row();
+
+                    ui.label("Resume tracks").on_hover_text("Start a track where you left it, for podcasts and audiobooks");
+                    ui.horizontal(|ui| {
+                        changed |= ui.checkbox(&mut self.settings.resume_tracks, "Remember positions past").changed();
+                        let slider = egui::Slider::new(&mut self.settings.resume_after_secs, 0.0..=600.0).suffix(" s");
+                        changed |= ui.add_enabled(self.settings.resume_tracks, slider).changed();
+                    });
+                    ui.end_row();
+
+                    ui.label("Now playing").on_hover_text("Keep a file up to date with the current track, e.g. for an OBS text source");
+                    ui.vertical(|ui| {
+                        export_changed |= ui.checkbox(&mut self.settings.now_playing_enabled, "Write the current track to a file").changed();
+                        ui.add_enabled_ui(self.settings.now_playing_enabled, |ui| {
+                            ui.horizontal(|ui| {
+                                export_changed |= ui.radio_value(&mut self.settings.now_playing_format, NowPlayingFormat::Text, "Artist - Title").changed();
+                                export_changed |= ui.radio_value(&mut self.settings.now_playing_format, NowPlayingFormat::Json, "JSON").changed();
+                            });
+                            let json = self.settings.now_playing_format == NowPlayingFormat::Json;
+                            export_changed |= ui.add_enabled(json, egui::Checkbox::new(&mut self.settings.now_playing_art, "Include album art")).changed();
+                            let default_path = now_playing_path(None, self.settings.now_playing_format).map(|p| p.display().to_string()).unwrap_or_default();
+                            let mut path = self.settings.now_playing_path.clone().unwrap_or_default();
+                            let field = ui.add(egui::TextEdit::singleline(&mut path).hint_text(default_path).desired_width(300.0));
+                            if field.changed() {
+                                self.settings.now_playing_path = Some(path).filter(|p| !p.trim().is_empty());
+                                changed = true;
+                            }
+                            // Only write once the path is finished, not on every keystroke
+                            export_changed |= field.lost_focus();
+                        });
+                    });
+                    ui.end_row();
+                });
+            });
+        self.show_settings = open;
+        changed |= export_changed;
+        if export_changed && self.settings.now_playing_enabled {
+            let song = player.current_song().filter(|_| player.is_playing() || player.is_paused()).cloned();
+            self.export_now_playing(song.as_ref());
+        } else if export_changed {
+            // Turned off: leave the overlay blank rather than stuck on the last track
+            if let Some(path) = now_playing_path(self.settings.now_playing_path.as_deref(), self.settings.now_playing_format) {
+                let _ = write_now_playing(&path, self.settings.now_playing_format, None, false);
             }
+        }
 
-            // Update progress timer
-            if self.is_playing && !self.is_paused {
-                self.current_position = manager.get_current_position();
-                self.total_duration = manager.get_total_duration();
+        if changed {
+            player.set_auto_advance_delay(std::time::Duration::from_secs_f32(self.settings.auto_advance_delay_secs));
+            player.set_delay_between_albums_only(self.settings.delay_between_albums_only);
+            player.set_crossfade(std::time::Duration::from_secs_f32(self.settings.crossfade_secs));
+            player.set_fade(std::time::Duration::from_secs_f32(self.settings.fade_secs));
+            player.set_normalize(self.settings.normalize_volume);
+            player.set_album_gain(self.settings.album_gain);
+            player.set_resume_after(self.settings.resume_after());
+            player.set_library_root(self.settings.library_root());
+            if let Err(e) = self.settings.save() {
+                self.toasts.error(format!("Could not save settings: {}", e));
             }
         }
     }
 
-    fn render_playlist_panel(&mut self, ui: &mut Ui) {
+    fn render_playlist_panel(&mut self, ui: &mut Ui, player: &mut Player) {
         ui.group(|ui| {
             ui.set_width(ui.available_width());
-            ui.heading(RichText::new("Playlist").font(FontId::proportional(24.0)).color(Color32::WHITE));
+            ui.heading(RichText::new("Playlist").font(FontId::proportional(24.0)).color(ui.visuals().strong_text_color()));
+            self.render_playlist_selector(ui, player);
             ui.separator();
             if !self.selected_songs.is_empty() {
-                ui.label(RichText::new(format!("Selected: {} songs", self.selected_songs.len())).color(Color32::from_rgb(80, 180, 255)));
-            }
-            ScrollArea::vertical().max_height(600.0).show(ui, |ui| {
-                for (i, song) in self.demo_songs.iter().enumerate() {
-                    let selected = self.selected_songs.contains(&i);
-                    let label = RichText::new(format!("{} - {}", song.title, song.artist))
-                        .font(FontId::proportional(18.0))
-                        .color(if selected { Color32::from_rgb(80, 180, 255) } else { Color32::WHITE });
-                    let resp = ui.selectable_label(selected, label).on_hover_text("Click to select. Ctrl+Click for multi-select.");
-                    if resp.clicked() {
-                        if ui.input(|i| i.modifiers.ctrl) {
-                            if selected {
-                                self.selected_songs.retain(|&x| x != i);
-                            } else {
-                                self.selected_songs.push(i);
-                            }
-                        } else {
-                            self.selected_songs.clear();
-                            self.selected_songs.push(i);
-                            self.selected_song_index = Some(i);
+                self.render_selection_actions(ui, player);
+            }
+            self.render_search(ui);
+            self.render_sort_header(ui, player);
+            let mut clicks = RowClicks::default();
+            let filtering = self.favorites_only || !self.search.trim().is_empty();
+            if filtering && !player.songs().is_empty() && !player.songs().iter().any(|song| self.is_shown(song)) {
+                ui.label(RichText::new("No songs match").color(Color32::GRAY));
+            }
+            ScrollArea::vertical().max_height(600.0).show(ui, |ui| match self.settings.playlist_view {
+                PlaylistView::List => {
+                    for (i, song) in player.songs().iter().enumerate() {
+                        if !self.is_shown(song) {
+                            continue;
                         }
+                        // Dropping between filtered rows would land somewhere unexpected
+                        self.render_song_row(ui, i, song, !filtering, &mut clicks);
                     }
                 }
+                view => self.render_grouped_songs(ui, view, player, &mut clicks),
             });
+            if !player.songs().is_empty() {
+                let total: f64 = player.songs().iter().filter_map(|s| s.duration).sum();
+                let unknown = player.songs().iter().filter(|s| s.duration.is_none()).count();
+                let mut summary = format!("{} songs · {}", player.songs().len(), format_duration(total));
+                if unknown > 0 {
+                    summary.push_str(&format!(" (+{} of unknown length)", unknown));
+                }
+                ui.label(RichText::new(summary).color(Color32::GRAY));
+            }
+            if clicks.selected.is_some() {
+                player.select(clicks.selected);
+            }
+            if let Some((from, insert_at)) = clicks.dropped {
+                self.move_song(from, insert_at, player);
+            }
+            if let Some(indices) = clicks.queue {
+                for index in indices {
+                    player.add_to_queue(index);
+                }
+            }
+            if let Some((index, action)) = clicks.action {
+                self.handle_song_action(ui.ctx(), index, action, player);
+            }
+            if let Some(scan) = &mut self.folder_scan {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(RichText::new(format!("Scanned {} files, found {} songs…", scan.scanned(), scan.found())).color(Color32::from_rgb(80, 180, 255)));
+                    // Songs found so far stay in the playlist
+                    if ui.small_button("Cancel").on_hover_text("Stop scanning and keep the songs found so far").clicked() {
+                        scan.cancel();
+                    }
+                });
+            }
+            if player.probing_durations() > 0 {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(RichText::new(format!("Reading song lengths… {} left", player.probing_durations())).color(Color32::GRAY));
+                });
+            }
+            if player.is_exporting() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(RichText::new("Exporting to WAV…").color(Color32::from_rgb(80, 180, 255)));
+                });
+            }
             ui.separator();
             ui.horizontal(|ui| {
                 if ui.add(egui::Button::new(RichText::new("Add Song").font(FontId::proportional(16.0)))).clicked() {
                     if let Some(path) = FileDialog::new()
-                        .add_filter("Audio", &["mp3", "wav", "flac", "ogg", "m4a"])
+                        .add_filter("Audio", &self.settings.enabled_formats)
                         .pick_file() {
-                        let file_path = path.display().to_string();
-                        let title = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string());
-                        let song = Song {
-                            title,
-                            artist: "Unknown".to_string(),
-                            file_path,
-                            duration: None,
-                        };
-                        self.demo_songs.push(song);
+                        let song = Song::from_path(&path);
+                        let title = song.title.clone();
+                        if !player.add_song(song) {
+                            self.toasts.info(format!("\"{}\" is already in the playlist", title));
+                        }
                     }
                 }
                 if ui.add(egui::Button::new(RichText::new("Add Folder").font(FontId::proportional(16.0)))).clicked() {
                     if let Some(folder_path) = FileDialog::new().pick_folder() {
-                        self.add_folder_songs(&folder_path);
+                        self.start_folder_scan(vec![folder_path], ui.ctx());
                     }
                 }
+                if ui.add(egui::Button::new(RichText::new("Add URL").font(FontId::proportional(16.0)))).on_hover_text("Add an internet radio station or podcast (http:// only, HTTPS isn't supported)").clicked() {
+                    self.stream_url = Some(String::new());
+                }
                 if ui.add(egui::Button::new(RichText::new("Remove Selected").font(FontId::proportional(16.0)))).clicked() {
-                    self.remove_selected_songs();
+                    self.remove_selected_songs(player);
+                }
+                if ui.add(egui::Button::new(RichText::new("Remove Duplicates").font(FontId::proportional(16.0)))).on_hover_text("Remove songs listed more than once, keeping the first").clicked() {
+                    self.remove_duplicate_songs(player);
                 }
                 if ui.add(egui::Button::new(RichText::new("Clear All").font(FontId::proportional(16.0)))).clicked() {
-                    self.clear_all_songs();
+                    self.clear_all_songs(player);
                 }
             });
+            ui.horizontal(|ui| {
+                if ui.add(egui::Button::new(RichText::new("Save Playlist").font(FontId::proportional(16.0)))).clicked() {
+                    let file_name = format!("{}.json", player.current_playlist_name().unwrap_or("playlist"));
+                    if let Some(path) = FileDialog::new()
+                        .add_filter("Playlist", &["json"])
+                        .add_filter("M3U playlist", &["m3u", "m3u8"])
+                        .set_file_name(file_name)
+                        .save_file() {
+                        if let Err(e) = player.save_playlist(&path.display().to_string()) {
+                            self.toasts.error(format!("Could not save playlist: {}", e));
+                        }
+                    }
+                }
+                if ui.add_enabled(!self.undo_stack.is_empty(), egui::Button::new(RichText::new("↶ Undo").font(FontId::proportional(16.0)))).on_hover_text("Ctrl+Z").clicked() {
+                    self.undo(player);
+                }
+                if ui.add_enabled(!self.redo_stack.is_empty(), egui::Button::new(RichText::new("↷ Redo").font(FontId::proportional(16.0)))).on_hover_text("Ctrl+Y").clicked() {
+                    self.redo(player);
+                }
+                if ui.add(egui::Button::new(RichText::new("Load Playlist").font(FontId::proportional(16.0)))).clicked() {
+                    if let Some(path) = FileDialog::new()
+                        .add_filter("Playlist", &["json", "m3u", "m3u8"])
+                        .pick_file() {
+                        match player.load_playlist(&path.display().to_string()) {
+                            Ok(()) => self.selected_songs.clear(),
+                            Err(e) => self.toasts.error(format!("Could not load playlist: {}", e)),
+                        }
+                    }
+                }
+            });
+            self.render_add_url(ui, player);
+            self.render_queue(ui, player);
+            self.render_recently_played(ui, player);
+            self.render_statistics(ui, player);
+        });
+    }
+
+    /// Buttons acting on every selected song, shown while there is a selection.
+    fn render_selection_actions(&mut self, ui: &mut Ui, player: &mut Player) {
+        // Work on a snapshot in playlist order, so the actions don't depend on the click
+        // order or on the list changing underneath them
+        let mut selected: Vec<usize> = self.selected_songs.iter().copied().filter(|&i| i < player.songs().len()).collect();
+        selected.sort_unstable();
+        selected.dedup();
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(format!("Selected: {} songs", selected.len())).color(Color32::from_rgb(80, 180, 255)));
+            if ui.button("➕ Queue").on_hover_text("Add the selected songs to the queue").clicked() {
+                for &i in &selected {
+                    player.add_to_queue(i);
+                }
+            }
+            let current = player.current_playlist_name().map(str::to_string);
+            let others: Vec<String> = player.playlist_names().into_iter().filter(|name| Some(name) != current.as_ref()).collect();
+            ui.add_enabled_ui(!others.is_empty(), |ui| {
+                ui.menu_button("📋 Add to Playlist", |ui| {
+                    for name in others {
+                        if ui.button(&name).clicked() {
+                            match player.add_songs_to_playlist(&name, &selected) {
+                                Ok(0) => self.toasts.info(format!("Added {} songs to {}", selected.len(), name)),
+                                Ok(skipped) => self.toasts.info(format!("Added {} songs to {} ({} already there)", selected.len() - skipped, name, skipped)),
+                                Err(e) => self.toasts.error(format!("Could not add songs to {}: {}", name, e)),
+                            }
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+            ui.menu_button("⭐ Rate", |ui| {
+                for rating in (0..=5).rev() {
+                    let text = if rating == 0 { "No rating".to_string() } else { "★".repeat(rating as usize) };
+                    if ui.button(text).clicked() {
+                        for &i in &selected {
+                            player.set_rating(i, rating);
+                        }
+                        ui.close_menu();
+                    }
+                }
+            });
+            // Marks them all, unless they all are already
+            let all_favorites = selected.iter().all(|&i| player.songs()[i].favorite);
+            let (text, hint) = if all_favorites { ("♡ Unfavorite", "Remove the selected songs from favorites") } else { ("♥ Favorite", "Add the selected songs to favorites") };
+            if ui.button(text).on_hover_text(hint).clicked() {
+                for &i in &selected {
+                    player.set_favorite(i, !all_favorites);
+                }
+            }
+            if ui.button("💾 Export as WAV…").on_hover_text("Write the selected songs, one after another, to a single WAV file").clicked() {
+                self.export_wav(player, &selected, "export.wav".to_string());
+            }
         });
     }
 
-    fn render_controls_panel(&mut self, ui: &mut Ui, audio_manager: Arc<Mutex<AudioManager>>) {
+    /// Asks where to save, then exports the songs at `indices` to one WAV file.
+    fn export_wav(&mut self, player: &mut Player, indices: &[usize], file_name: String) {
+        if let Some(path) = FileDialog::new()
+            .add_filter("WAV audio", &["wav"])
+            .set_file_name(file_name)
+            .save_file() {
+            player.export_wav(indices, path.display().to_string(), self.settings.export_effects);
+        }
+    }
+
+    fn render_statistics(&mut self, ui: &mut Ui, player: &Player) {
+        egui::CollapsingHeader::new(RichText::new("📈 Statistics").font(FontId::proportional(16.0)))
+            .default_open(false)
+            .show(ui, |ui| {
+                // Recomputed only when the songs change, since it reads every file's size
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                for song in player.songs() {
+                    (&song.file_path, &song.artist, &song.album, song.duration.map(f64::to_bits)).hash(&mut hasher);
+                }
+                let fingerprint = hasher.finish();
+                if self.stats.as_ref().map(|(f, _)| *f) != Some(fingerprint) {
+                    self.stats = Some((fingerprint, PlaylistStats::compute(player.songs())));
+                }
+                let Some((_, stats)) = &self.stats else { return };
+                let mut duration = format_duration(stats.total_duration);
+                if stats.unknown_durations > 0 {
+                    duration.push_str(&format!(" (+{} of unknown length)", stats.unknown_durations));
+                }
+                let mut size = format_file_size(stats.total_bytes);
+                if stats.missing_files > 0 {
+                    size.push_str(&format!(" ({} files missing)", stats.missing_files));
+                }
+                egui::Grid::new("playlist_stats").num_columns(2).spacing([16.0, 4.0]).show(ui, |ui| {
+                    for (name, value) in [
+                        ("Songs", stats.songs.to_string()),
+                        ("Total time", duration),
+                        ("Artists", stats.artists.to_string()),
+                        ("Albums", stats.albums.to_string()),
+                        ("Size on disk", size),
+                    ] {
+                        ui.label(name);
+                        ui.label(RichText::new(value).color(Color32::from_rgb(80, 180, 255)));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    /// One song of the playlist panel. Only the flat list can be reordered by dragging,
+    /// since a drop inside a group has no clear position in the playlist.
+    fn render_song_row(&mut self, ui: &mut Ui, i: usize, song: &Song, draggable: bool, clicks: &mut RowClicks) {
+        let selected = self.selected_songs.contains(&i);
+        let mut text = format!("{} - {}", song.title, song.artist);
+        // The album view already says which album each song is on
+        if self.settings.show_album && self.settings.playlist_view != PlaylistView::Album {
+            if let Some(album) = &song.album {
+                text.push_str(&format!(" · {}", album));
+            }
+        }
+        let label = RichText::new(text)
+            .font(FontId::proportional(18.0))
+            .color(if selected { Color32::from_rgb(80, 180, 255) } else { ui.visuals().strong_text_color() });
+        let duration = song.duration.map(format_duration).unwrap_or_else(|| "--:--".to_string());
+        let resp = ui.horizontal(|ui| {
+            let resp = ui.selectable_label(selected, label)
+                .interact(egui::Sense::drag())
+                .on_hover_text("Click to select. Ctrl+Click for multi-select. Drag to reorder. Right-click to queue.");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(RichText::new(duration).font(FontId::proportional(16.0)).color(Color32::GRAY));
+                if let Some(action) = Self::render_rating(ui, song) {
+                    clicks.action = Some((i, action));
+                }
+            });
+            resp
+        }).inner;
+        resp.context_menu(|ui| {
+            let items = [
+                (SongAction::Play, "▶ Play"),
+                (SongAction::PlayNext, "⏭ Play Next"),
+                (SongAction::AddToQueue, "➕ Add to Queue"),
+                (SongAction::Remove, "🗑 Remove"),
+                (SongAction::ShowInFileManager, "📂 Show in File Manager"),
+                (SongAction::CopyPath, "📋 Copy File Path"),
+                (SongAction::EditMetadata, "✏ Edit Metadata…"),
+                (SongAction::ExportWav, "💾 Export as WAV…"),
+            ];
+            for (action, text) in items {
+                if ui.button(text).clicked() {
+                    clicks.action = Some((i, action));
+                    ui.close_menu();
+                }
+            }
+            let favorite = if song.favorite { "♡ Remove from Favorites" } else { "♥ Add to Favorites" };
+            if ui.button(favorite).clicked() {
+                clicks.action = Some((i, SongAction::ToggleFavorite));
+                ui.close_menu();
+            }
+            ui.menu_button("⭐ Rate", |ui| {
+                for rating in (0..=5).rev() {
+                    let text = if rating == 0 { "No rating".to_string() } else { "★".repeat(rating as usize) };
+                    if ui.radio(song.rating == rating, text).clicked() {
+                        clicks.action = Some((i, SongAction::Rate(rating)));
+                        ui.close_menu();
+                    }
+                }
+            });
+        });
+        if draggable {
+            resp.dnd_set_drag_payload(DraggedSong(i));
+            if let Some(pointer) = ui.ctx().pointer_interact_pos() {
+                // Dropping on the top half of a row inserts above it, the bottom half below
+                let insert_at = if pointer.y < resp.rect.center().y { i } else { i + 1 };
+                if resp.dnd_hover_payload::<DraggedSong>().is_some() {
+                    let y = if insert_at == i { resp.rect.top() } else { resp.rect.bottom() };
+                    ui.painter().hline(resp.rect.x_range(), y, egui::Stroke::new(2.0, Color32::from_rgb(80, 180, 255)));
+                }
+                if let Some(dragged) = resp.dnd_release_payload::<DraggedSong>() {
+                    clicks.dropped = Some((dragged.0, insert_at));
+                }
+            }
+        }
+        if resp.clicked() {
+            if ui.input(|i| i.modifiers.ctrl) {
+                if selected {
+                    self.selected_songs.retain(|&x| x != i);
+                } else {
+                    self.selected_songs.push(i);
+                }
+            } else {
+                self.selected_songs.clear();
+                self.selected_songs.push(i);
+                clicks.selected = Some(i);
+            }
+        }
+    }
+
+    /// The playlist as collapsible sections, one per album or artist in alphabetical
+    /// order. Songs without the tag are collected under "Unknown".
+    fn render_grouped_songs(&mut self, ui: &mut Ui, view: PlaylistView, player: &Player, clicks: &mut RowClicks) {
+        let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (i, song) in player.songs().iter().enumerate() {
+            if !self.is_shown(song) {
+                continue;
+            }
+            let name = match view {
+                PlaylistView::Album => song.album.as_deref(),
+                _ => Some(song.artist.as_str()),
+            };
+            let name = name.map(str::trim).filter(|n| !n.is_empty()).unwrap_or("Unknown");
+            groups.entry(name.to_string()).or_default().push(i);
+        }
+        for (name, mut indices) in groups {
+            let mut title = format!("{} ({})", name, indices.len());
+            if view == PlaylistView::Album {
+                // Albums list in track order. Untagged tracks go after the tagged ones, by
+                // file name, which ripped albums usually number anyway
+                let songs = player.songs();
+                let order = |i: usize| (songs[i].track_number.is_none(), songs[i].track_number, &songs[i].file_path);
+                indices.sort_by(|&a, &b| order(a).cmp(&order(b)));
+                if let Some(year) = indices.iter().find_map(|&i| songs[i].year) {
+                    title = format!("{} · {} ({})", name, year, indices.len());
+                }
+            }
+            let id = ui.make_persistent_id(("playlist_group", view, &name));
+            egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, true)
+                .show_header(ui, |ui| {
+                    ui.label(RichText::new(title).font(FontId::proportional(18.0)).strong());
+                    if ui.small_button("➕ Queue").on_hover_text("Add every song here to the queue").clicked() {
+                        clicks.queue = Some(indices.clone());
+                    }
+                })
+                .body(|ui| {
+                    for &i in &indices {
+                        self.render_song_row(ui, i, &player.songs()[i], false, clicks);
+                    }
+                });
+        }
+    }
+
+    /// The heart and stars at the end of a song's row, laid out right to left. Clicking
+    /// a star sets that rating, or clears it if it's already the rating.
+    fn render_rating(ui: &mut Ui, song: &Song) -> Option<SongAction> {
+        let mut action = None;
+        ui.spacing_mut().item_spacing.x = 2.0;
+        for star in (1..=5).rev() {
+            let (text, color) = if star <= song.rating { ("★", Color32::from_rgb(255, 200, 60)) } else { ("☆", Color32::GRAY) };
+            let resp = ui.add(egui::Label::new(RichText::new(text).font(FontId::proportional(16.0)).color(color)).sense(egui::Sense::click()));
+            if resp.on_hover_text(format!("Rate {} of 5", star)).clicked() {
+                action = Some(SongAction::Rate(if song.rating == star { 0 } else { star }));
+            }
+        }
+        let (text, color) = if song.favorite { ("♥", Color32::from_rgb(230, 80, 110)) } else { ("♡", Color32::GRAY) };
+        let resp = ui.add(egui::Label::new(RichText::new(text).font(FontId::proportional(16.0)).color(color)).sense(egui::Sense::click()));
+        if resp.on_hover_text("Favorite").clicked() {
+            action = Some(SongAction::ToggleFavorite);
+        }
+        action
+    }
+
+    fn handle_song_action(&mut self, ctx: &Context, index: usize, action: SongAction, player: &mut Player) {
+        match action {
+            SongAction::Play => player.play_index(index),
+            SongAction::PlayNext => player.play_next(index),
+            SongAction::AddToQueue => player.add_to_queue(index),
+            SongAction::Remove => {
+                self.push_undo(player);
+                player.remove_songs(&[index]);
+                self.selected_songs.retain(|&i| i != index);
+                for i in &mut self.selected_songs {
+                    if *i > index {
+                        *i -= 1;
+                    }
+                }
+            }
+            SongAction::ShowInFileManager => {
+                if let Err(e) = reveal_in_file_manager(&player.songs()[index].file_path) {
+                    self.toasts.error(format!("Could not open the file manager: {}", e));
+                }
+            }
+            SongAction::CopyPath => {
+                let path = player.songs()[index].file_path.clone();
+                ctx.output_mut(|o| o.copied_text = path);
+                self.toasts.info("Copied the file path");
+            }
+            SongAction::Rate(rating) => player.set_rating(index, rating),
+            SongAction::ToggleFavorite => player.toggle_favorite(index),
+            SongAction::ExportWav => {
+                let file_name = format!("{}.wav", player.songs()[index].title);
+                self.export_wav(player, &[index], file_name);
+            }
+            SongAction::EditMetadata => {
+                let song = &player.songs()[index];
+                self.metadata_edit = Some(MetadataEdit {
+                    file_path: song.file_path.clone(),
+                    title: song.title.clone(),
+                    artist: song.artist.clone(),
+                    album: song.album.clone().unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    fn render_metadata_window(&mut self, ctx: &Context, player: &mut Player) {
+        let Some(edit) = &mut self.metadata_edit else { return };
+        let mut open = true;
+        let mut save = false;
+        egui::Window::new("Edit Metadata")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(RichText::new(&edit.file_path).small().color(Color32::GRAY));
+                egui::Grid::new("metadata_grid").num_columns(2).spacing([16.0, 8.0]).show(ui, |ui| {
+                    ui.label("Title");
+                    ui.text_edit_singleline(&mut edit.title);
+                    ui.end_row();
+                    ui.label("Artist");
+                    ui.text_edit_singleline(&mut edit.artist);
+                    ui.end_row();
+                    ui.label("Album");
+                    ui.text_edit_singleline(&mut edit.album);
+                    ui.end_row();
+                });
+                save = ui.add(egui::Button::new(RichText::new("Save").font(FontId::proportional(16.0)))).clicked();
+            });
+        if save {
+            // Looked up again in case the list changed while the window was open
+            match player.songs().iter().position(|s| s.file_path == edit.file_path) {
+                Some(index) => {
+                    let mut song = player.songs()[index].clone();
+                    song.title = edit.title.trim().to_string();
+                    song.artist = edit.artist.trim().to_string();
+                    song.album = Some(edit.album.trim().to_string()).filter(|a| !a.is_empty());
+                    // The window stays open on failure so the edits aren't lost
+                    match write_tags(&song.file_path, &song) {
+                        Ok(()) => {
+                            player.update_song_metadata(index, song.title, song.artist, song.album);
+                            open = false;
+                        }
+                        Err(e) => self.toasts.error(format!("Could not save metadata: {}", e)),
+                    }
+                }
+                None => {
+                    self.toasts.error("Could not save metadata: the song is no longer in the playlist");
+                    open = false;
+                }
+            }
+        }
+        if !open {
+            self.metadata_edit = None;
+        }
+    }
+
+    fn render_queue(&mut self, ui: &mut Ui, player: &mut Player) {
+        let queued = player.queued_songs();
+        let heading = if queued.is_empty() { "📋 Up Next".to_string() } else { format!("📋 Up Next ({})", queued.len()) };
+        let mut action = None;
+        egui::CollapsingHeader::new(RichText::new(heading).font(FontId::proportional(16.0)))
+            .id_source("play_queue")
+            .default_open(false)
+            .show(ui, |ui| {
+                if queued.is_empty() {
+                    ui.label(RichText::new("Right-click a song to queue it").color(Color32::GRAY));
+                    return;
+                }
+                let last = queued.len() - 1;
+                for (pos, song) in queued.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(pos > 0, egui::Button::new("⬆")).on_hover_text("Move up").clicked() {
+                            action = Some(QueueAction::Move(pos, pos - 1));
+                        }
+                        if ui.add_enabled(pos < last, egui::Button::new("⬇")).on_hover_text("Move down").clicked() {
+                            action = Some(QueueAction::Move(pos, pos + 1));
+                        }
+                        if ui.button("✖").on_hover_text("Remove from the queue").clicked() {
+                            action = Some(QueueAction::Remove(pos));
+                        }
+                        ui.label(format!("{}. {} - {}", pos + 1, song.title, song.artist));
+                    });
+                }
+                if ui.button("Clear Queue").clicked() {
+                    action = Some(QueueAction::Clear);
+                }
+            });
+        match action {
+            Some(QueueAction::Move(from, to)) => player.move_in_queue(from, to),
+            Some(QueueAction::Remove(pos)) => player.remove_from_queue(pos),
+            Some(QueueAction::Clear) => player.clear_queue(),
+            None => {}
+        }
+    }
+
+    fn render_recently_played(&mut self, ui: &mut Ui, player: &mut Player) {
+        egui::CollapsingHeader::new(RichText::new("🕘 Recently Played").font(FontId::proportional(16.0)))
+            .default_open(false)
+            .show(ui, |ui| {
+                let recent = player.recently_played(RECENTLY_PLAYED_LIMIT);
+                if recent.is_empty() {
+                    ui.label(RichText::new("Nothing played yet").color(Color32::GRAY));
+                    return;
+                }
+                let mut to_play = None;
+                for song in recent {
+                    let when = song.last_played.map(format_time_ago).unwrap_or_default();
+                    let plays = if song.play_count == 1 { "1 play".to_string() } else { format!("{} plays", song.play_count) };
+                    let in_playlist = player.songs().iter().any(|s| s.file_path == song.file_path);
+                    let hint = if in_playlist { "Click to play" } else { "Not in this playlist. Click to add it and play" };
+                    let label = ui.add(egui::Label::new(format!("{} - {}  ·  {}  ·  {}", song.title, song.artist, plays, when)).sense(egui::Sense::click()));
+                    if label.on_hover_text(hint).clicked() {
+                        to_play = Some(song.clone());
+                    }
+                }
+                if let Some(song) = to_play {
+                    // Songs from other playlists are added to the end of this one
+                    let index = match player.songs().iter().position(|s| s.file_path == song.file_path) {
+                        Some(index) => index,
+                        None => {
+                            player.add_songs(vec![song]);
+                            player.songs().len() - 1
+                        }
+                    };
+                    player.play_index(index);
+                }
+            });
+    }
+
+    fn render_search(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            let edit = egui::TextEdit::singleline(&mut self.search).hint_text("Search title, artist or album (Ctrl+F)");
+            let resp = ui.add(edit);
+            if std::mem::take(&mut self.focus_search) {
+                resp.request_focus();
+            }
+            if resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.search.clear();
+            }
+            if !self.search.is_empty() && ui.small_button("✖").on_hover_text("Clear the search").clicked() {
+                self.search.clear();
+            }
+        });
+    }
+
+    /// Whether `song` passes the search and the favorites filter. Filtering only hides
+    /// rows; indices still refer to the full song list.
+    fn is_shown(&self, song: &Song) -> bool {
+        if self.favorites_only && !song.favorite {
+            return false;
+        }
+        let query = self.search.trim().to_lowercase();
+        query.is_empty()
+            || song.title.to_lowercase().contains(&query)
+            || song.artist.to_lowercase().contains(&query)
+            || song.album.as_ref().is_some_and(|album| album.to_lowercase().contains(&query))
+    }
+
+    fn render_sort_header(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            ui.label("Sort by:");
+            for (key, name) in [(SortKey::Title, "Title"), (SortKey::Artist, "Artist"), (SortKey::Duration, "Duration"), (SortKey::Rating, "Rating")] {
+                let current = self.settings.playlist_sort.filter(|sort| sort.key == key);
+                let text = match current {
+                    Some(sort) if sort.ascending => format!("{} ▲", name),
+                    Some(_) => format!("{} ▼", name),
+                    None => name.to_string(),
+                };
+                if ui.selectable_label(current.is_some(), text).clicked() {
+                    // Clicking the active column again flips the direction
+                    let sort = PlaylistSort { key, ascending: !current.is_some_and(|sort| sort.ascending) };
+                    self.sort_songs(sort, player);
+                }
+            }
+            ui.separator();
+            ui.checkbox(&mut self.favorites_only, "♥ Favorites only");
+        });
+        ui.horizontal(|ui| {
+            ui.label("View:");
+            let mut view = self.settings.playlist_view;
+            for (option, name) in [(PlaylistView::List, "List"), (PlaylistView::Album, "Albums"), (PlaylistView::Artist, "Artists")] {
+                ui.selectable_value(&mut view, option, name);
+            }
+            if view != self.settings.playlist_view {
+                self.settings.playlist_view = view;
+                if let Err(e) = self.settings.save() {
+                    self.toasts.error(format!("Could not save settings: {}", e));
+                }
+            }
+        });
+    }
+
+    fn sort_songs(&mut self, sort: PlaylistSort, player: &mut Player) {
+        self.push_undo(player);
+        let new_index = player.sort_songs(sort);
+        for i in &mut self.selected_songs {
+            *i = new_index[*i];
+        }
+        self.settings.playlist_sort = Some(sort);
+        if let Err(e) = self.settings.save() {
+            self.toasts.error(format!("Could not save settings: {}", e));
+        }
+    }
+
+    fn render_playlist_selector(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            let current = player.current_playlist_name().unwrap_or_default().to_string();
+            let mut chosen = None;
+            egui::ComboBox::from_id_source("playlist_selector")
+                .selected_text(RichText::new(&current).font(FontId::proportional(16.0)))
+                .show_ui(ui, |ui| {
+                    for name in player.playlist_names() {
+                        if ui.selectable_label(name == current, &name).clicked() {
+                            chosen = Some(name);
+                        }
+                    }
+                });
+            if let Some(name) = chosen {
+                if let Err(e) = player.switch_playlist(&name) {
+                    self.toasts.error(format!("Could not open playlist: {}", e));
+                }
+                self.selected_songs.clear();
+            }
+            if ui.button("➕ New").on_hover_text("Create a playlist").clicked() {
+                self.new_playlist_name = Some(String::new());
+                self.rename_playlist = None;
+            }
+            if ui.button("✏ Rename").on_hover_text("Rename this playlist").clicked() {
+                self.rename_playlist = Some(current.clone());
+                self.new_playlist_name = None;
+            }
+            let can_delete = player.playlist_names().len() > 1;
+            if ui.add_enabled(can_delete, egui::Button::new("🗑 Delete")).on_hover_text("Delete this playlist").clicked() {
+                if let Err(e) = player.delete_playlist(&current) {
+                    self.toasts.error(format!("Could not delete playlist: {}", e));
+                }
+                self.selected_songs.clear();
+            }
+        });
+
+        let mut create = false;
+        let mut cancel = false;
+        if let Some(name) = &mut self.new_playlist_name {
+            ui.horizontal(|ui| {
+                let field = ui.add(egui::TextEdit::singleline(name).hint_text("Playlist name"));
+                field.request_focus();
+                create = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                create |= ui.button("Create").clicked();
+                cancel = ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+            });
+        }
+        if create {
+            let name = self.new_playlist_name.take().unwrap_or_default();
+            match player.create_playlist(&name) {
+                Ok(()) => self.selected_songs.clear(),
+                Err(e) => self.toasts.error(format!("Could not create playlist: {}", e)),
+            }
+        } else if cancel {
+            self.new_playlist_name = None;
+        }
+
+        let mut rename = false;
+        let mut cancel = false;
+        if let Some(name) = &mut self.rename_playlist {
+            ui.horizontal(|ui| {
+                let field = ui.add(egui::TextEdit::singleline(name).hint_text("New name"));
+                field.request_focus();
+                rename = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                rename |= ui.button("Rename").clicked();
+                cancel = ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+            });
+        }
+        if rename {
+            let new_name = self.rename_playlist.take().unwrap_or_default();
+            let Some(current) = player.current_playlist_name().map(str::to_string) else { return };
+            match player.rename_playlist(&current, &new_name) {
+                Ok(()) => {
+                    // Keep undo working across the rename
+                    let new_name = new_name.trim();
+                    for snapshot in self.undo_stack.iter_mut().chain(self.redo_stack.iter_mut()) {
+                        if snapshot.playlist.as_deref() == Some(current.as_str()) {
+                            snapshot.playlist = Some(new_name.to_string());
+                        }
+                    }
+                }
+                Err(e) => self.toasts.error(format!("Could not rename playlist: {}", e)),
+            }
+        } else if cancel {
+            self.rename_playlist = None;
+        }
+    }
+
+    fn render_add_url(&mut self, ui: &mut Ui, player: &mut Player) {
+        let mut add = false;
+        let mut cancel = false;
+        if let Some(url) = &mut self.stream_url {
+            ui.horizontal(|ui| {
+                let field = ui.add(egui::TextEdit::singleline(url).hint_text("http://example.com/stream.mp3 (HTTPS isn't supported)").desired_width(300.0));
+                field.request_focus();
+                add = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                add |= ui.button("Add").clicked();
+                cancel = ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape));
+            });
+        }
+        if add {
+            let url = self.stream_url.take().unwrap_or_default();
+            let url = url.trim();
+            if is_https_url(url) {
+                self.toasts.error("Could not add stream: HTTPS isn't supported yet, use the station's http:// address");
+            } else if is_stream_url(url) {
+                if !player.add_song(Song::from_url(url)) {
+                    self.toasts.info(format!("{} is already in the playlist", url));
+                }
+            } else {
+                self.toasts.error(format!("Could not add stream: {} is not an http:// URL", url));
+            }
+        } else if cancel {
+            self.stream_url = None;
+        }
+    }
+
+    fn render_controls_panel(&mut self, ui: &mut Ui, player: &mut Player) {
         ui.group(|ui| {
             ui.set_width(ui.available_width());
-            ui.heading(RichText::new("Controls").font(FontId::proportional(24.0)).color(Color32::WHITE));
+            ui.heading(RichText::new("Controls").font(FontId::proportional(24.0)).color(ui.visuals().strong_text_color()));
             ui.separator();
+            let has_output = player.has_audio_output();
+            if !has_output {
+                ui.label(RichText::new("🔇 No audio output device was found. Playlists still work; playback comes back once a device is available.")
+                    .color(Color32::from_rgb(230, 160, 60)));
+            }
             ui.horizontal(|ui| {
+                ui.set_enabled(has_output);
                 let prev = ui.add(egui::Button::new(RichText::new("⏮ Prev").font(FontId::proportional(16.0))));
-                let play_pause_label = if self.is_playing && !self.is_paused {
+                let play_pause_label = if player.is_playing() {
                     "⏸ Pause"
                 } else {
                     "▶ Play"
@@ -188,272 +1362,823 @@ impl MusicPlayerUI {
                 let play_pause = ui.add(egui::Button::new(RichText::new(play_pause_label).font(FontId::proportional(16.0))));
                 let next = ui.add(egui::Button::new(RichText::new("⏭ Next").font(FontId::proportional(16.0))));
                 let stop = ui.add(egui::Button::new(RichText::new("⏹ Stop").font(FontId::proportional(16.0))));
-                if prev.clicked() { self.handle_previous(audio_manager.clone()); }
-                if play_pause.clicked() { self.handle_play_pause(audio_manager.clone()); }
-                if next.clicked() { self.handle_next(audio_manager.clone()); }
-                if stop.clicked() { self.handle_stop(audio_manager.clone()); }
+                let shuffle_on = player.mode() == PlaybackMode::Shuffle;
+                let shuffle = ui.add(egui::SelectableLabel::new(shuffle_on, RichText::new("🔀 Shuffle").font(FontId::proportional(16.0))))
+                    .on_hover_text(if shuffle_on { "Shuffle is on" } else { "Shuffle is off" });
+                let repeat_label = match player.repeat_mode() {
+                    RepeatMode::None => "🔁 Repeat: Off",
+                    RepeatMode::All => "🔁 Repeat: All",
+                    RepeatMode::One => "🔂 Repeat: One",
+                };
+                let repeat = ui.add(egui::SelectableLabel::new(player.repeat_mode() != RepeatMode::None, RichText::new(repeat_label).font(FontId::proportional(16.0))));
+                if prev.clicked() { player.previous(); }
+                if play_pause.clicked() { player.toggle_play_pause(); }
+                if next.clicked() { player.next(); }
+                if stop.clicked() { player.stop(); }
+                if shuffle.clicked() { player.toggle_shuffle(); }
+                if repeat.clicked() { player.set_repeat_mode(player.repeat_mode().cycle()); }
             });
             ui.add_space(8.0);
             ui.label(RichText::new("Volume:").font(FontId::proportional(16.0)));
-            let volume_slider = ui.add(egui::Slider::new(&mut self.volume, 0.0..=1.0).text("Volume"));
-            if volume_slider.changed() {
-                self.handle_volume_change(audio_manager.clone());
-            }
+            ui.horizontal(|ui| {
+                let (icon, hint) = if player.is_muted() { ("🔇", "Unmute") } else { ("🔊", "Mute") };
+                if ui.add(egui::Button::new(RichText::new(icon).font(FontId::proportional(16.0)))).on_hover_text(hint).clicked() {
+                    player.toggle_mute();
+                }
+                let mut volume = player.volume();
+                let volume_slider = ui.add(egui::Slider::new(&mut volume, 0.0..=1.0).text("Volume"));
+                if volume_slider.changed() {
+                    // Dragging the slider while muted unmutes at the new level
+                    player.set_volume(volume);
+                }
+            });
+            self.render_speed(ui, player);
+            self.render_balance(ui, player);
+            self.render_tone(ui, player);
+            self.render_output_device(ui, player);
+            self.render_sleep_timer(ui, player);
+            self.render_equalizer(ui, player);
+            self.render_spectrum(ui, player);
+            self.render_level_meter(ui, player);
             ui.separator();
             ui.label(RichText::new("Now Playing:").font(FontId::proportional(16.0)).color(Color32::from_rgb(80, 180, 255)));
-            if let Some(idx) = self.selected_song_index {
-                let song = &self.demo_songs[idx];
-                ui.label(RichText::new(format!("{} - {}", song.title, song.artist)).font(FontId::proportional(18.0)).color(Color32::WHITE));
-                ui.separator();
-                ui.label(RichText::new("Progress:").font(FontId::proportional(16.0)));
-                let (elapsed, frac) = if self.pending_next {
-                    let total = self.total_duration.unwrap_or(std::time::Duration::from_secs(1));
-                    (total, 1.0)
-                } else {
-                    let elapsed = if self.is_playing && !self.is_paused {
-                        if let Some(start) = self.playback_start {
-                            start.elapsed()
-                        } else {
-                            std::time::Duration::from_secs(0)
+            if let Some(song) = player.current_song() {
+                ui.horizontal(|ui| {
+                    let art_size = egui::vec2(64.0, 64.0);
+                    match self.album_art.get(ui.ctx(), &song.file_path) {
+                        Some(texture) => {
+                            ui.add(egui::Image::new(texture).fit_to_exact_size(art_size).rounding(4.0));
                         }
-                    } else if self.is_paused {
-                        self.paused_at.unwrap_or(std::time::Duration::from_secs(0))
-                    } else {
-                        std::time::Duration::from_secs(0)
-                    };
-                    let mut elapsed_secs = elapsed.as_secs_f32();
-                    let mut frac = 0.0;
-                    if let Some(total) = self.total_duration {
-                        let total_secs = total.as_secs_f32();
-                        if elapsed_secs > total_secs {
-                            elapsed_secs = total_secs;
+                        None => {
+                            // Placeholder while loading or when the file has no art
+                            let (rect, _) = ui.allocate_exact_size(art_size, egui::Sense::hover());
+                            ui.painter().rect_filled(rect, 4.0, ui.visuals().widgets.inactive.bg_fill);
+                            ui.painter().text(rect.center(), egui::Align2::CENTER_CENTER, "🎵", FontId::proportional(28.0), ui.visuals().weak_text_color());
                         }
-                        frac = (elapsed_secs / total_secs).min(1.0);
                     }
-                    (std::time::Duration::from_secs_f32(elapsed_secs), frac)
+                    ui.vertical(|ui| {
+                        ui.label(RichText::new(format!("{} - {}", song.title, song.artist)).font(FontId::proportional(18.0)).color(ui.visuals().strong_text_color()));
+                        if let Some(album) = &song.album {
+                            ui.label(RichText::new(album).font(FontId::proportional(14.0)).italics());
+                        }
+                        if let Some(info) = player.current_track_info() {
+                            ui.label(RichText::new(info.summary()).font(FontId::proportional(12.0)).color(Color32::GRAY));
+                        }
+                    });
+                });
+                ui.separator();
+                ui.label(RichText::new("Progress:").font(FontId::proportional(16.0)));
+                let elapsed = player.position();
+                let total_duration = player.total_duration();
+                let frac = match total_duration {
+                    Some(total) if total.as_secs_f32() > 0.0 => (elapsed.as_secs_f32() / total.as_secs_f32()).min(1.0),
+                    _ => 0.0,
                 };
-                if self.total_duration.is_some() {
-                    ui.add(egui::ProgressBar::new(frac).desired_width(200.0).show_percentage());
+                let file_path = song.file_path.clone();
+                let ctx = ui.ctx().clone();
+                let shown_frac = self.shown_fraction(frac, total_duration);
+                if !is_stream_url(&file_path) {
+                    if let Some(peaks) = self.waveforms.get(&file_path, move || ctx.request_repaint()) {
+                        Self::render_waveform(ui, peaks, shown_frac);
+                    }
+                }
+                let mut seek_target = None;
+                match total_duration {
+                    Some(total) if player.is_seekable() => seek_target = self.render_seek_bar(ui, frac, total, player),
+                    // Streams show how far along they are but can't be seeked
+                    Some(_) => {
+                        ui.add(egui::ProgressBar::new(frac));
+                    }
+                    None => {}
                 }
-                let display_secs = elapsed.as_secs() as u64;
+                // While scrubbing, the label previews the position under the pointer
+                let elapsed = self.scrub_position.unwrap_or(elapsed);
+                if let Some(position) = seek_target {
+                    self.handle_seek(position, player);
+                }
+                let display_secs = elapsed.as_secs();
                 let current_mins = display_secs / 60;
                 let current_secs_remainder = display_secs % 60;
-                let total_secs = self.total_duration.map(|d| d.as_secs()).unwrap_or(0);
-                let total_mins = total_secs / 60;
-                let total_secs_remainder = total_secs % 60;
-                ui.label(RichText::new(format!("{:02}:{:02} / {:02}:{:02}", current_mins, current_secs_remainder, total_mins, total_secs_remainder)).font(FontId::proportional(16.0)).color(Color32::WHITE));
+                let total_secs = total_duration.map(|d| d.as_secs()).unwrap_or(0);
+                let (sign, right_secs) = if self.settings.show_remaining { ("-", total_secs.saturating_sub(display_secs)) } else { ("", total_secs) };
+                let right_mins = right_secs / 60;
+                let right_secs_remainder = right_secs % 60;
+                let time = format!("{:02}:{:02} / {}{:02}:{:02}", current_mins, current_secs_remainder, sign, right_mins, right_secs_remainder);
+                let time_label = ui.add(egui::Label::new(RichText::new(time).font(FontId::proportional(16.0)).color(ui.visuals().strong_text_color())).sense(egui::Sense::click()));
+                let hint = if self.settings.show_remaining { "Click to show the total length" } else { "Click to show the time remaining" };
+                if time_label.on_hover_text(hint).clicked() {
+                    self.settings.show_remaining = !self.settings.show_remaining;
+                    if let Err(e) = self.settings.save() {
+                        self.toasts.error(format!("Could not save settings: {}", e));
+                    }
+                }
+                self.render_ab_loop(ui, player);
             } else {
+                self.album_art = AlbumArt::default();
                 ui.label(RichText::new("No song selected").font(FontId::proportional(16.0)).color(Color32::GRAY));
             }
             ui.separator();
-            let status = if self.pending_next {
+            let status = if !player.has_audio_output() {
+                "🔇 No audio output"
+            } else if player.is_buffering() {
+                "⏳ Buffering..."
+            } else if player.is_waiting() {
                 "⏳ Waiting..."
-            } else if self.is_playing && !self.is_paused {
+            } else if player.is_playing() {
                 "▶ Playing"
-            } else if self.is_paused {
+            } else if player.is_paused() {
                 "⏸ Paused"
             } else {
                 "⏹ Stopped"
             };
+            let mut status = status.to_string();
+            if player.mode() == PlaybackMode::Shuffle {
+                status.push_str(" · 🔀 Shuffle");
+            }
+            match player.repeat_mode() {
+                RepeatMode::All => status.push_str(" · 🔁 All"),
+                RepeatMode::One => status.push_str(" · 🔂 One"),
+                RepeatMode::None => {}
+            }
             ui.label(RichText::new(format!("Status: {}", status)).font(FontId::proportional(16.0)).color(Color32::from_rgb(80, 180, 255)));
         });
     }
 
-    fn handle_play_pause(&mut self, audio_manager: Arc<Mutex<AudioManager>>) {
-        if let Ok(mut manager) = audio_manager.try_lock() {
-            if self.is_playing && !self.is_paused {
-                // Currently playing, so pause
-                manager.pause();
-                self.is_paused = true;
-                self.is_playing = false;
-                if let Some(start) = self.playback_start {
-                    self.paused_at = Some(start.elapsed());
-                }
-            } else if self.is_paused {
-                // Currently paused, so resume
-                manager.resume();
-                self.is_playing = true;
-                self.is_paused = false;
-                if let Some(paused) = self.paused_at {
-                    self.playback_start = Some(std::time::Instant::now() - paused);
-                }
-                self.paused_at = None;
-            } else {
-                // Not playing, so start playing selected song
-                if let Some(idx) = self.selected_song_index {
-                    let song = &self.demo_songs[idx];
-                    if let Err(e) = manager.play_file(&song.file_path) {
-                        eprintln!("Failed to play file: {}", e);
-                    } else {
-                        self.is_playing = true;
-                        self.is_paused = false;
-                        self.playback_start = Some(std::time::Instant::now());
-                        self.paused_at = None;
+    fn render_ab_loop(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            let (a, b) = player.loop_points();
+            let point = |p: Option<std::time::Duration>| p.map(|p| format_duration(p.as_secs_f64())).unwrap_or_else(|| "--:--".to_string());
+            ui.label(RichText::new(format!("🔁 A-B: {} → {}", point(a), point(b))).font(FontId::proportional(16.0)));
+            if ui.button("Set A").on_hover_text("Start the loop at the current position").clicked() {
+                player.set_loop_a();
+            }
+            if ui.add_enabled(a.is_some(), egui::Button::new("Set B")).on_hover_text("End the loop at the current position").clicked() {
+                player.set_loop_b();
+            }
+            if ui.add_enabled(a.is_some() || b.is_some(), egui::Button::new("Clear")).clicked() {
+                player.clear_loop();
+            }
+        });
+    }
+
+    fn render_output_device(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("🔈 Output:").font(FontId::proportional(16.0)));
+            let current = player.output_device().map(str::to_string);
+            let mut choice = current.clone();
+            let combo = egui::ComboBox::from_id_source("output_device")
+                .selected_text(current.as_deref().unwrap_or("System default"))
+                .width(200.0)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut choice, None, "System default");
+                    for name in &self.output_devices {
+                        ui.selectable_value(&mut choice, Some(name.clone()), name);
+                    }
+                });
+            if combo.response.clicked() {
+                self.output_devices = AudioManager::list_devices();
+            }
+            if choice != current {
+                match player.set_output_device(choice.as_deref()) {
+                    Ok(()) => {
+                        self.settings.output_device = choice;
+                        if let Err(e) = self.settings.save() {
+                            self.toasts.error(format!("Could not save settings: {}", e));
+                        }
                     }
+                    Err(e) => self.toasts.error(format!("Could not switch output device: {}", e)),
                 }
             }
-        }
+        });
     }
 
-    fn handle_stop(&mut self, audio_manager: Arc<Mutex<AudioManager>>) {
-        if let Ok(mut manager) = audio_manager.try_lock() {
-            manager.stop();
-            self.is_playing = false;
-            self.is_paused = false;
-            self.current_position = std::time::Duration::from_secs(0);
-            self.total_duration = None;
-            self.playback_start = None;
-            self.paused_at = None;
-        }
+    fn render_equalizer(&mut self, ui: &mut Ui, player: &mut Player) {
+        egui::CollapsingHeader::new(RichText::new("🎚 Equalizer").font(FontId::proportional(16.0)))
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut enabled = player.eq_enabled();
+                let mut gains = player.eq_gains();
+                let mut changed = false;
+                // Slider drags update the sound live but only hit the disk once released
+                let mut save = false;
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut enabled, "Enabled").changed() {
+                        player.set_eq_enabled(enabled);
+                        save = true;
+                    }
+                    for (name, preset) in EQ_PRESETS {
+                        if ui.button(*name).clicked() {
+                            gains = preset.to_vec();
+                            changed = true;
+                            save = true;
+                        }
+                    }
+                });
+                ui.add_enabled_ui(enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        for (gain, frequency) in gains.iter_mut().zip(EQ_BANDS) {
+                            ui.vertical(|ui| {
+                                let slider = ui.add(egui::Slider::new(gain, -EQ_MAX_GAIN_DB..=EQ_MAX_GAIN_DB).vertical().show_value(false));
+                                let slider = slider.on_hover_text(format!("{:+.1} dB", gain));
+                                changed |= slider.changed();
+                                save |= slider.drag_released() || (slider.changed() && !slider.dragged());
+                                let label = if frequency >= 1000.0 { format!("{}k", frequency / 1000.0) } else { format!("{}", frequency) };
+                                ui.label(RichText::new(label).font(FontId::proportional(11.0)));
+                            });
+                        }
+                    });
+                });
+                if changed {
+                    player.set_eq_gains(&gains);
+                }
+                if save {
+                    self.settings.eq_enabled = enabled;
+                    self.settings.eq_gains = player.eq_gains();
+                    if let Err(e) = self.settings.save() {
+                        self.toasts.error(format!("Could not save settings: {}", e));
+                    }
+                }
+            });
     }
 
-    fn handle_volume_change(&mut self, audio_manager: Arc<Mutex<AudioManager>>) {
-        if let Ok(mut manager) = audio_manager.try_lock() {
-            manager.set_volume(self.volume);
+    fn render_spectrum(&mut self, ui: &mut Ui, player: &mut Player) {
+        egui::CollapsingHeader::new(RichText::new("📊 Spectrum").font(FontId::proportional(16.0)))
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut enabled = player.spectrum_enabled();
+                let mut save = false;
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut enabled, "Enabled").changed() {
+                        player.set_spectrum_enabled(enabled);
+                        save = true;
+                    }
+                    let bars = ui.add(egui::Slider::new(&mut self.settings.spectrum_bars, SPECTRUM_BAR_RANGE).text("Bars"));
+                    let smoothing = ui.add(egui::Slider::new(&mut self.settings.spectrum_smoothing, 0.0..=0.95).text("Smoothing"));
+                    if bars.changed() || smoothing.changed() {
+                        player.set_spectrum_options(self.settings.spectrum_bars, self.settings.spectrum_smoothing);
+                    }
+                    save |= bars.drag_released() || smoothing.drag_released();
+                });
+                if enabled {
+                    Self::render_spectrum_bars(ui, &player.spectrum_bars());
+                }
+                if save {
+                    self.settings.spectrum_enabled = enabled;
+                    if let Err(e) = self.settings.save() {
+                        self.toasts.error(format!("Could not save settings: {}", e));
+                    }
+                }
+            });
+    }
+
+    fn render_level_meter(&mut self, ui: &mut Ui, player: &mut Player) {
+        egui::CollapsingHeader::new(RichText::new("📶 Level Meter").font(FontId::proportional(16.0)))
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut enabled = player.meter_enabled();
+                if ui.checkbox(&mut enabled, "Enabled").changed() {
+                    player.set_meter_enabled(enabled);
+                    self.settings.meter_enabled = enabled;
+                    if let Err(e) = self.settings.save() {
+                        self.toasts.error(format!("Could not save settings: {}", e));
+                    }
+                }
+                if !enabled {
+                    return;
+                }
+                let levels = player.output_levels();
+                for (channel, name) in ["L", "R"].into_iter().enumerate() {
+                    let hold = &mut self.peak_hold[channel];
+                    let peak = levels.peak[channel];
+                    if !player.is_playing() {
+                        *hold = (0.0, std::time::Instant::now());
+                    } else if peak >= hold.0 || hold.1.elapsed() > PEAK_HOLD {
+                        *hold = (peak, std::time::Instant::now());
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label(name);
+                        Self::render_level_bar(ui, levels.rms[channel], peak, hold.0);
+                    });
+                }
+            });
+    }
+
+    /// One channel of the level meter on a -60 dB to 0 dB scale: RMS as the solid bar,
+    /// the peak as a fainter extension and the held peak as a tick.
+    fn render_level_bar(ui: &mut Ui, rms: f32, peak: f32, held: f32) {
+        let position = |amplitude: f32| {
+            let db = 20.0 * amplitude.max(1e-6).log10();
+            ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0)
+        };
+        let color = |fraction: f32| match fraction {
+            f if f > 0.95 => Color32::from_rgb(230, 70, 60),
+            f if f > 0.8 => Color32::from_rgb(230, 190, 60),
+            _ => Color32::from_rgb(80, 180, 255),
+        };
+        let size = egui::vec2(ui.available_width().min(280.0), 10.0);
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter();
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let bar = |fraction: f32| egui::Rect::from_min_size(rect.min, egui::vec2(rect.width() * fraction, rect.height()));
+        let (rms, peak, held) = (position(rms), position(peak), position(held));
+        painter.rect_filled(bar(peak), 2.0, color(peak).gamma_multiply(0.4));
+        painter.rect_filled(bar(rms), 2.0, color(rms));
+        if held > 0.0 {
+            let x = rect.left() + rect.width() * held;
+            painter.vline(x, rect.y_range(), egui::Stroke::new(2.0, color(held)));
         }
     }
 
-    fn handle_previous(&mut self, audio_manager: Arc<Mutex<AudioManager>>) {
-        if self.demo_songs.is_empty() {
+    fn render_spectrum_bars(ui: &mut Ui, bars: &[f32]) {
+        let size = egui::vec2(ui.available_width().min(300.0), 60.0);
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter();
+        painter.rect_filled(rect, 4.0, ui.visuals().extreme_bg_color);
+        if bars.is_empty() {
             return;
         }
+        let slot = rect.width() / bars.len() as f32;
+        for (i, level) in bars.iter().enumerate() {
+            let x = rect.left() + slot * i as f32;
+            let height = (rect.height() * level).max(1.0);
+            let bar = egui::Rect::from_min_max(egui::pos2(x + 1.0, rect.bottom() - height), egui::pos2(x + slot - 1.0, rect.bottom()));
+            let color = Color32::from_rgb(80, 180, 255).gamma_multiply(0.5 + 0.5 * level);
+            painter.rect_filled(bar, 1.0, color);
+        }
+    }
 
-        // If no song is selected, select the last song
-        if self.selected_song_index.is_none() {
-            self.selected_song_index = Some(self.demo_songs.len() - 1);
-        } else {
-            // Move to previous song, wrapping around to the end
-            let current_index = self.selected_song_index.unwrap();
-            if current_index == 0 {
-                self.selected_song_index = Some(self.demo_songs.len() - 1);
-            } else {
-                self.selected_song_index = Some(current_index - 1);
+    fn render_speed(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("⏩ Speed:").font(FontId::proportional(16.0)));
+            let current = player.speed();
+            let mut chosen = None;
+            egui::ComboBox::from_id_source("playback_speed")
+                .selected_text(format!("{}×", current))
+                .show_ui(ui, |ui| {
+                    for speed in PLAYBACK_SPEEDS {
+                        if ui.selectable_label(current == speed, format!("{}×", speed)).clicked() {
+                            chosen = Some(speed);
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Faster or slower playback, for podcasts and lectures. The pitch changes with the speed");
+            if ui.add_enabled(current != 1.0, egui::Button::new("Normal")).clicked() {
+                chosen = Some(1.0);
+            }
+            if let Some(speed) = chosen {
+                player.set_speed(speed);
+                self.settings.playback_speed = speed;
+                if let Err(e) = self.settings.save() {
+                    self.toasts.error(format!("Could not save settings: {}", e));
+                }
+            }
+        });
+    }
+
+    fn render_balance(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("⚖ Balance:").font(FontId::proportional(16.0)));
+            let mut balance = player.balance();
+            let slider = ui.add(egui::Slider::new(&mut balance, -1.0..=1.0).custom_formatter(|value, _| {
+                match value {
+                    v if v < -0.005 => format!("L {:.0}%", -v * 100.0),
+                    v if v > 0.005 => format!("R {:.0}%", v * 100.0),
+                    _ => "Center".to_string(),
+                }
+            }));
+            let mut save = slider.drag_released() || (slider.changed() && !slider.dragged());
+            if slider.changed() {
+                player.set_balance(balance);
+            }
+            if ui.add_enabled(balance != 0.0, egui::Button::new("Center")).clicked() {
+                player.set_balance(0.0);
+                save = true;
+            }
+            let mut mono = player.is_mono();
+            if ui.checkbox(&mut mono, "Mono").on_hover_text("Mix both channels together, for a single speaker or one earphone").changed() {
+                player.set_mono(mono);
+                save = true;
+            }
+            if save {
+                self.settings.balance = player.balance();
+                self.settings.mono = player.is_mono();
+                if let Err(e) = self.settings.save() {
+                    self.toasts.error(format!("Could not save settings: {}", e));
+                }
+            }
+        });
+    }
+
+    fn render_tone(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("🎛 Tone:").font(FontId::proportional(16.0)));
+            let mut bass = player.bass();
+            let mut treble = player.treble();
+            let bass_slider = ui.add(egui::Slider::new(&mut bass, -TONE_MAX_GAIN_DB..=TONE_MAX_GAIN_DB).text("Bass").suffix(" dB").fixed_decimals(1));
+            let treble_slider = ui.add(egui::Slider::new(&mut treble, -TONE_MAX_GAIN_DB..=TONE_MAX_GAIN_DB).text("Treble").suffix(" dB").fixed_decimals(1));
+            if bass_slider.changed() {
+                player.set_bass(bass);
+            }
+            if treble_slider.changed() {
+                player.set_treble(treble);
+            }
+            let mut save = [&bass_slider, &treble_slider].iter().any(|s| s.drag_released() || (s.changed() && !s.dragged()));
+            if ui.add_enabled(bass != 0.0 || treble != 0.0, egui::Button::new("Flat")).clicked() {
+                player.set_bass(0.0);
+                player.set_treble(0.0);
+                save = true;
+            }
+            if save {
+                self.settings.bass_db = player.bass();
+                self.settings.treble_db = player.treble();
+                if let Err(e) = self.settings.save() {
+                    self.toasts.error(format!("Could not save settings: {}", e));
+                }
+            }
+        });
+    }
+
+    fn render_sleep_timer(&mut self, ui: &mut Ui, player: &mut Player) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("😴 Sleep:").font(FontId::proportional(16.0)));
+            match player.sleep_remaining() {
+                Some(remaining) => {
+                    let secs = remaining.as_secs();
+                    ui.label(RichText::new(format!("stopping in {:02}:{:02}", secs / 60, secs % 60)).font(FontId::proportional(16.0)).color(Color32::from_rgb(80, 180, 255)));
+                    if ui.button("Cancel").clicked() {
+                        player.set_sleep_timer(None);
+                    }
+                }
+                None => {
+                    for minutes in [15, 30, 60] {
+                        if ui.button(format!("{} min", minutes)).on_hover_text(format!("Stop playback in {} minutes", minutes)).clicked() {
+                            player.set_sleep_timer(Some(std::time::Duration::from_secs(minutes * 60)));
+                        }
+                    }
+                }
+            }
+            let mut stop_after = player.stop_after_current();
+            if ui.checkbox(&mut stop_after, "After this song").on_hover_text("Stop when the current song ends").changed() {
+                player.set_stop_after_current(stop_after);
+            }
+        });
+    }
+
+    /// Draws the progress bar as a seekable track. Dragging only previews the target
+    /// time; the actual seek is returned once the drag is released (or on a click) so
+    /// the file isn't re-decoded on every mouse move.
+    /// Right-clicking the bar places the A-B loop points at the pointer.
+    fn render_seek_bar(&mut self, ui: &mut Ui, frac: f32, total: std::time::Duration, player: &mut Player) -> Option<std::time::Duration> {
+        let size = egui::vec2(ui.available_width().min(300.0), 18.0);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        let pointer_frac = response
+            .interact_pointer_pos()
+            .map(|pos| ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0));
+
+        let mut seek_target = None;
+        if response.dragged() {
+            if let Some(f) = pointer_frac {
+                self.scrub_position = Some(total.mul_f32(f));
             }
         }
+        if response.drag_released() {
+            seek_target = self.scrub_position.take();
+        } else if response.clicked() {
+            seek_target = pointer_frac.map(|f| total.mul_f32(f));
+        } else if response.secondary_clicked() {
+            self.loop_menu_position = pointer_frac.map(|f| total.mul_f32(f));
+        }
+        if !response.dragged() {
+            // A drag that ended while the bar wasn't shown never reports its release
+            self.scrub_position = None;
+        }
 
-        // Auto-play the selected song if we were already playing
-        if self.is_playing && !self.is_paused {
-            self.play_selected_song(audio_manager);
+        let shown_frac = self.shown_fraction(frac, Some(total));
+        let painter = ui.painter();
+        let track = egui::Rect::from_center_size(rect.center(), egui::vec2(rect.width(), 6.0));
+        painter.rect_filled(track, 3.0, ui.visuals().widgets.inactive.bg_fill);
+        let mut played = track;
+        played.set_right(track.left() + track.width() * shown_frac);
+        painter.rect_filled(played, 3.0, Color32::from_rgb(40, 80, 160));
+        let marker_x = |point: std::time::Duration| track.left() + track.width() * (point.as_secs_f32() / total.as_secs_f32()).min(1.0);
+        let loop_color = Color32::from_rgb(230, 160, 60);
+        let (a, b) = player.loop_points();
+        if let (Some(a), Some(b)) = (a, b) {
+            let region = egui::Rect::from_x_y_ranges(marker_x(a)..=marker_x(b), track.y_range());
+            painter.rect_filled(region, 0.0, loop_color.gamma_multiply(0.4));
         }
+        for point in [a, b].into_iter().flatten() {
+            let x = marker_x(point);
+            painter.line_segment([egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())], egui::Stroke::new(2.0, loop_color));
+        }
+        let thumb_radius = if response.hovered() || response.dragged() { 7.0 } else { 5.0 };
+        painter.circle_filled(egui::pos2(played.right(), rect.center().y), thumb_radius, Color32::from_rgb(80, 180, 255));
+
+        // Preview the timestamp under the pointer before the user commits to a seek
+        if let Some(pos) = response.hover_pos() {
+            let hover_frac = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+            let target = self.scrub_position.unwrap_or_else(|| total.mul_f32(hover_frac));
+            egui::show_tooltip_at_pointer(ui.ctx(), response.id.with("seek_tooltip"), |ui| {
+                ui.label(format_duration(target.as_secs_f64()));
+            });
+        }
+
+        response.context_menu(|ui| {
+            let Some(position) = self.loop_menu_position else {
+                ui.close_menu();
+                return;
+            };
+            ui.label(RichText::new(format_duration(position.as_secs_f64())).color(Color32::GRAY));
+            if ui.button("Set A here").clicked() {
+                player.set_loop_a_at(position);
+                ui.close_menu();
+            }
+            if ui.add_enabled(a.is_some_and(|a| position > a), egui::Button::new("Set B here")).clicked() {
+                player.set_loop_b_at(position);
+                ui.close_menu();
+            }
+            if ui.add_enabled(a.is_some() || b.is_some(), egui::Button::new("Clear loop")).clicked() {
+                player.clear_loop();
+                ui.close_menu();
+            }
+        });
+
+        seek_target
     }
 
-    fn handle_next(&mut self, audio_manager: Arc<Mutex<AudioManager>>) {
-        if self.demo_songs.is_empty() {
-            return;
+    /// Fraction of the track to show as played: the scrub position while dragging the
+    /// seek bar, otherwise the playback position `frac`.
+    fn shown_fraction(&self, frac: f32, total: Option<std::time::Duration>) -> f32 {
+        match (self.scrub_position, total) {
+            (Some(position), Some(total)) if total.as_secs_f32() > 0.0 => (position.as_secs_f32() / total.as_secs_f32()).min(1.0),
+            _ => frac,
         }
+    }
 
-        // If no song is selected, select the first song
-        if self.selected_song_index.is_none() {
-            self.selected_song_index = Some(0);
-        } else {
-            // Move to next song, wrapping around to the beginning
-            let current_index = self.selected_song_index.unwrap();
-            if current_index == self.demo_songs.len() - 1 {
-                self.selected_song_index = Some(0);
+    fn render_waveform(ui: &mut Ui, peaks: &[f32], played_frac: f32) {
+        let size = egui::vec2(ui.available_width().min(300.0), 40.0);
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter();
+        let bar_width = rect.width() / peaks.len() as f32;
+        for (i, &peak) in peaks.iter().enumerate() {
+            let x = rect.left() + (i as f32 + 0.5) * bar_width;
+            let half_height = (peak * rect.height() / 2.0).max(0.5);
+            let color = if (i as f32 + 0.5) / peaks.len() as f32 <= played_frac {
+                Color32::from_rgb(80, 180, 255)
             } else {
-                self.selected_song_index = Some(current_index + 1);
-            }
+                ui.visuals().widgets.inactive.bg_fill
+            };
+            painter.line_segment(
+                [egui::pos2(x, rect.center().y - half_height), egui::pos2(x, rect.center().y + half_height)],
+                egui::Stroke::new(bar_width.max(1.0), color),
+            );
         }
+    }
 
-        // Auto-play the selected song if we were already playing
-        if self.is_playing && !self.is_paused {
-            self.play_selected_song(audio_manager);
+    fn handle_seek(&mut self, position: std::time::Duration, player: &mut Player) {
+        if let Err(e) = player.seek(position) {
+            self.toasts.error(format!("Could not seek: {}", e));
         }
     }
 
-    fn play_selected_song(&mut self, audio_manager: Arc<Mutex<AudioManager>>) {
-        if let Some(idx) = self.selected_song_index {
-            if let Ok(mut manager) = audio_manager.try_lock() {
-                let song = &self.demo_songs[idx];
-                if let Err(e) = manager.play_file(&song.file_path) {
-                    eprintln!("Failed to play file: {}", e);
-                } else {
-                    self.is_playing = true;
-                    self.is_paused = false;
-                    self.playback_start = Some(std::time::Instant::now());
-                    self.paused_at = None;
-                    self.current_position = std::time::Duration::from_secs(0);
-                    self.total_duration = manager.get_total_duration();
+    fn start_folder_scan(&mut self, folders: Vec<std::path::PathBuf>, ctx: &Context) {
+        // Only one scan at a time; new folders replace whatever was still running
+        if let Some(scan) = &mut self.folder_scan {
+            scan.cancel();
+        }
+        self.skipped_duplicates = 0;
+        self.play_scanned = false;
+        let ctx = ctx.clone();
+        self.folder_scan = Some(FolderScan::start(folders, self.settings.enabled_formats.clone(), self.settings.scan, move || ctx.request_repaint()));
+    }
+
+    /// Adds files dropped onto the window from a file manager.
+    fn handle_dropped_files(&mut self, ctx: &Context, player: &mut Player) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        if !dropped.is_empty() {
+            self.open_paths(dropped.into_iter().filter_map(|file| file.path).collect(), false, ctx, player);
+        }
+    }
+
+    /// Opens files and folders from a drop or the command line. Audio files are added
+    /// directly, folders are scanned in the background, and anything else is ignored.
+    /// With `play`, the first song added starts playing; for folders that's the first
+    /// one the scan finds.
+    pub fn open_paths(&mut self, paths: Vec<std::path::PathBuf>, play: bool, ctx: &Context, player: &mut Player) {
+        let mut songs = Vec::new();
+        let mut folders = Vec::new();
+        let mut ignored = 0;
+        for path in paths {
+            if path.is_dir() {
+                folders.push(path);
+            } else if has_extension(&path, &["m3u", "m3u8"]) {
+                // A dropped playlist opens as a playlist of its own, like "Load Playlist"
+                match player.load_playlist(&path.display().to_string()) {
+                    Ok(()) => self.selected_songs.clear(),
+                    Err(e) => self.toasts.error(format!("Could not load playlist: {}", e)),
                 }
+            } else if self.settings.accepts_file(&path) {
+                songs.push(Song::from_path(&path));
+            } else {
+                ignored += 1;
             }
         }
+        if ignored > 0 {
+            self.toasts.info(format!("{} files aren't in an enabled audio format and were left out", ignored));
+        }
+        // Files given directly play ahead of anything a folder scan finds
+        let play_scanned = play && songs.is_empty();
+        if !songs.is_empty() {
+            let first = songs[0].file_path.clone();
+            let skipped = player.add_songs(songs);
+            if skipped > 0 {
+                self.toasts.info(format!("{} duplicates skipped", skipped));
+            }
+            if play {
+                play_path(player, &first);
+            }
+        }
+        if !folders.is_empty() {
+            self.start_folder_scan(folders, ctx);
+            self.play_scanned = play_scanned;
+        }
     }
 
-    fn auto_advance_to_next_song(&mut self, audio_manager: Arc<Mutex<AudioManager>>) {
-        if self.demo_songs.is_empty() {
+    fn render_drop_overlay(ctx: &Context) {
+        if ctx.input(|i| i.raw.hovered_files.is_empty()) {
             return;
         }
+        let screen = ctx.screen_rect();
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("drop_overlay")));
+        painter.rect_filled(screen, 0.0, Color32::from_black_alpha(180));
+        painter.text(screen.center(), egui::Align2::CENTER_CENTER, "Drop files, folders or M3U playlists to add", FontId::proportional(28.0), Color32::from_rgb(80, 180, 255));
+    }
 
-        // If no song is selected, select the first song
-        if self.selected_song_index.is_none() {
-            self.selected_song_index = Some(0);
-            self.play_selected_song(audio_manager);
-            return;
+    fn poll_folder_scan(&mut self, player: &mut Player) {
+        if let Some(scan) = &mut self.folder_scan {
+            let songs = scan.poll();
+            if !songs.is_empty() {
+                let first = songs[0].file_path.clone();
+                self.skipped_duplicates += player.add_songs(songs);
+                if std::mem::take(&mut self.play_scanned) {
+                    play_path(player, &first);
+                }
+            }
+            if scan.is_finished() {
+                match scan.unreadable() {
+                    [] => {}
+                    [path] => self.toasts.error(format!("Skipped {}: it couldn't be decoded", path.display())),
+                    paths => {
+                        let names: Vec<String> = paths
+                            .iter()
+                            .take(3)
+                            .map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string())
+                            .collect();
+                        let more = if paths.len() > names.len() { ", …" } else { "" };
+                        self.toasts.error(format!("Skipped {} files that couldn't be decoded: {}{}", paths.len(), names.join(", "), more));
+                    }
+                }
+                self.folder_scan = None;
+                if self.skipped_duplicates > 0 {
+                    self.toasts.info(format!("{} duplicates skipped", self.skipped_duplicates));
+                }
+            }
         }
+    }
 
-        let current_index = self.selected_song_index.unwrap();
-        
-        // Check if there's a next song
-        if current_index < self.demo_songs.len() - 1 {
-            // Move to next song
-            self.selected_song_index = Some(current_index + 1);
-            self.play_selected_song(audio_manager);
-        } else {
-            // No more songs, stop playback
-            if let Ok(mut manager) = audio_manager.try_lock() {
-                manager.stop();
-                self.is_playing = false;
-                self.is_paused = false;
-                self.current_position = std::time::Duration::from_secs(0);
-                self.total_duration = None;
-                self.playback_start = None;
-                self.paused_at = None;
-            }
-        }
-    }
-
-    fn add_folder_songs(&mut self, folder_path: &std::path::Path) {
-        let mut added_songs = Vec::new();
-        let supported_extensions = ["mp3", "wav", "flac", "ogg", "m4a"];
-        let walkdir = WalkDir::new(folder_path)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path().is_file() && supported_extensions.contains(&e.path().extension().unwrap_or_default().to_string_lossy().to_string().as_str()));
-
-        for entry in walkdir {
-            let path = entry.path();
-            let title = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "Unknown".to_string());
-            let artist = "Unknown".to_string(); // No artist info available from folder
-            let file_path = path.display().to_string();
-            let song = Song {
-                title,
-                artist,
-                file_path,
-                duration: None,
-            };
-            added_songs.push(song);
+    /// Moves a dragged song to the gap before `insert_at` (counted before the move) and
+    /// keeps it selected.
+    fn move_song(&mut self, from: usize, insert_at: usize, player: &mut Player) {
+        let to = if insert_at > from { insert_at - 1 } else { insert_at };
+        if to == from {
+            return;
         }
-        self.demo_songs.extend(added_songs);
+        self.push_undo(player);
+        player.move_song(from, to);
+        self.selected_songs.clear();
+        self.selected_songs.push(to);
     }
 
-    fn remove_selected_songs(&mut self) {
+    fn remove_selected_songs(&mut self, player: &mut Player) {
         if self.selected_songs.is_empty() {
             return;
         }
-        let mut new_songs = Vec::new();
-        for (i, song) in self.demo_songs.iter().enumerate() {
-            if !self.selected_songs.contains(&i) {
-                new_songs.push(song.clone());
-            }
+        self.push_undo(player);
+        player.remove_songs(&self.selected_songs);
+        self.selected_songs.clear();
+    }
+
+    fn remove_duplicate_songs(&mut self, player: &mut Player) {
+        let before = self.snapshot(player);
+        let removed = player.remove_duplicates();
+        if removed == 0 {
+            self.toasts.info("No duplicates found");
+            return;
         }
-        self.demo_songs = new_songs;
+        self.record_undo(before);
         self.selected_songs.clear();
-        self.selected_song_index = None;
+        self.toasts.info(format!("Removed {} duplicates", removed));
     }
 
-    fn clear_all_songs(&mut self) {
-        self.demo_songs.clear();
+    fn clear_all_songs(&mut self, player: &mut Player) {
+        if let Some(mut scan) = self.folder_scan.take() {
+            scan.cancel();
+        }
+        if !player.songs().is_empty() {
+            self.push_undo(player);
+        }
+        player.clear();
         self.selected_songs.clear();
-        self.selected_song_index = None;
     }
-} 
\ No newline at end of file
+
+    fn snapshot(&self, player: &Player) -> PlaylistSnapshot {
+        PlaylistSnapshot {
+            playlist: player.current_playlist_name().map(str::to_string),
+            songs: player.songs().to_vec(),
+            selected: self.selected_songs.clone(),
+        }
+    }
+
+    /// Records the playlist as it is before an edit. Any redo history is dropped, as
+    /// it no longer follows on from here.
+    fn push_undo(&mut self, player: &Player) {
+        let snapshot = self.snapshot(player);
+        self.record_undo(snapshot);
+    }
+
+    /// Makes `snapshot`, taken before a change, the next state "Undo" goes back to.
+    fn record_undo(&mut self, snapshot: PlaylistSnapshot) {
+        self.undo_stack.push(snapshot);
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    fn undo(&mut self, player: &mut Player) {
+        if let Some(snapshot) = self.undo_stack.pop() {
+            let current = self.snapshot(player);
+            if self.restore_snapshot(snapshot, player) {
+                self.redo_stack.push(current);
+            }
+        }
+    }
+
+    fn redo(&mut self, player: &mut Player) {
+        if let Some(snapshot) = self.redo_stack.pop() {
+            let current = self.snapshot(player);
+            if self.restore_snapshot(snapshot, player) {
+                self.undo_stack.push(current);
+            }
+        }
+    }
+
+    /// Puts back a snapshot's songs and selection. History from another playlist can't
+    /// be applied to this one, so switching playlists clears it instead.
+    fn restore_snapshot(&mut self, snapshot: PlaylistSnapshot, player: &mut Player) -> bool {
+        if snapshot.playlist.as_deref() != player.current_playlist_name() {
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+            return false;
+        }
+        player.replace_songs(snapshot.songs);
+        self.selected_songs = snapshot.selected.into_iter().filter(|&i| i < player.songs().len()).collect();
+        true
+    }
+}
+
+/// Applies the player's style: the dark or light base colours with the shared blue
+/// accent, spacing and rounding.
+fn apply_theme(ctx: &Context, dark: bool) {
+    let mut style = (*ctx.style()).clone();
+    if dark {
+        style.visuals = Visuals::dark();
+        style.visuals.widgets.inactive.bg_fill = Color32::from_rgb(30, 30, 40);
+        style.visuals.widgets.noninteractive.bg_fill = Color32::from_rgb(24, 24, 28);
+        style.visuals.panel_fill = Color32::from_rgb(24, 24, 28);
+    } else {
+        style.visuals = Visuals::light();
+        style.visuals.widgets.inactive.bg_fill = Color32::from_rgb(220, 222, 230);
+        style.visuals.widgets.noninteractive.bg_fill = Color32::from_rgb(245, 245, 248);
+        style.visuals.panel_fill = Color32::from_rgb(245, 245, 248);
+    }
+    style.visuals.widgets.active.bg_fill = Color32::from_rgb(40, 80, 160); // accent blue
+    style.visuals.widgets.hovered.bg_fill = Color32::from_rgb(60, 100, 200);
+    style.visuals.selection.bg_fill = Color32::from_rgb(40, 80, 160);
+    style.visuals.selection.stroke = egui::Stroke::new(2.0, Color32::from_rgb(80, 180, 255));
+    style.spacing.item_spacing = egui::vec2(12.0, 8.0);
+    style.spacing.button_padding = egui::vec2(16.0, 8.0);
+    style.visuals.window_rounding = 8.0.into();
+    style.visuals.window_shadow = egui::epaint::Shadow::big_dark();
+    ctx.set_style(style);
+}
+
+/// Plays the song at `path`, wherever it ended up in the playlist. It may already have
+/// been listed under another path to the same file.
+fn play_path(player: &mut Player, path: &str) {
+    let id = canonical_path(path);
+    if let Some(index) = player.songs().iter().position(|song| canonical_path(&song.file_path) == id) {
+        player.play_index(index);
+    }
+}
\ No newline at end of file
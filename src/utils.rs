@@ -1,4 +1,8 @@
-use std::path::Path;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::warn;
 
 pub fn get_file_name_from_path(path: &str) -> String {
     Path::new(path)
@@ -8,17 +12,141 @@ pub fn get_file_name_from_path(path: &str) -> String {
         .to_string()
 }
 
-pub fn is_audio_file(path: &str) -> bool {
-    if let Some(extension) = Path::new(path).extension() {
-        let ext = extension.to_string_lossy().to_lowercase();
-        matches!(ext.as_str(), "mp3" | "wav" | "flac" | "ogg" | "m4a" | "aac")
+/// Resolves `path` to an absolute, symlink-free form so the same file reached through
+/// different relative paths compares equal. Paths that can't be resolved (e.g. the file
+/// is gone) are returned unchanged.
+pub fn canonical_path(path: &str) -> String {
+    std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Formats the player knows how to decode, in the order shown in the settings window.
+/// Users can enable more extensions there; files that don't decode are skipped.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["mp3", "wav", "flac", "ogg", "m4a", "aac"];
+
+/// Whether `path` ends in one of `extensions`, ignoring case.
+pub fn has_extension<S: AsRef<str>>(path: &Path, extensions: &[S]) -> bool {
+    let Some(extension) = path.extension() else { return false };
+    let extension = extension.to_string_lossy();
+    extensions.iter().any(|e| e.as_ref().eq_ignore_ascii_case(&extension))
+}
+
+/// Turns Windows separators into `/` so a path written on Windows reads on other
+/// systems. Windows itself accepts either, so paths are left alone there.
+pub fn normalize_separators(path: &str) -> String {
+    if cfg!(windows) {
+        path.to_string()
     } else {
-        false
+        path.replace('\\', "/")
     }
 }
 
+/// `path` relative to `root`, with `/` separators on every system, or `None` if it
+/// isn't inside `root`.
+pub fn relative_to_root(path: &str, root: &Path) -> Option<String> {
+    let relative = Path::new(path).strip_prefix(root).ok()?;
+    let parts: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+    Some(parts.join("/"))
+}
+
+/// Formats a byte count with a binary unit, e.g. "4.2 GB".
+pub fn format_file_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Formats as "mm:ss", or "h:mm:ss" from an hour up (e.g. a whole playlist).
 pub fn format_duration(seconds: f64) -> String {
     let minutes = (seconds / 60.0) as u32;
     let seconds = (seconds % 60.0) as u32;
-    format!("{:02}:{:02}", minutes, seconds)
+    if minutes >= 60 {
+        format!("{}:{:02}:{:02}", minutes / 60, minutes % 60, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+/// Describes how long ago `time` was, e.g. "just now", "5 min ago" or "3 days ago".
+pub fn format_time_ago(time: chrono::DateTime<chrono::Utc>) -> String {
+    let elapsed = chrono::Utc::now().signed_duration_since(time);
+    if elapsed.num_minutes() < 1 {
+        "just now".to_string()
+    } else if elapsed.num_hours() < 1 {
+        format!("{} min ago", elapsed.num_minutes())
+    } else if elapsed.num_days() < 1 {
+        format!("{} h ago", elapsed.num_hours())
+    } else if elapsed.num_days() == 1 {
+        "yesterday".to_string()
+    } else {
+        format!("{} days ago", elapsed.num_days())
+    }
+}
+
+/// Opens the platform's file manager at `path`, selecting the file where the file
+/// manager supports it.
+pub fn reveal_in_file_manager(path: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("explorer");
+        command.arg(format!("/select,{}", path));
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = std::process::Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    } else {
+        // xdg-open can't select a file, so open the folder containing it
+        let folder = Path::new(path).parent().unwrap_or(Path::new("."));
+        let mut command = std::process::Command::new("xdg-open");
+        command.arg(folder);
+        command
+    };
+    command.spawn().map(|_| ())
+}
+
+/// Per-user directory for the player's config and state files, following each
+/// platform's convention. Returns `None` when the relevant environment variable is unset.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join("rust_music_player"))
+}
+
+/// Reads a JSON file from the config directory. Missing files yield `None`; corrupt
+/// ones are logged and also yield `None` so callers can start from defaults.
+pub fn load_config_json<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let path = config_dir()?.join(file_name);
+    let content = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Ignoring corrupt file {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn save_config_json<T: Serialize>(file_name: &str, value: &T) -> Result<PathBuf> {
+    let dir = config_dir().ok_or_else(|| anyhow::anyhow!("No config directory available"))?;
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(file_name);
+    std::fs::write(&path, serde_json::to_string_pretty(value)?)?;
+    Ok(path)
 } 
\ No newline at end of file
@@ -0,0 +1,101 @@
+use crate::decode::for_each_frame;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use tracing::{info, warn};
+
+/// Number of peaks computed per track; enough for a controls-panel-wide thumbnail.
+pub const WAVEFORM_BUCKETS: usize = 300;
+
+/// Decodes the whole file and reduces it to `buckets` peak amplitudes in `0.0..=1.0`,
+/// one per equal slice of the track. Returns `None` if the file can't be decoded.
+pub fn compute_peaks(file_path: &str, buckets: usize) -> Option<Vec<f32>> {
+    // Peaks per fixed-size block of frames, since the total length isn't always known
+    // up front; these are folded into the requested number of buckets at the end.
+    const BLOCK_FRAMES: usize = 1024;
+    let mut blocks = Vec::new();
+    let mut block_peak = 0.0f32;
+    let mut block_len = 0;
+    for_each_frame(file_path, |frame| {
+        let peak = frame.iter().fold(0.0f32, |acc, s| acc.max(s.abs()));
+        block_peak = block_peak.max(peak);
+        block_len += 1;
+        if block_len == BLOCK_FRAMES {
+            blocks.push(block_peak);
+            block_peak = 0.0;
+            block_len = 0;
+        }
+    })?;
+    if block_len > 0 {
+        blocks.push(block_peak);
+    }
+    if blocks.is_empty() || buckets == 0 {
+        return None;
+    }
+
+    let peaks = (0..buckets)
+        .map(|i| {
+            let start = (i * blocks.len() / buckets).min(blocks.len() - 1);
+            let end = ((i + 1) * blocks.len() / buckets).clamp(start + 1, blocks.len());
+            blocks[start..end].iter().copied().fold(0.0, f32::max).min(1.0)
+        })
+        .collect();
+    Some(peaks)
+}
+
+/// Waveforms computed so far, keyed by file path. Missing ones are computed on a
+/// background thread the first time they're asked for.
+pub struct WaveformCache {
+    waveforms: HashMap<String, Option<Vec<f32>>>,
+    pending: HashSet<String>,
+    sender: Sender<(String, Option<Vec<f32>>)>,
+    receiver: Receiver<(String, Option<Vec<f32>>)>,
+}
+
+impl Default for WaveformCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaveformCache {
+    pub fn new() -> Self {
+        let (sender, receiver) = unbounded();
+        Self {
+            waveforms: HashMap::new(),
+            pending: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Returns the waveform for `file_path` if it's ready. Otherwise starts computing it
+    /// (once) and calls `notify` from the worker thread when it's done.
+    pub fn get<F>(&mut self, file_path: &str, notify: F) -> Option<&[f32]>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.receive();
+        if !self.waveforms.contains_key(file_path) && self.pending.insert(file_path.to_string()) {
+            let sender = self.sender.clone();
+            let path = file_path.to_string();
+            thread::spawn(move || {
+                let peaks = compute_peaks(&path, WAVEFORM_BUCKETS);
+                if peaks.is_none() {
+                    warn!("Could not compute waveform for {}", path);
+                }
+                let _ = sender.send((path, peaks));
+                notify();
+            });
+        }
+        self.waveforms.get(file_path).and_then(|w| w.as_deref())
+    }
+
+    fn receive(&mut self) {
+        while let Ok((path, peaks)) = self.receiver.try_recv() {
+            info!("Waveform ready for {}", path);
+            self.pending.remove(&path);
+            self.waveforms.insert(path, peaks);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+use rust_music_player::audio::AudioManager;
+use rust_music_player::playlist::Song;
+use rust_music_player::{Player, PlayerEvent};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest a fixture is given to play through before the test gives up.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Writes `secs` of a quiet 440 Hz sine as 16-bit mono WAV.
+fn write_wav(path: &Path, secs: f32) {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 44_100,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    let frames = (secs * spec.sample_rate as f32) as u32;
+    for i in 0..frames {
+        let t = i as f32 / spec.sample_rate as f32;
+        writer.write_sample(((t * 440.0 * std::f32::consts::TAU).sin() * 1000.0) as i16).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn fixture_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust_music_player-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Calls `update` like the UI does every frame until `done` holds, returning every
+/// event seen on the way.
+fn drive(player: &mut Player, mut done: impl FnMut(&Player, &[PlayerEvent]) -> bool) -> Vec<PlayerEvent> {
+    let start = Instant::now();
+    let mut events = player.take_events();
+    while !done(player, &events) {
+        assert!(start.elapsed() < TIMEOUT, "timed out; events so far: {:?}", events);
+        thread::sleep(Duration::from_millis(20));
+        player.update();
+        events.extend(player.take_events());
+    }
+    events
+}
+
+#[test]
+fn plays_a_playlist_through_to_the_end() {
+    let dir = fixture_dir("playback");
+    let paths = [dir.join("first.wav"), dir.join("second.wav")];
+    for path in &paths {
+        write_wav(path, 0.3);
+    }
+
+    let mut player = Player::new(AudioManager::new());
+    assert_eq!(player.add_songs(paths.iter().map(|p| Song::from_path(p)).collect()), 0);
+    assert_eq!(player.songs()[0].title, "first");
+    assert!(player.songs()[0].duration.is_some_and(|d| (d - 0.3).abs() < 0.01));
+
+    player.play();
+    if !player.has_audio_output() {
+        // Without a device nothing plays, but the failure is reported and the playlist
+        // can still be stepped through
+        let events = player.take_events();
+        assert!(events.iter().any(|e| matches!(e, PlayerEvent::Error(_))), "{:?}", events);
+        assert!(!player.is_playing());
+        player.next();
+        assert_eq!(player.current_index(), Some(1));
+        assert!(!player.is_playing());
+    } else {
+        let events = drive(&mut player, |_, events| events.contains(&PlayerEvent::TrackStarted(1)));
+        assert_eq!(events.first(), Some(&PlayerEvent::TrackStarted(0)));
+        assert_eq!(player.current_index(), Some(1));
+
+        // Repeat is off, so the last song playing out stops playback
+        drive(&mut player, |player, _| !player.is_playing());
+        assert_eq!(player.current_index(), Some(1));
+        assert!(player.songs().iter().all(|song| song.play_count == 1));
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
\ No newline at end of file
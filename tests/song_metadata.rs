@@ -0,0 +1,28 @@
+use rust_music_player::playlist::Song;
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+#[test]
+fn reads_the_tags_of_a_tagged_mp3() {
+    let song = Song::from_path(&fixture("tagged.mp3"));
+    assert_eq!(song.title, "Tagged Title");
+    assert_eq!(song.artist, "Tagged Artist");
+    assert_eq!(song.album.as_deref(), Some("Tagged Album"));
+    assert_eq!(song.track_number, Some(3));
+    assert_eq!(song.year, Some(2004));
+}
+
+#[test]
+fn falls_back_to_the_file_name_for_an_untagged_wav() {
+    let song = Song::from_path(&fixture("untagged.wav"));
+    assert_eq!(song.title, "untagged");
+    assert_eq!(song.artist, "Unknown");
+    assert_eq!(song.album, None);
+    assert_eq!(song.track_number, None);
+    assert_eq!(song.year, None);
+    // 2000 frames at 8 kHz
+    assert_eq!(song.duration, Some(0.25));
+}
\ No newline at end of file